@@ -0,0 +1,159 @@
+use bevy::{
+    prelude::*,
+    render::texture::DEFAULT_IMAGE_HANDLE
+};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use crate::consts::*;
+use crate::theme::{ActiveTheme, Theme, Themed};
+use crate::types::*;
+
+/// Distance between markers along a paddle or goal
+const MARKER_SPACING: f32 = 0.35;
+
+/// Player colors that stay apart with a color vision deficiency
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum Palette {
+    /// The colors of the theme
+    Theme,
+    /// Orange and blue, for red-green color blindness
+    Deuteranopia,
+    /// Yellow and blue, for red-green color blindness with weak reds
+    Protanopia,
+    /// Red and teal, for blue-yellow color blindness
+    Tritanopia,
+}
+
+impl Palette {
+    /// Colors of the left and right side, `None` to keep the theme's
+    fn sides(self) -> Option<(Color, Color)> {
+        match self {
+            Palette::Theme => None,
+            Palette::Deuteranopia => Some((Color::rgb(0.90, 0.62, 0.0), Color::rgb(0.0, 0.45, 0.70))),
+            Palette::Protanopia => Some((Color::rgb(0.94, 0.89, 0.26), Color::rgb(0.0, 0.45, 0.70))),
+            Palette::Tritanopia => Some((Color::rgb(0.80, 0.20, 0.10), Color::rgb(0.0, 0.60, 0.55))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+    pub palette: Palette,
+    /// Black field, white walls and text and bright player colors
+    pub high_contrast: bool,
+    /// Dots on the left player's paddle and goal and stripes on the right player's
+    pub markers: bool,
+    /// Multiplies the speed of the ball and the paddles
+    pub game_speed: f32,
+    /// Blink the winner text
+    pub flashing: bool,
+}
+
+impl Default for AccessibilitySettings {
+    fn default() -> Self {
+        AccessibilitySettings {
+            palette: Palette::Theme,
+            high_contrast: false,
+            markers: false,
+            game_speed: 1.0,
+            flashing: true,
+        }
+    }
+}
+
+/// `color` made as bright as it can be without changing its hue
+fn brightest(color: Color) -> Color {
+    let [r, g, b, a] = color.as_rgba_f32();
+    let max = r.max(g).max(b);
+    if max > 0.0 { Color::rgba(r / max, g / max, b / max, a) } else { Color::WHITE }
+}
+
+/// `theme` with the palette and high contrast mode applied
+pub fn adjust_theme(theme: &Theme, settings: &AccessibilitySettings) -> Theme {
+    let mut theme = theme.clone();
+    if let Some((left, right)) = settings.palette.sides() {
+        // Each goal is on the side of the player it is scored against
+        theme.left_paddle = left;
+        theme.right_paddle = right;
+        theme.left_goal = right * 0.7;
+        theme.right_goal = left * 0.7;
+        theme.left_light.color = left;
+        theme.right_light.color = right;
+    }
+    if settings.high_contrast {
+        theme.background = Color::BLACK;
+        theme.floor = Color::BLACK;
+        theme.wall = Color::WHITE;
+        theme.ball = Color::WHITE;
+        theme.ball_light.color = Color::WHITE;
+        theme.ambient.intensity = theme.ambient.intensity.max(0.3);
+        theme.text = Color::WHITE;
+        theme.text_secondary = Color::rgb(0.85, 0.85, 0.85);
+        for color in [&mut theme.left_paddle, &mut theme.right_paddle, &mut theme.left_goal, &mut theme.right_goal] {
+            *color = brightest(*color);
+        }
+    }
+    theme
+}
+
+pub struct AccessibilityPlugin;
+
+impl Plugin for AccessibilityPlugin {
+    fn build(&self, app: &mut App) {
+        // Runs after the game entities from `setup` exist
+        app.add_startup_system_to_stage(StartupStage::PostStartup, setup_markers);
+    }
+}
+
+/// Adds markers that tell the players apart by shape: dots for the left player and stripes
+/// for the right, on each paddle and on the goal it defends
+fn setup_markers(mut commands: Commands,
+                 settings: Res<AccessibilitySettings>,
+                 mut meshes: ResMut<Assets<Mesh>>,
+                 mut materials: ResMut<Assets<StandardMaterial>>,
+                 theme: ActiveTheme,
+                 colliding_query: Query<(Entity, &Colliding)>) {
+    if !settings.markers {
+        return;
+    }
+    let theme = theme.get();
+    for (entity, colliding) in colliding_query.iter() {
+        let (side, themed) = match colliding.kind {
+            Collider::Paddle(player) => (player, Themed::PaddleMarker(player)),
+            Collider::Goal(Player::Left) => (Player::Right, Themed::GoalMarker(Player::Left)),
+            Collider::Goal(Player::Right) => (Player::Left, Themed::GoalMarker(Player::Right)),
+            Collider::Wall | Collider::Ball => continue,
+        };
+        let width = colliding.size.x;
+        let size = match side {
+            Player::Left => Vec2::splat(width * 0.4),
+            Player::Right => Vec2::new(width * 0.8, width * 0.2),
+        };
+        let mesh = meshes.add(match side {
+            Player::Left => Mesh::from(shape::Icosphere { radius: size.x / 2.0, subdivisions: 4 }),
+            Player::Right => Mesh::from(shape::Box::new(size.x, size.y, size.y)),
+        });
+        let material = materials.add(StandardMaterial {
+            base_color: theme.color(themed).unwrap_or_default(),
+            unlit: true,
+            ..Default::default()
+        });
+        let count = (colliding.size.y / MARKER_SPACING).floor().max(1.0) as usize;
+        commands.entity(entity).with_children(|parent| {
+            for index in 0..count {
+                let y = (index as f32 - (count - 1) as f32 / 2.0) * MARKER_SPACING;
+                parent.spawn_bundle(PbrBundle {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    // On top of the box in 3D, in front of the sprite in 2D
+                    transform: Transform::from_xyz(0.0, y, WALL_THICKNESS / 2.0),
+                    ..Default::default()
+                })
+                .insert_bundle((Sprite { color: theme.color(themed).unwrap_or_default(), custom_size: Some(size), ..Default::default() },
+                                DEFAULT_IMAGE_HANDLE.typed::<Image>()))
+                .insert(themed);
+            }
+        });
+    }
+}
@@ -0,0 +1,103 @@
+use std::{collections::hash_map::RandomState, hash::{BuildHasher, Hasher}, path::PathBuf, process, thread, time::Instant};
+use bevy::{prelude::*, log::LogPlugin};
+use clap::Parser;
+use bevy_pong::{
+    config::Settings,
+    lan::{Announcement, Announcer, DISCOVERY_PORT},
+    net::{tick_duration, DEFAULT_MAX_SPECTATORS, MAX_CATCH_UP},
+    server::{Server, ServerConfig}
+};
+
+/// Dedicated server for online Pong matches. Players join with `bevy-pong --connect ADDRESS`
+/// and are paired in the order they arrive, `bevy-pong --spectate ADDRESS` watches the newest match.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    /// UDP port to serve matches on
+    #[arg(long, default_value_t = 7777)]
+    port: u16,
+
+    /// Settings file with the match rules and game speed, instead of the one in the user config directory
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Seed for the random number generator of the first match, the next ones count up from it
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Spectators each match takes at most
+    #[arg(long, value_name = "COUNT", default_value_t = DEFAULT_MAX_SPECTATORS)]
+    max_spectators: usize,
+
+    /// Name shown to players looking for games on the local network
+    #[arg(long, default_value = "Pong server")]
+    name: String,
+
+    /// Do not announce the server on the local network
+    #[arg(long)]
+    no_announce: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+    let settings = match Settings::load(args.config.as_deref()) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    };
+    // The app sets up logging for the whole process, the matches have no logger of their own.
+    // The server runs in it in place of a schedule.
+    App::new()
+        .add_plugin(LogPlugin)
+        .set_runner(move |_| serve(&args, &settings))
+        .run();
+}
+
+fn serve(args: &Args, settings: &Settings) {
+    let config = ServerConfig {
+        rules: settings.rules.clone(),
+        game_speed: settings.accessibility.game_speed,
+        seed: args.seed.unwrap_or_else(|| RandomState::new().build_hasher().finish()),
+        max_spectators: args.max_spectators,
+    };
+    let mut server = match Server::bind(args.port, config) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("error: could not serve on port {}: {}", args.port, e);
+            process::exit(2);
+        }
+    };
+    info!("Serving matches on port {}", args.port);
+    let mut announcer = if args.no_announce {
+        None
+    } else {
+        let port = server.local_addr().map_or(args.port, |address| address.port());
+        let announcement = Announcement::new(args.name.clone(), port, settings.rules.clone(), None);
+        Announcer::new(announcement, DISCOVERY_PORT)
+            .map_err(|e| warn!("Cannot announce the server on the local network: {}", e))
+            .ok()
+    };
+
+    let started = Instant::now();
+    let mut next_tick = started;
+    loop {
+        server.poll(started.elapsed());
+        if let Some(announcer) = &mut announcer {
+            announcer.set_players(server.players() as u32);
+            announcer.poll(started.elapsed());
+        }
+        let now = Instant::now();
+        if now < next_tick {
+            thread::sleep(next_tick - now);
+            continue;
+        }
+        server.tick();
+        next_tick += tick_duration();
+        // Too far behind to catch up, such as after the machine was suspended
+        if now > next_tick + tick_duration() * MAX_CATCH_UP {
+            next_tick = now;
+        }
+    }
+}
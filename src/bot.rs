@@ -0,0 +1,593 @@
+//! Paddles controlled by programs outside the game, over the protocol described in
+//! `docs/bot-protocol.md`: newline-delimited JSON over TCP, a hello answered with a welcome,
+//! the game's state after every simulation tick and paddle commands back.
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant}
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::net::{encode_direction, NetInputs};
+use crate::types::*;
+
+/// Bump whenever a message changes in a way older bots cannot read
+pub const BOT_PROTOCOL_VERSION: u32 = 1;
+pub const DEFAULT_BOT_PORT: u16 = 7779;
+/// The game goes on without a lockstep bot's command after waiting this long
+const LOCKSTEP_TIMEOUT: Duration = Duration::from_secs(1);
+/// A longer line is not a message of this protocol
+const MAX_LINE: usize = 4096;
+/// Unsent data a bot may fall behind on before it is dropped for not reading
+const MAX_BACKLOG: usize = 1 << 20;
+
+/// Sent by a bot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotRequest {
+    /// The first message. `player` is the paddle to control, none to only watch. A lockstep bot
+    /// holds the game until it has answered each state with a command.
+    Hello {
+        version: u32,
+        player: Option<Player>,
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        lockstep: bool,
+    },
+    /// Moves the paddle from the next tick on, -1 down to 1 up. `tick` is that of the state
+    /// the command answers.
+    Command { tick: u32, direction: f32 },
+}
+
+/// Sent by the game
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotMessage {
+    /// The answer to a valid hello, `tick` is that of the next state
+    Welcome { version: u32, player: Option<Player>, tick: u32 },
+    /// Sent before the game closes the connection
+    Error { message: String },
+    State(BotState),
+}
+
+/// The game after a simulation tick
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BotState {
+    /// Counts the simulation's ticks from 0, one state each
+    pub tick: u32,
+    pub state: AppState,
+    pub balls: Vec<BotBody>,
+    pub left: BotBody,
+    pub right: BotBody,
+    pub score: Score,
+    /// Tick of the newest command from this bot that the game has used
+    pub command_tick: Option<u32>,
+}
+
+/// Position of the middle and velocity per second, x grows to the right and y up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct BotBody {
+    pub position: [f32; 2],
+    pub velocity: [f32; 2],
+}
+
+impl BotBody {
+    fn new(transform: &Transform, moving: &Moving) -> BotBody {
+        BotBody { position: transform.translation.truncate().to_array(), velocity: moving.velocity.truncate().to_array() }
+    }
+}
+
+struct Hello {
+    player: Option<Player>,
+    name: String,
+    lockstep: bool,
+}
+
+struct BotClient {
+    stream: TcpStream,
+    address: SocketAddr,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    hello: Option<Hello>,
+    /// Tick and direction of the newest command
+    command: Option<(u32, f32)>,
+    closed: bool,
+}
+
+impl BotClient {
+    fn send(&mut self, message: &BotMessage) {
+        match serde_json::to_vec(message) {
+            Ok(line) => {
+                self.outgoing.extend(line);
+                self.outgoing.push(b'\n');
+            },
+            Err(e) => warn!("Could not encode a message to bot {}: {}", self.address, e),
+        }
+    }
+
+    /// Tells the bot what went wrong and closes the connection once that is sent
+    fn refuse(&mut self, message: String) {
+        warn!("Closing the connection to bot {}: {}", self.address, message);
+        self.send(&BotMessage::Error { message });
+        self.closed = true;
+    }
+
+    fn flush(&mut self) {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => break,
+                Ok(size) => {
+                    self.outgoing.drain(..size);
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    debug!("Could not send to bot {}: {}", self.address, e);
+                    self.outgoing.clear();
+                    self.closed = true;
+                },
+            }
+        }
+        if self.outgoing.len() > MAX_BACKLOG {
+            warn!("Dropping bot {}, it does not read what it is sent", self.address);
+            self.outgoing.clear();
+            self.closed = true;
+        }
+    }
+
+    /// Complete lines that have arrived
+    fn receive(&mut self) -> Vec<Vec<u8>> {
+        let mut buffer = [0; MAX_LINE];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                },
+                Ok(size) => self.incoming.extend(&buffer[..size]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    debug!("Could not receive from bot {}: {}", self.address, e);
+                    self.closed = true;
+                    break;
+                },
+            }
+        }
+        let mut lines = Vec::new();
+        while let Some(end) = self.incoming.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.incoming.drain(..=end).collect();
+            lines.push(line);
+        }
+        if self.incoming.len() > MAX_LINE {
+            self.refuse(format!("a message is longer than {} bytes", MAX_LINE));
+        }
+        lines
+    }
+}
+
+/// Listens for bots and keeps their connections
+pub struct BotServer {
+    listener: TcpListener,
+    clients: Vec<BotClient>,
+    /// Tick of the next state
+    tick: u32,
+}
+
+impl BotServer {
+    pub fn bind(port: u16) -> io::Result<BotServer> {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port))?;
+        listener.set_nonblocking(true)?;
+        Ok(BotServer { listener, clients: Vec::new(), tick: 0 })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn has_clients(&self) -> bool {
+        !self.clients.is_empty()
+    }
+
+    /// Accepts new bots and handles what they have sent. Only the paddles of players with
+    /// `Controller::Bot` in `controllers` can be controlled.
+    pub fn poll(&mut self, controllers: &Controllers) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    if let Err(e) = stream.set_nonblocking(true).and_then(|_| stream.set_nodelay(true)) {
+                        warn!("Could not set up the connection to bot {}: {}", address, e);
+                        continue;
+                    }
+                    debug!("Bot connected from {}", address);
+                    self.clients.push(BotClient {
+                        stream,
+                        address,
+                        incoming: Vec::new(),
+                        outgoing: Vec::new(),
+                        hello: None,
+                        command: None,
+                        closed: false,
+                    });
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Could not accept a bot: {}", e);
+                    break;
+                },
+            }
+        }
+        for index in 0..self.clients.len() {
+            let lines = self.clients[index].receive();
+            for line in lines {
+                if self.clients[index].closed {
+                    break;
+                }
+                match serde_json::from_slice::<BotRequest>(&line) {
+                    Ok(request) => self.handle(index, request, controllers),
+                    Err(e) => self.clients[index].refuse(format!("cannot read the message: {}", e)),
+                }
+            }
+        }
+        for client in &mut self.clients {
+            client.flush();
+        }
+        self.clients.retain(|client| {
+            if client.closed {
+                let name = client.hello.as_ref().map_or("", |hello| hello.name.as_str());
+                info!("Bot {} at {} disconnected", name, client.address);
+                let _ = client.stream.shutdown(Shutdown::Both);
+            }
+            !client.closed
+        });
+    }
+
+    fn handle(&mut self, index: usize, request: BotRequest, controllers: &Controllers) {
+        let tick = self.tick;
+        let taken = |player| self.clients.iter().any(|client| client.hello.as_ref().is_some_and(|hello| hello.player == Some(player)));
+        let refusal = match &request {
+            _ if self.clients[index].hello.is_some() && matches!(request, BotRequest::Hello { .. }) => {
+                Some("the hello was already sent".to_string())
+            },
+            BotRequest::Hello { version, .. } if *version != BOT_PROTOCOL_VERSION => {
+                Some(format!("protocol version {} is not supported, expected {}", version, BOT_PROTOCOL_VERSION))
+            },
+            BotRequest::Hello { player: Some(player), .. } if controllers.get(*player) != Controller::Bot => {
+                Some(format!("the {:?} paddle is not controlled by a bot", player))
+            },
+            BotRequest::Hello { player: Some(player), .. } if taken(*player) => {
+                Some(format!("another bot controls the {:?} paddle", player))
+            },
+            BotRequest::Command { .. } if self.clients[index].hello.is_none() => Some("the first message must be a hello".to_string()),
+            BotRequest::Command { .. } if self.clients[index].hello.as_ref().is_some_and(|hello| hello.player.is_none()) => {
+                Some("a bot that only watches cannot send commands".to_string())
+            },
+            _ => None,
+        };
+        let client = &mut self.clients[index];
+        if let Some(refusal) = refusal {
+            client.refuse(refusal);
+            return;
+        }
+        match request {
+            BotRequest::Hello { player, name, lockstep, .. } => {
+                match player {
+                    Some(player) => info!("Bot {} at {} controls the {:?} paddle", name, client.address, player),
+                    None => info!("Bot {} at {} watches", name, client.address),
+                }
+                client.send(&BotMessage::Welcome { version: BOT_PROTOCOL_VERSION, player, tick });
+                client.hello = Some(Hello { player, name, lockstep });
+            },
+            BotRequest::Command { tick: command_tick, direction } => {
+                // Newer than any state sent, or older than a command already used
+                if command_tick >= tick || client.command.is_some_and(|(newest, _)| command_tick < newest) {
+                    return;
+                }
+                let direction = if direction.is_finite() { direction.clamp(-1.0, 1.0) } else { 0.0 };
+                client.command = Some((command_tick, direction));
+            },
+        }
+    }
+
+    /// Waits, up to `timeout`, for every lockstep bot to answer the newest state
+    pub fn wait_for_lockstep(&mut self, controllers: &Controllers, timeout: Duration) {
+        let started = Instant::now();
+        loop {
+            let answered = self.tick == 0 || self.clients.iter()
+                .filter(|client| client.hello.as_ref().is_some_and(|hello| hello.lockstep && hello.player.is_some()))
+                .all(|client| client.command.is_some_and(|(tick, _)| tick + 1 >= self.tick));
+            if answered {
+                return;
+            }
+            if started.elapsed() >= timeout {
+                debug!("Going on without a command from a lockstep bot for tick {}", self.tick - 1);
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+            self.poll(controllers);
+        }
+    }
+
+    /// Direction of the newest command for the paddle of `player`, 0 without a bot
+    pub fn direction(&self, player: Player) -> f32 {
+        self.clients.iter()
+            .find(|client| client.hello.as_ref().is_some_and(|hello| hello.player == Some(player)))
+            .and_then(|client| client.command)
+            .map_or(0.0, |(_, direction)| direction)
+    }
+
+    /// Sends every bot the state of the game after a tick, its tick is set here
+    pub fn send_state(&mut self, mut state: BotState) {
+        state.tick = self.tick;
+        self.tick += 1;
+        for client in self.clients.iter_mut().filter(|client| client.hello.is_some()) {
+            state.command_tick = client.command.map(|(tick, _)| tick);
+            client.send(&BotMessage::State(state.clone()));
+            client.flush();
+        }
+    }
+}
+
+/// Serves the bots while there is a `BotServer` resource. The simulation asks them for the
+/// paddle directions before each tick and sends them the state after it, see `game::run_local`.
+pub struct BotPlugin;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        // Bots connect and say hello also while the simulation stands still
+        app.add_system_to_stage(CoreStage::PreUpdate, bot_poll);
+    }
+}
+
+fn bot_poll(server: Option<ResMut<BotServer>>, controllers: Res<Controllers>) {
+    if let Some(mut server) = server {
+        server.poll(&controllers);
+    }
+}
+
+/// Before a tick, waits for lockstep bots to answer the last state and gives the paddles
+/// controlled by bots their newest commands
+pub fn bot_inputs(world: &mut World) {
+    let controllers = *world.resource::<Controllers>();
+    let directions = match world.get_resource_mut::<BotServer>() {
+        Some(mut server) => {
+            server.poll(&controllers);
+            server.wait_for_lockstep(&controllers, LOCKSTEP_TIMEOUT);
+            [Player::Left, Player::Right].map(|player| server.direction(player))
+        },
+        None => return,
+    };
+    let mut inputs = world.resource_mut::<NetInputs>();
+    for (index, player) in [Player::Left, Player::Right].into_iter().enumerate() {
+        if controllers.get(player) == Controller::Bot {
+            inputs.0[index] = encode_direction(directions[index]);
+        }
+    }
+}
+
+/// After a tick, sends every bot the game as the tick left it
+pub fn send_bot_state(world: &mut World) {
+    if !world.contains_resource::<BotServer>() {
+        return;
+    }
+    let balls = world.query_filtered::<(&Transform, &Moving), With<Ball>>()
+        .iter(world)
+        .map(|(transform, moving)| BotBody::new(transform, moving))
+        .collect();
+    let mut paddles = [BotBody::default(); 2];
+    for (Paddle(player), transform, moving) in world.query::<(&Paddle, &Transform, &Moving)>().iter(world) {
+        paddles[*player as usize] = BotBody::new(transform, moving);
+    }
+    let state = BotState {
+        tick: 0,
+        state: world.resource::<State<AppState>>().current().clone(),
+        balls,
+        left: paddles[Player::Left as usize],
+        right: paddles[Player::Right as usize],
+        score: world.resource::<Score>().clone(),
+        command_tick: None,
+    };
+    world.resource_mut::<BotServer>().send_state(state);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use bevy::input::InputPlugin;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::accessibility::AccessibilitySettings;
+    use crate::consts::PADDLE_SPEED;
+    use crate::game::GamePlugin;
+    use crate::net::tick_duration;
+    use crate::setup::setup;
+    use super::*;
+
+    const BOTH: Controllers = Controllers { left: Controller::Bot, right: Controller::Bot };
+
+    struct TestBot {
+        writer: TcpStream,
+        reader: BufReader<TcpStream>,
+    }
+
+    impl TestBot {
+        fn connect(server: &mut BotServer) -> TestBot {
+            let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server.local_addr().unwrap().port())).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            server.poll(&BOTH);
+            TestBot { writer: stream.try_clone().unwrap(), reader: BufReader::new(stream) }
+        }
+
+        fn send(&mut self, line: &str) {
+            self.writer.write_all(format!("{}\n", line).as_bytes()).unwrap();
+        }
+
+        /// Lets the server take in what was sent and reads its answer
+        fn exchange(&mut self, server: &mut BotServer, line: &str) -> BotMessage {
+            self.send(line);
+            settle(server);
+            self.receive()
+        }
+
+        fn receive(&mut self) -> BotMessage {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+    }
+
+    fn settle(server: &mut BotServer) {
+        thread::sleep(Duration::from_millis(20));
+        server.poll(&BOTH);
+    }
+
+    fn state() -> BotState {
+        BotState {
+            tick: 0,
+            state: AppState::InGame,
+            balls: vec![BotBody { position: [1.0, 2.0], velocity: [-3.0, 4.0] }],
+            left: BotBody::default(),
+            right: BotBody::default(),
+            score: Score::default(),
+            command_tick: None,
+        }
+    }
+
+    #[test]
+    fn bot_gets_states_and_moves_its_paddle() {
+        let mut server = BotServer::bind(0).unwrap();
+        let mut bot = TestBot::connect(&mut server);
+        let welcome = bot.exchange(&mut server, r#"{"type": "hello", "version": 1, "player": "Left", "name": "test"}"#);
+        assert_eq!(welcome, BotMessage::Welcome { version: BOT_PROTOCOL_VERSION, player: Some(Player::Left), tick: 0 });
+
+        server.send_state(state());
+        server.send_state(state());
+        let first = bot.receive();
+        assert!(matches!(first, BotMessage::State(BotState { tick: 0, command_tick: None, .. })), "{:?}", first);
+        assert_eq!(bot.receive(), BotMessage::State(BotState { tick: 1, ..state() }));
+
+        bot.send(r#"{"type": "command", "tick": 1, "direction": 0.5}"#);
+        // Older than the one used and newer than any state, both ignored
+        bot.send(r#"{"type": "command", "tick": 0, "direction": -1.0}"#);
+        bot.send(r#"{"type": "command", "tick": 5, "direction": -1.0}"#);
+        settle(&mut server);
+        assert_eq!(server.direction(Player::Left), 0.5);
+        assert_eq!(server.direction(Player::Right), 0.0);
+
+        server.send_state(state());
+        assert_eq!(bot.receive(), BotMessage::State(BotState { tick: 2, command_tick: Some(1), ..state() }));
+    }
+
+    #[test]
+    fn bad_hellos_are_refused() {
+        let mut server = BotServer::bind(0).unwrap();
+        let mut first = TestBot::connect(&mut server);
+        first.exchange(&mut server, r#"{"type": "hello", "version": 1, "player": "Right"}"#);
+
+        let refusals = [
+            r#"{"type": "hello", "version": 99, "player": "Left"}"#,
+            r#"{"type": "hello", "version": 1, "player": "Right"}"#,
+            r#"{"type": "command", "tick": 0, "direction": 1.0}"#,
+            r#"not json"#,
+        ];
+        for line in refusals {
+            let mut bot = TestBot::connect(&mut server);
+            let answer = bot.exchange(&mut server, line);
+            assert!(matches!(answer, BotMessage::Error { .. }), "{} got {:?}", line, answer);
+        }
+        assert_eq!(server.clients.len(), 1);
+
+        // Only paddles set to be controlled by a bot
+        let mut bot = TestBot::connect(&mut server);
+        bot.send(r#"{"type": "hello", "version": 1, "player": "Left"}"#);
+        thread::sleep(Duration::from_millis(20));
+        server.poll(&Controllers { left: Controller::Cpu, right: Controller::Bot });
+        assert!(matches!(bot.receive(), BotMessage::Error { .. }));
+
+        // Watching takes no paddle
+        let mut watcher = TestBot::connect(&mut server);
+        let welcome = watcher.exchange(&mut server, r#"{"type": "hello", "version": 1, "player": null}"#);
+        assert!(matches!(welcome, BotMessage::Welcome { player: None, .. }));
+    }
+
+    #[test]
+    fn lockstep_waits_for_the_bot() {
+        let mut server = BotServer::bind(0).unwrap();
+        let mut bot = TestBot::connect(&mut server);
+        bot.exchange(&mut server, r#"{"type": "hello", "version": 1, "player": "Left", "lockstep": true}"#);
+        server.send_state(state());
+        bot.receive();
+
+        // Not answered, the wait gives up
+        let started = Instant::now();
+        server.wait_for_lockstep(&BOTH, Duration::from_millis(50));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        let answer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            bot.send(r#"{"type": "command", "tick": 0, "direction": -1.0}"#);
+            bot
+        });
+        server.wait_for_lockstep(&BOTH, Duration::from_secs(5));
+        assert_eq!(server.direction(Player::Left), -1.0);
+        answer.join().unwrap();
+    }
+
+    #[test]
+    fn a_lockstep_bot_answers_every_tick() {
+        let mut server = BotServer::bind(0).unwrap();
+        let mut bot = TestBot::connect(&mut server);
+        let welcome = bot.exchange(&mut server, r#"{"type": "hello", "version": 1, "player": "Left", "lockstep": true}"#);
+        assert!(matches!(welcome, BotMessage::Welcome { tick: 0, .. }));
+        let mut app = App::new();
+        app.insert_resource(Time::default())
+            .add_plugin(InputPlugin)
+            .add_event::<PongEvent>()
+            .init_resource::<Score>()
+            .insert_resource(MatchRules::default())
+            .insert_resource(Controllers { left: Controller::Bot, right: Controller::Cpu })
+            .insert_resource(AccessibilitySettings::default())
+            .insert_resource(GameRng(ChaCha8Rng::seed_from_u64(1)))
+            .insert_resource(MatchSeed::new(1))
+            .insert_resource(server)
+            .add_startup_system(setup)
+            .add_plugin(GamePlugin)
+            .add_plugin(BotPlugin)
+            .add_state(AppState::NewGame);
+
+        // Up and down on alternate ticks, each state answered before the next tick
+        let direction = |tick: u32| if tick.is_multiple_of(2) { 1.0 } else { -1.0 };
+        let states = thread::spawn(move || {
+            let mut states = Vec::new();
+            while states.len() < 300 {
+                let state = match bot.receive() {
+                    BotMessage::State(state) => state,
+                    other => panic!("expected a state, got {:?}", other),
+                };
+                bot.send(&format!(r#"{{"type": "command", "tick": {}, "direction": {}}}"#, state.tick, direction(state.tick)));
+                states.push(state);
+            }
+            states
+        });
+        // Three ticks a frame
+        let started = Instant::now();
+        app.world.resource_mut::<Time>().update_with_instant(started);
+        let mut frame = 0;
+        while !states.is_finished() {
+            frame += 1;
+            app.world.resource_mut::<Time>().update_with_instant(started + tick_duration() * 3 * frame);
+            app.update();
+        }
+        let states = states.join().unwrap();
+
+        for (tick, state) in states.iter().enumerate() {
+            assert_eq!(state.tick, tick as u32);
+            assert_eq!(state.command_tick, tick.checked_sub(1).map(|tick| tick as u32));
+        }
+        let in_game: Vec<_> = states.iter().filter(|state| state.state == AppState::InGame).collect();
+        assert!(in_game.len() > 60);
+        for state in in_game {
+            assert_eq!(state.left.velocity[1], direction(state.tick - 1) * PADDLE_SPEED, "at tick {}", state.tick);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+use bevy::prelude::*;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use crate::consts::*;
+use crate::net::NetSession;
+use crate::types::*;
+use crate::visuals::PongCamera2d;
+
+/// How quickly the camera catches up with where its mode wants it, per second
+const CAMERA_SMOOTHING: f32 = 3.0;
+const ORBIT_SPEED: f32 = 0.3;
+/// Ball speed relative to the paddle above which a hit shakes the screen
+const HARD_HIT_SPEED: f32 = 9.0;
+const TRAUMA_DECAY: f32 = 1.0;
+const MAX_SHAKE_OFFSET: f32 = 0.4;
+const MAX_SHAKE_ROLL: f32 = 0.04;
+const TILTED_EYE: Vec3 = Vec3::new(0.0, -AREA_HEIGHT/2.0, AREA_WIDTH);
+/// Radians per second the free camera turns
+const FREE_TURN_SPEED: f32 = 1.2;
+/// Distance per second the free camera moves in or out
+const FREE_ZOOM_SPEED: f32 = AREA_WIDTH * 0.5;
+const FREE_MIN_DISTANCE: f32 = AREA_HEIGHT * 0.5;
+const FREE_MAX_DISTANCE: f32 = AREA_WIDTH * 2.0;
+/// Short of straight down, where the camera's up direction would be undefined
+const FREE_MAX_PITCH: f32 = FRAC_PI_2 - 0.05;
+/// Low beside the goal the ball went into, whatever the camera mode
+const GOAL_REPLAY_EYE: Vec3 = Vec3::new(AREA_WIDTH * 0.3, -AREA_HEIGHT * 0.7, AREA_HEIGHT * 0.25);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum CameraMode {
+    /// Looking at the field from behind the bottom wall
+    Tilted,
+    /// Straight down at the field
+    TopDown,
+    /// Circles the field during the countdown and after goals
+    Orbit,
+    /// Leans toward the ball
+    Follow,
+    /// Turned with the arrow keys and moved in and out with PAGE UP/DOWN, only for spectators
+    #[value(skip)]
+    Free,
+}
+
+impl CameraMode {
+    fn next(self, spectating: bool) -> CameraMode {
+        match self {
+            CameraMode::Tilted => CameraMode::TopDown,
+            CameraMode::TopDown => CameraMode::Orbit,
+            CameraMode::Orbit => CameraMode::Follow,
+            CameraMode::Follow if spectating => CameraMode::Free,
+            CameraMode::Follow | CameraMode::Free => CameraMode::Tilted,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    pub mode: CameraMode,
+    /// Shake the screen on goals and hard hits
+    pub shake: bool,
+    /// Show the last seconds before a goal again in slow motion
+    pub goal_replay: bool,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings { mode: CameraMode::Tilted, shake: true, goal_replay: true }
+    }
+}
+
+/// Where the 3D camera is and is heading. Added to the camera by `VisualsPlugin`.
+#[derive(Component)]
+pub struct CameraRig {
+    eye: Vec3,
+    target: Vec3,
+    up: Vec3,
+    orbit_angle: f32,
+    free: FreeLook,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        CameraRig { eye: TILTED_EYE, target: Vec3::ZERO, up: Vec3::Z, orbit_angle: 0.0, free: FreeLook::default() }
+    }
+}
+
+/// Where the free camera looks at the middle of the field from
+struct FreeLook {
+    /// Around the field, 0 behind the bottom wall
+    yaw: f32,
+    /// Above the field, 0 level with it
+    pitch: f32,
+    distance: f32,
+}
+
+impl FreeLook {
+    fn eye(&self) -> Vec3 {
+        let horizontal = self.distance * self.pitch.cos();
+        Vec3::new(horizontal * self.yaw.sin(), -horizontal * self.yaw.cos(), self.distance * self.pitch.sin())
+    }
+}
+
+impl Default for FreeLook {
+    /// Starts from the tilted view
+    fn default() -> Self {
+        FreeLook { yaw: 0.0, pitch: TILTED_EYE.z.atan2(-TILTED_EYE.y), distance: TILTED_EYE.length() }
+    }
+}
+
+/// Screen shake amount from 0 to 1, decays over time
+#[derive(Default)]
+pub struct Trauma(pub f32);
+
+impl Trauma {
+    pub fn add(&mut self, amount: f32) {
+        self.0 = (self.0 + amount).min(1.0);
+    }
+}
+
+pub struct CameraPlugin {
+    pub settings: CameraSettings,
+}
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(self.settings)
+            .init_resource::<Trauma>()
+            .add_system(camera_input)
+            .add_system(add_trauma)
+            .add_system(update_camera_rig.after(camera_input).after(add_trauma));
+    }
+}
+
+fn camera_input(mut settings: ResMut<CameraSettings>,
+                time: Res<Time>,
+                keyboard_input: Res<Input<KeyCode>>,
+                session: Option<Res<NetSession>>,
+                mut rig_query: Query<&mut CameraRig>) {
+    let spectating = session.is_some_and(|session| session.spectating());
+    if keyboard_input.just_pressed(KeyCode::F4) {
+        settings.mode = settings.mode.next(spectating);
+        info!("Camera: {:?}", settings.mode);
+    }
+    if settings.mode == CameraMode::Free && !spectating {
+        // Stopped watching
+        settings.mode = CameraMode::Tilted;
+    }
+    if settings.mode == CameraMode::Free {
+        let axis = |negative, positive| {
+            keyboard_input.pressed(positive) as i32 as f32 - keyboard_input.pressed(negative) as i32 as f32
+        };
+        let dt = time.delta_seconds();
+        for mut rig in rig_query.iter_mut() {
+            let free = &mut rig.free;
+            free.yaw = (free.yaw + axis(KeyCode::Right, KeyCode::Left) * FREE_TURN_SPEED * dt) % TAU;
+            free.pitch = (free.pitch + axis(KeyCode::Down, KeyCode::Up) * FREE_TURN_SPEED * dt).clamp(0.0, FREE_MAX_PITCH);
+            free.distance = (free.distance + axis(KeyCode::PageUp, KeyCode::PageDown) * FREE_ZOOM_SPEED * dt)
+                .clamp(FREE_MIN_DISTANCE, FREE_MAX_DISTANCE);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        settings.shake = !settings.shake;
+        info!("Screen shake: {}", if settings.shake { "on" } else { "off" });
+    }
+}
+
+fn add_trauma(mut events: EventReader<PongEvent>,
+              mut trauma: ResMut<Trauma>,
+              ball_query: Query<&Moving, With<Ball>>,
+              paddle_query: Query<(&Paddle, &Moving)>) {
+    for e in events.iter() {
+        match e {
+            PongEvent::Goal(_) => trauma.add(0.6),
+            PongEvent::Bounce(Collider::Paddle(player), _) => {
+                let paddle_velocity = paddle_query.iter()
+                    .find(|(Paddle(paddle_player), _)| paddle_player == player)
+                    .map_or(Vec3::ZERO, |(_, moving)| moving.velocity);
+                if ball_query.iter().any(|ball| (ball.velocity - paddle_velocity).length() > HARD_HIT_SPEED) {
+                    trauma.add(0.3);
+                }
+            },
+            PongEvent::Bounce(..) | PongEvent::Countdown(_) => (),
+        }
+    }
+}
+
+/// Smooth pseudo-random value in -1..1, different for each `seed`
+fn shake_noise(time: f32, seed: f32) -> f32 {
+    ((time * 31.0 + seed).sin() + (time * 17.3 + seed * 2.1).sin() * 0.5) / 1.5
+}
+
+fn update_camera_rig(settings: Res<CameraSettings>,
+                     state: Res<State<AppState>>,
+                     time: Res<Time>,
+                     mut trauma: ResMut<Trauma>,
+                     ball_query: Query<&Transform, (With<Ball>, Without<CameraRig>)>,
+                     mut rig_query: Query<(&mut CameraRig, &mut Transform)>,
+                     mut camera_2d_query: Query<&mut Transform, (With<PongCamera2d>, Without<CameraRig>, Without<Ball>)>) {
+    let dt = time.delta_seconds();
+    let ball = ball_query.iter().next().map_or(Vec3::ZERO, |transform| transform.translation);
+    let showing_field = matches!(state.current(), AppState::Ready | AppState::Goal);
+
+    let shake = if settings.shake { trauma.0 * trauma.0 } else { 0.0 };
+    trauma.0 = (trauma.0 - TRAUMA_DECAY * dt).max(0.0);
+    let t = time.seconds_since_startup() as f32;
+    let shake_offset = Vec3::new(shake_noise(t, 1.0), shake_noise(t, 2.0), 0.0) * MAX_SHAKE_OFFSET * shake;
+    let shake_roll = shake_noise(t, 3.0) * MAX_SHAKE_ROLL * shake;
+
+    for (mut rig, mut transform) in rig_query.iter_mut() {
+        let (eye, target, up) = match settings.mode {
+            _ if *state.current() == AppState::GoalReplay => {
+                let side = if ball.x < 0.0 { -1.0 } else { 1.0 };
+                (GOAL_REPLAY_EYE * Vec3::new(side, 1.0, 1.0), ball, Vec3::Z)
+            },
+            CameraMode::Tilted => (TILTED_EYE, Vec3::ZERO, Vec3::Z),
+            CameraMode::TopDown => (Vec3::new(0.0, 0.0, AREA_WIDTH * 0.75), Vec3::ZERO, Vec3::Y),
+            CameraMode::Orbit if showing_field => {
+                rig.orbit_angle = (rig.orbit_angle + ORBIT_SPEED * dt) % TAU;
+                let radius = AREA_WIDTH * 0.6;
+                let eye = Vec3::new(radius * rig.orbit_angle.sin(), -radius * rig.orbit_angle.cos(), AREA_WIDTH * 0.7);
+                (eye, Vec3::ZERO, Vec3::Z)
+            },
+            CameraMode::Orbit => {
+                rig.orbit_angle = 0.0;
+                (TILTED_EYE, Vec3::ZERO, Vec3::Z)
+            },
+            CameraMode::Follow => {
+                let lean = Vec3::new(ball.x * 0.3, ball.y * 0.2, 0.0);
+                (TILTED_EYE + lean, lean * 1.5, Vec3::Z)
+            },
+            CameraMode::Free => (rig.free.eye(), Vec3::ZERO, Vec3::Z),
+        };
+        // Exponential smoothing, independent of the frame rate
+        let blend = 1.0 - (-CAMERA_SMOOTHING * dt).exp();
+        rig.eye = rig.eye.lerp(eye, blend);
+        rig.target = rig.target.lerp(target, blend);
+        rig.up = rig.up.lerp(up, blend).normalize();
+
+        *transform = Transform::from_translation(rig.eye + shake_offset).looking_at(rig.target + shake_offset, rig.up);
+        transform.rotate_local_z(shake_roll);
+    }
+
+    for mut transform in camera_2d_query.iter_mut() {
+        transform.translation.x = shake_offset.x;
+        transform.translation.y = shake_offset.y;
+        transform.rotation = Quat::from_rotation_z(shake_roll);
+    }
+}
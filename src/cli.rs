@@ -0,0 +1,314 @@
+use std::path::PathBuf;
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+use bevy_pong::accessibility::Palette;
+use bevy_pong::config::Settings;
+use bevy_pong::camera::CameraMode;
+use bevy_pong::net::{Netcode, DEFAULT_MAX_SPECTATORS};
+use bevy_pong::types::*;
+use bevy_pong::visuals::ViewMode;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum StartMode {
+    /// Show the title screen
+    Title,
+    /// Skip the title screen and start a match right away
+    Match,
+}
+
+fn parse_size(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(size) if size >= 1.0 && size.is_finite() => Ok(size),
+        Ok(_) => Err("must be at least 1".to_string()),
+        Err(_) => Err(format!("'{}' is not a number", s)),
+    }
+}
+
+fn parse_scale(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(scale) if (0.25..=4.0).contains(&scale) => Ok(scale),
+        Ok(_) => Err("must be between 0.25 and 4".to_string()),
+        Err(_) => Err(format!("'{}' is not a number", s)),
+    }
+}
+
+fn parse_game_speed(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(speed) if (0.25..=2.0).contains(&speed) => Ok(speed),
+        Ok(_) => Err("must be between 0.25 and 2".to_string()),
+        Err(_) => Err(format!("'{}' is not a number", s)),
+    }
+}
+
+/// A 3D take on the classic Pong
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Args {
+    /// Window width in logical pixels
+    #[arg(long, value_name = "PIXELS", value_parser = parse_size)]
+    pub width: Option<f32>,
+
+    /// Window height in logical pixels
+    #[arg(long, value_name = "PIXELS", value_parser = parse_size)]
+    pub height: Option<f32>,
+
+    /// Run in borderless fullscreen
+    #[arg(long, conflicts_with = "windowed")]
+    pub fullscreen: bool,
+
+    /// Run in a window
+    #[arg(long)]
+    pub windowed: bool,
+
+    /// Wait for vertical sync
+    #[arg(long, conflicts_with = "no_vsync")]
+    pub vsync: bool,
+
+    /// Do not wait for vertical sync
+    #[arg(long)]
+    pub no_vsync: bool,
+
+    /// How to draw the game, F3 switches between the views while playing
+    #[arg(long, value_enum)]
+    pub view: Option<ViewMode>,
+
+    /// Text size relative to the size that suits the window
+    #[arg(long, value_name = "FACTOR", value_parser = parse_scale)]
+    pub ui_scale: Option<f32>,
+
+    /// How the 3D camera moves, F4 switches between the modes while playing
+    #[arg(long, value_enum)]
+    pub camera: Option<CameraMode>,
+
+    /// Do not shake the screen on goals and hard hits, F5 toggles while playing
+    #[arg(long)]
+    pub no_shake: bool,
+
+    /// Do not show the last seconds before a goal again in slow motion
+    #[arg(long)]
+    pub no_goal_replay: bool,
+
+    /// Player colors, with palettes that suit color vision deficiencies
+    #[arg(long, value_enum)]
+    pub palette: Option<Palette>,
+
+    /// Draw with black, white and bright player colors
+    #[arg(long)]
+    pub high_contrast: bool,
+
+    /// Mark paddles and goals with dots and stripes so the players differ by shape too
+    #[arg(long)]
+    pub markers: bool,
+
+    /// Speed of the ball and the paddles, below 1 for more time to react
+    #[arg(long, value_name = "FACTOR", value_parser = parse_game_speed)]
+    pub game_speed: Option<f32>,
+
+    /// Do not blink the winner text
+    #[arg(long)]
+    pub no_flashing: bool,
+
+    /// Settings file to use instead of the one in the user config directory
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Seed for the random number generator
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Where to start, a match right away when headless and the title screen otherwise
+    #[arg(long, value_enum)]
+    pub start: Option<StartMode>,
+
+    /// Controller of the left paddle: local, keyboard, gamepad[:N], cpu or bot. Defaults to cpu when headless.
+    #[arg(long, value_name = "CONTROLLER")]
+    pub left: Option<Controller>,
+
+    /// Controller of the right paddle: local, keyboard, gamepad[:N], cpu or bot. Defaults to cpu when headless.
+    #[arg(long, value_name = "CONTROLLER")]
+    pub right: Option<Controller>,
+
+    /// Name of the left player, used for statistics
+    #[arg(long, value_name = "NAME")]
+    pub left_name: Option<String>,
+
+    /// Name of the right player, used for statistics
+    #[arg(long, value_name = "NAME")]
+    pub right_name: Option<String>,
+
+    /// Language of the UI text, e.g. en or fi. Defaults to the system locale.
+    #[arg(long, value_name = "LOCALE")]
+    pub locale: Option<String>,
+
+    /// Look of the game: default, classic, neon, amber or the asset path of a .theme.ron file.
+    /// F2 switches between themes while playing.
+    #[arg(long)]
+    pub theme: Option<String>,
+
+    /// Statistics file to use instead of the one in the user data directory
+    #[arg(long, value_name = "PATH")]
+    pub stats_file: Option<PathBuf>,
+
+    /// Host an online match on this UDP port, playing the left paddle
+    #[arg(long, value_name = "PORT", conflicts_with_all = ["connect", "spectate"])]
+    pub host: Option<u16>,
+
+    /// Spectators the hosted match takes at most, 0 for none
+    #[arg(long, value_name = "COUNT", requires = "host", default_value_t = DEFAULT_MAX_SPECTATORS)]
+    pub max_spectators: usize,
+
+    /// Join the online match hosted at this address, e.g. 192.168.1.5:7777, playing the right paddle,
+    /// or a match on the `pong-server` there, playing the paddle the server gives
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "spectate")]
+    pub connect: Option<String>,
+
+    /// Watch the online match hosted at this address, or the newest match on the `pong-server` there
+    #[arg(long, value_name = "ADDRESS")]
+    pub spectate: Option<String>,
+
+    /// How online matches cope with network latency, the host's choice is used
+    #[arg(long, value_enum, default_value_t = Netcode::Lockstep)]
+    pub netcode: Netcode,
+
+    /// Ticks from pressing a key to the paddle moving in online matches, the host's choice is used.
+    /// Defaults to 3 with lockstep and 1 with rollback.
+    #[arg(long, value_name = "TICKS", value_parser = clap::value_parser!(u32).range(0..=30))]
+    pub input_delay: Option<u32>,
+
+    /// Listen for paddle bots on this TCP port, see docs/bot-protocol.md. Defaults to 7779
+    /// when a paddle is controlled by a bot.
+    #[arg(long, value_name = "PORT")]
+    pub bot_port: Option<u16>,
+
+    /// Run the simulation without a window, exiting when the match is over. Starts a match of
+    /// the CPU against itself unless told otherwise.
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Seconds of play after which a headless match is stopped whatever the score, two CPUs
+    /// may never miss
+    #[arg(long, value_name = "SECONDS", requires = "headless", default_value_t = 300,
+          value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_time: u32,
+
+    /// Write the sound effects to WAV files in the directory and exit
+    #[arg(long, value_name = "DIR")]
+    pub export_sounds: Option<PathBuf>,
+}
+
+impl Args {
+    /// The arguments of the command line, exits with a usage error on combinations that cannot work
+    pub fn parse_checked() -> Args {
+        let args = Args::parse();
+        if args.headless && args.start == Some(StartMode::Title) {
+            Args::command()
+                .error(ErrorKind::ArgumentConflict, "--headless cannot --start at the title, there is nobody to press start")
+                .exit();
+        }
+        args
+    }
+
+    pub fn start(&self) -> StartMode {
+        self.start.unwrap_or(if self.headless { StartMode::Match } else { StartMode::Title })
+    }
+
+    /// Overrides loaded settings with the values given on the command line
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(width) = self.width {
+            settings.window.width = width;
+        }
+        if let Some(height) = self.height {
+            settings.window.height = height;
+        }
+        if self.fullscreen || self.windowed {
+            settings.window.fullscreen = self.fullscreen;
+        }
+        if self.vsync || self.no_vsync {
+            settings.window.vsync = self.vsync;
+        }
+        if let Some(view) = self.view {
+            settings.window.view = view;
+        }
+        if let Some(ui_scale) = self.ui_scale {
+            settings.window.ui_scale = ui_scale;
+        }
+        if let Some(mode) = self.camera {
+            settings.camera.mode = mode;
+        }
+        if self.no_shake {
+            settings.camera.shake = false;
+        }
+        if self.no_goal_replay {
+            settings.camera.goal_replay = false;
+        }
+        if let Some(palette) = self.palette {
+            settings.accessibility.palette = palette;
+        }
+        if self.high_contrast {
+            settings.accessibility.high_contrast = true;
+        }
+        if self.markers {
+            settings.accessibility.markers = true;
+        }
+        if let Some(game_speed) = self.game_speed {
+            settings.accessibility.game_speed = game_speed;
+        }
+        if self.no_flashing {
+            settings.accessibility.flashing = false;
+        }
+        // Nobody is there to play a headless match, the CPU plays the paddles not given
+        if self.headless {
+            settings.controllers = Controllers { left: Controller::Cpu, right: Controller::Cpu };
+        }
+        if let Some(left) = self.left {
+            settings.controllers.left = left;
+        }
+        if let Some(right) = self.right {
+            settings.controllers.right = right;
+        }
+        if let Some(locale) = &self.locale {
+            settings.locale = Some(locale.clone());
+        }
+        if let Some(theme) = &self.theme {
+            settings.theme = Some(theme.clone());
+        }
+        if let Some(name) = &self.left_name {
+            settings.players.left = name.clone();
+        }
+        if let Some(name) = &self.right_name {
+            settings.players.right = name.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> (Args, Settings) {
+        let args = Args::try_parse_from([&["bevy-pong"], args].concat()).unwrap();
+        let mut settings = Settings::default();
+        args.apply(&mut settings);
+        (args, settings)
+    }
+
+    #[test]
+    fn headless_plays_a_match_of_the_cpu() {
+        let (args, settings) = parse(&["--headless"]);
+        assert_eq!(args.start(), StartMode::Match);
+        assert_eq!((settings.controllers.left, settings.controllers.right), (Controller::Cpu, Controller::Cpu));
+
+        assert!(Args::try_parse_from(["bevy-pong", "--max-time", "60"]).is_err());
+
+        let (args, settings) = parse(&["--headless", "--right", "bot"]);
+        assert_eq!(args.start(), StartMode::Match);
+        assert_eq!((settings.controllers.left, settings.controllers.right), (Controller::Cpu, Controller::Bot));
+    }
+
+    #[test]
+    fn windowed_starts_at_the_title() {
+        let (args, settings) = parse(&[]);
+        assert_eq!(args.start(), StartMode::Title);
+        let defaults = Settings::default().controllers;
+        assert_eq!((settings.controllers.left, settings.controllers.right), (defaults.left, defaults.right));
+    }
+}
@@ -0,0 +1,142 @@
+use std::{fmt, fs, io, path::{Path, PathBuf}};
+use serde::{Deserialize, Serialize};
+use crate::accessibility::AccessibilitySettings;
+use crate::camera::CameraSettings;
+use crate::scaling::{REFERENCE_HEIGHT, REFERENCE_WIDTH};
+use crate::types::*;
+use crate::visuals::ViewMode;
+
+const SETTINGS_FILE: &str = "settings.ron";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub width: f32,
+    pub height: f32,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    pub view: ViewMode,
+    /// Text size relative to the size that suits the window
+    pub ui_scale: f32,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            width: REFERENCE_WIDTH,
+            height: REFERENCE_HEIGHT,
+            fullscreen: false,
+            vsync: true,
+            view: ViewMode::ThreeD,
+            ui_scale: 1.0,
+        }
+    }
+}
+
+/// Volume levels from 0 to 1
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub music: f32,
+    pub effects: f32,
+    pub muted: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings { master: 1.0, music: 0.6, effects: 0.8, muted: false }
+    }
+}
+
+/// Everything that can be configured from the settings file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub window: WindowSettings,
+    pub rules: MatchRules,
+    pub controllers: Controllers,
+    pub players: PlayerNames,
+    pub camera: CameraSettings,
+    pub accessibility: AccessibilitySettings,
+    pub audio: AudioSettings,
+    /// Language of the UI text, e.g. `fi`. The system locale is used if not set.
+    pub locale: Option<String>,
+    /// Built-in theme name or asset path of a `.theme.ron` file, the default theme if not set
+    pub theme: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, ron::error::SpannedError),
+    Write(PathBuf, io::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "invalid settings in {}: {}", path.display(), e),
+            ConfigError::Write(path, e) => write!(f, "could not write {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Directory for per-user application files, `None` where there is no such thing (e.g. the web)
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("bevy-pong"))
+}
+
+/// Directory for per-user data such as statistics, `None` where there is no such thing
+pub fn data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("bevy-pong"))
+}
+
+impl Settings {
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(SETTINGS_FILE))
+    }
+
+    /// Loads settings from `path`, or from the default location if `None`.
+    /// A missing file at the default location is not an error and yields the defaults.
+    pub fn load(path: Option<&Path>) -> Result<Settings, ConfigError> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Settings::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Settings::default()),
+            },
+        };
+        match fs::read_to_string(&path) {
+            Ok(content) => ron::from_str(&content).map_err(|e| ConfigError::Parse(path, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Ok(Settings::default()),
+            Err(e) => Err(ConfigError::Io(path, e)),
+        }
+    }
+
+    /// Changes the settings stored at `path`, or at the default location if `None`.
+    /// Settings given on the command line are not in the file and are left out.
+    pub fn update(path: Option<&Path>, change: impl FnOnce(&mut Settings)) -> Result<(), ConfigError> {
+        let path = match path.map(Path::to_path_buf).or_else(Settings::default_path) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut settings = Settings::load(Some(&path)).or_else(|e| match e {
+            ConfigError::Io(_, ref io_error) if io_error.kind() == io::ErrorKind::NotFound => Ok(Settings::default()),
+            e => Err(e),
+        })?;
+        change(&mut settings);
+        ron::ser::to_string_pretty(&settings, ron::ser::PrettyConfig::default())
+            .map_err(io::Error::other)
+            .and_then(|content| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(&path, content)
+            })
+            .map_err(|e| ConfigError::Write(path, e))
+    }
+}
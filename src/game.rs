@@ -1,77 +1,244 @@
 use crate::types::*;
 use crate::consts::*;
+use crate::accessibility::AccessibilitySettings;
+use crate::locale::Locale;
+use std::time::Duration;
+use crate::bot::{bot_inputs, send_bot_state};
+use crate::net::{encode_direction, tick_duration, NetInputs, NetSession, MAX_CATCH_UP};
+use crate::replay::{record_tick, run_playback, Playback};
+use crate::rollback::run_online;
 use bevy::{
     prelude::*,
+    ecs::{schedule::{ShouldRun, StateError}, system::SystemParam},
     sprite::collide_aabb::{collide, Collision}
 };
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app
-            // For some reason on_enter fails in setting the new state
-            .add_system_set(SystemSet::on_update(AppState::NewGame).with_system(new_game))
+            .insert_resource(Serve(Player::Left))
+            .init_resource::<SimTime>()
+            .init_resource::<TickClock>()
+            .init_resource::<NetInputs>()
+            .init_resource::<PhaseTimer>()
+            .init_resource::<NextState>()
+            .init_resource::<MatchSeed>()
+            .init_resource::<RootState>()
+            .add_system_to_stage(CoreStage::PreUpdate, frame_time)
+            .add_system_to_stage(CoreStage::PreUpdate, local_input.after(frame_time))
+            .add_stage_before(CoreStage::Update, SimulationStage, Simulation { stage: simulation_stage() })
             .add_system_set(SystemSet::on_enter(AppState::Ready).with_system(ready_enter))
-            .add_system_set(SystemSet::on_update(AppState::Ready).with_system(ready_update))
             .add_system_set(SystemSet::on_exit(AppState::Ready).with_system(ready_exit))
-            .add_system_set(SystemSet::on_update(AppState::InGame)
-                .with_system(paddle_input)
-                .with_system(move_system)
-                .with_system(ball_collide_system)
-                .with_system(event_listener_system))
+            .add_system(countdown_text)
             .add_system_set(SystemSet::on_enter(AppState::Goal).with_system(goal_enter))
-            .add_system_set(SystemSet::on_update(AppState::Goal).with_system(goal_update))
             .add_system_set(SystemSet::on_exit(AppState::Goal).with_system(goal_exit))
             .add_system_set(SystemSet::on_enter(AppState::Win).with_system(win_enter))
-            .add_system_set(SystemSet::on_update(AppState::Win).with_system(win_update))
             .add_system_set(SystemSet::on_exit(AppState::Win).with_system(win_exit));
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+pub struct SimulationStage;
+
+/// Runs whenever the current state is `state`, there are no transitions inside the simulation
+fn in_state(state: AppState) -> impl FnMut(Res<State<AppState>>) -> ShouldRun {
+    move |current: Res<State<AppState>>| if *current.current() == state { ShouldRun::Yes } else { ShouldRun::No }
+}
+
+/// The systems that move the match on by one step. They ask for state changes through
+/// `NextState`, the enter and exit systems of each state run in the update stage as usual.
+pub fn simulation_stage() -> SystemStage {
+    let mut stage = SystemStage::parallel();
+    stage
+        // For some reason on_enter fails in setting the new state
+        .add_system_set(SystemSet::new().with_run_criteria(in_state(AppState::NewGame)).with_system(new_game))
+        .add_system_set(SystemSet::new().with_run_criteria(in_state(AppState::Ready)).with_system(ready_update))
+        // In a fixed order, online peers must get the same result from the same input
+        .add_system_set(SystemSet::new().with_run_criteria(in_state(AppState::InGame))
+            .with_system(paddle_input)
+            .with_system(move_system.after(paddle_input))
+            .with_system(ball_collide_system.after(move_system))
+            .with_system(match_clock)
+            .with_system(time_limit_system.after(match_clock).after(ball_collide_system)))
+        .add_system_set(SystemSet::new().with_run_criteria(in_state(AppState::Goal)).with_system(goal_update))
+        .add_system_set(SystemSet::new().with_run_criteria(in_state(AppState::Win)).with_system(win_update));
+    stage
+}
+
+/// Runs the simulation in fixed ticks, as many as the frame time, an online match or a replay needs
+pub struct Simulation {
+    stage: SystemStage,
+}
+
+impl Stage for Simulation {
+    fn run(&mut self, world: &mut World) {
+        if world.contains_resource::<NetSession>() {
+            run_online(&mut self.stage, world);
+        } else if world.contains_resource::<Playback>() {
+            run_playback(&mut self.stage, world);
+        } else {
+            run_local(&mut self.stage, world);
+        }
+    }
+}
+
+/// Plays one tick with the paddle inputs of the left and right player
+pub fn play_tick(stage: &mut SystemStage, world: &mut World, inputs: [i8; 2]) {
+    world.resource_mut::<NetInputs>().0 = inputs;
+    world.resource_mut::<SimTime>().delta = tick_duration();
+    stage.run(world);
+}
+
+/// Plays the ticks the frame time has reached, with the input read this frame and the newest
+/// commands of bots, which see each tick. A state change
+/// ends the frame's ticks, the next tick is played after the enter and exit systems have run,
+/// so the same inputs always give the same match.
+fn run_local(stage: &mut SystemStage, world: &mut World) {
+    if apply_next_state(world) {
+        return;
+    }
+    // The match stands still behind the pause menu and while a goal is shown again
+    if matches!(world.resource::<State<AppState>>().current(), AppState::Paused | AppState::GoalReplay) {
+        world.resource_mut::<TickClock>().0 = Duration::ZERO;
+        return;
+    }
+    let tick = tick_duration();
+    while world.resource::<TickClock>().0 >= tick {
+        world.resource_mut::<TickClock>().0 -= tick;
+        bot_inputs(world);
+        let inputs = world.resource::<NetInputs>().0;
+        record_tick(world, inputs);
+        play_tick(stage, world, inputs);
+        send_bot_state(world);
+        if apply_next_state(world) {
+            return;
+        }
+    }
+}
+
+/// Passes the state change the simulation asked for on to the state machine, returns whether there was one.
+/// A change already queued from elsewhere, like the first state at startup, goes first and the
+/// request waits for the next frame.
+pub fn apply_next_state(world: &mut World) -> bool {
+    let next = match world.resource::<NextState>().0.clone() {
+        Some(next) => next,
+        None => return false,
+    };
+    match world.resource_mut::<State<AppState>>().set(next) {
+        Err(StateError::StateAlreadyQueued) => (),
+        result => {
+            result.unwrap();
+            world.resource_mut::<NextState>().0 = None;
+        },
+    }
+    true
+}
+
+/// Time the local simulation has yet to play in fixed ticks
+#[derive(Default)]
+pub struct TickClock(pub Duration);
+
+/// Online matches keep their own clock, see `net::net_step`
+pub fn frame_time(mut clock: ResMut<TickClock>, time: Res<Time>) {
+    clock.0 = (clock.0 + time.delta()).min(tick_duration() * MAX_CATCH_UP);
+}
+
+/// Reads the paddle directions of the local controllers for the ticks of this frame. Network
+/// inputs are left as they are, set by whatever plays the match.
+fn local_input(mut inputs: ResMut<NetInputs>,
+               controllers: Res<Controllers>,
+               devices: InputDevices,
+               session: Option<Res<NetSession>>,
+               paddle_query: Query<(&Paddle, &Transform)>,
+               ball_query: Query<&Transform, With<Ball>>) {
+    if session.is_some() {
+        return;
+    }
+    for (index, player) in [Player::Left, Player::Right].into_iter().enumerate() {
+        let paddle_y = paddle_query.iter()
+            .find(|(Paddle(paddle_player), _)| *paddle_player == player)
+            .map_or(0.0, |(_, transform)| transform.translation.y);
+        inputs.0[index] = match controllers.get(player) {
+            // Set for each tick, see `bot::bot_inputs`
+            Controller::Network | Controller::Bot => continue,
+            controller => encode_direction(devices.direction(controller, player, paddle_y, &ball_query)),
+        };
+    }
+}
+
 fn new_game(mut score: ResMut<Score>,
-            mut state: ResMut<State<AppState>>,
-            mut score_text_query: Query<&mut Text, With<ScoreText>>) {
+            mut rng: ResMut<GameRng>,
+            mut match_seed: ResMut<MatchSeed>,
+            mut paddle_query: Query<(&mut Transform, &mut Moving), With<Paddle>>,
+            mut next_state: ResMut<NextState>) {
     debug!("NewGame");
-    score.left = 0;
-    score.right = 0;
-    for mut text in score_text_query.iter_mut() {
-        text.sections[0].value = "0".to_string();
+    *score = Score::default();
+    // Every match plays from a seed of its own, all a replay needs to serve the same balls
+    match_seed.current = match_seed.next;
+    rng.0 = ChaCha8Rng::seed_from_u64(match_seed.current);
+    match_seed.next = rng.gen();
+    for (mut transform, mut moving) in paddle_query.iter_mut() {
+        transform.translation.y = 0.0;
+        moving.velocity = Vec3::ZERO;
     }
-    state.set(AppState::Ready).unwrap()
+    next_state.0 = Some(AppState::Ready);
 }
 
-fn ready_enter(mut ball_query: Query<&mut Transform, With<Ball>>,
-               mut ready_text_query: Query<(&mut Text, &mut Transform, &mut Style), (With<ReadyText>, Without<Ball>)>) {
+fn ready_enter(mut ball_query: Query<(&mut Transform, &mut Moving), With<Ball>>,
+               mut ready_text_query: Query<&mut Style, With<ReadyText>>,
+               mut rng: ResMut<GameRng>,
+               mut serve: ResMut<Serve>,
+               mut phase_timer: ResMut<PhaseTimer>,
+               mut events: EventWriter<PongEvent>) {
     debug!("Ready");
-    for mut transform in ball_query.iter_mut() {
+    phase_timer.0 = Timer::from_seconds(READY_DURATION, false);
+    events.send(PongEvent::Countdown(READY_DURATION.ceil() as u32));
+    for (mut transform, mut moving) in ball_query.iter_mut() {
         transform.translation.x = 0.0;
         transform.translation.y = 0.0;
+        let direction = |rng: &mut GameRng| if rng.gen() { 1.0 } else { -1.0 };
+        moving.velocity = Vec3::new(direction(&mut rng) * BALL_SPEED, direction(&mut rng) * BALL_SPEED, 0.0);
+        // The ball is served away from the serving player
+        serve.0 = if moving.velocity.x > 0.0 { Player::Left } else { Player::Right };
     }
-    for (mut text, mut transform, mut style) in ready_text_query.iter_mut() {
-        text.sections[0].value = format!("{}", READY_DURATION.ceil() as i32);
-        transform.scale = Vec3::ONE;
+    for mut style in ready_text_query.iter_mut() {
         style.display = Display::Flex;
     }
 }
 
-fn ready_update(mut state: ResMut<State<AppState>>,
-                time: Res<Time>,
-                mut local_timer: Local<Option<Timer>>,
-                mut ready_text_query: Query<(&mut Text, &mut Transform), With<ReadyText>>) {
-    let timer = local_timer.get_or_insert_with(|| Timer::from_seconds(READY_DURATION, false));
-    if timer.tick(time.delta()).just_finished() {
-        *local_timer = None;
-        state.set(AppState::InGame).unwrap();
+fn ready_update(mut next_state: ResMut<NextState>,
+                time: Res<SimTime>,
+                mut phase_timer: ResMut<PhaseTimer>,
+                mut ready_text_query: Query<&mut Transform, With<ReadyText>>,
+                mut events: EventWriter<PongEvent>) {
+    let timer = &mut phase_timer.0;
+    if timer.tick(time.delta).just_finished() {
+        events.send(PongEvent::Countdown(0));
+        next_state.0 = Some(AppState::InGame);
     } else {
-        for (mut text, mut text_transform) in ready_text_query.iter_mut() {
-            let current_time = timer.elapsed_secs();
-            let previous_time = timer.elapsed_secs() - time.delta().as_secs_f32();
-            if current_time.floor() > previous_time.floor() {
-                text.sections[0].value = format!("{}", (READY_DURATION - current_time).ceil() as i32);
+        let current_time = timer.elapsed_secs();
+        let previous_time = timer.elapsed_secs() - time.delta_seconds();
+        if current_time.floor() > previous_time.floor() {
+            events.send(PongEvent::Countdown((READY_DURATION - current_time).ceil() as u32));
+        }
+        for mut text_transform in ready_text_query.iter_mut() {
+            text_transform.scale += Vec3::splat(1.0 * time.delta_seconds());
+        }
+    }
+}
+
+/// Shows each second of the countdown as it is announced
+fn countdown_text(mut events: EventReader<PongEvent>,
+                  mut ready_text_query: Query<(&mut Text, &mut Transform), With<ReadyText>>) {
+    for e in events.iter() {
+        if let PongEvent::Countdown(seconds @ 1..) = e {
+            for (mut text, mut text_transform) in ready_text_query.iter_mut() {
+                text.sections[0].value = seconds.to_string();
                 text_transform.scale = Vec3::ONE;
-            } else {
-                text_transform.scale += Vec3::splat(1.0 * time.delta().as_secs_f32());
             }
         }
     }
@@ -86,50 +253,82 @@ pub fn ready_exit(mut ready_text_query: Query<&mut Style, With<ReadyText>>) {
 // InGame state systems
 //
 
-fn paddle_input(mut paddle_query: Query<(&Paddle, &mut Moving)>,
-                keyboard_input: Res<Input<KeyCode>>,
-                gamepads: Res<Gamepads>,
-                gamepad_axes: Res<Axis<GamepadAxis>>) {
-    let mut left_velocity = Vec3::ZERO;
-    let mut right_velocity = Vec3::ZERO;
-
-    if keyboard_input.pressed(KeyCode::A) {
-        left_velocity.y += PADDLE_SPEED;
-    }
-    if keyboard_input.pressed(KeyCode::Z) {
-        left_velocity.y -= PADDLE_SPEED;
-    }
-    if keyboard_input.pressed(KeyCode::K) {
-        right_velocity.y += PADDLE_SPEED;
+fn keyboard_direction(player: Player, keyboard_input: &Input<KeyCode>) -> f32 {
+    let (up, down) = match player {
+        Player::Left => (KeyCode::A, KeyCode::Z),
+        Player::Right => (KeyCode::K, KeyCode::M),
+    };
+    let mut direction = 0.0;
+    if keyboard_input.pressed(up) {
+        direction += 1.0;
     }
-    if keyboard_input.pressed(KeyCode::M) {
-        right_velocity.y -= PADDLE_SPEED;
+    if keyboard_input.pressed(down) {
+        direction -= 1.0;
     }
-    
-    for gamepad in gamepads.iter() {
-        let left_stick_y = gamepad_axes.get(GamepadAxis::new(*gamepad, GamepadAxisType::LeftStickY))
-            .unwrap_or(0.0);
-        let right_stick_y = gamepad_axes.get(GamepadAxis::new(*gamepad, GamepadAxisType::RightStickY))
-            .unwrap_or(0.0);
-        left_velocity.y += left_stick_y * PADDLE_SPEED;
-        right_velocity.y += right_stick_y * PADDLE_SPEED;
+    direction
+}
+
+/// Everything a controller on this machine can read its direction from
+#[derive(SystemParam)]
+pub struct InputDevices<'w, 's> {
+    keyboard_input: Res<'w, Input<KeyCode>>,
+    gamepads: Res<'w, Gamepads>,
+    gamepad_axes: Res<'w, Axis<GamepadAxis>>,
+    #[system_param(ignore)]
+    _marker: std::marker::PhantomData<&'s ()>,
+}
+
+impl<'w, 's> InputDevices<'w, 's> {
+    /// Direction the `controller` moves the paddle of `player` at `paddle_y`, up is positive
+    pub fn direction(&self,
+                     controller: Controller,
+                     player: Player,
+                     paddle_y: f32,
+                     ball_query: &Query<&Transform, With<Ball>>) -> f32 {
+        match controller {
+            Controller::Local => {
+                let stick = match player {
+                    Player::Left => GamepadAxisType::LeftStickY,
+                    Player::Right => GamepadAxisType::RightStickY,
+                };
+                let sticks: f32 = self.gamepads.iter()
+                    .filter_map(|gamepad| self.gamepad_axes.get(GamepadAxis::new(*gamepad, stick)))
+                    .sum();
+                keyboard_direction(player, &self.keyboard_input) + sticks
+            },
+            Controller::Keyboard => keyboard_direction(player, &self.keyboard_input),
+            Controller::Gamepad(id) => self.gamepad_axes.get(GamepadAxis::new(Gamepad::new(id), GamepadAxisType::LeftStickY))
+                .unwrap_or(0.0),
+            Controller::Cpu => {
+                // Follow the ball, with some slack so the paddle does not jitter
+                ball_query.iter()
+                    .map(|ball_transform| ball_transform.translation.y - paddle_y)
+                    .find(|distance| distance.abs() > PADDLE_LENGTH / 4.0)
+                    .map_or(0.0, f32::signum)
+            },
+            // Not read from a device, see `local_input`
+            Controller::Network | Controller::Bot => 0.0,
+        }
     }
-    
+}
+
+fn paddle_input(mut paddle_query: Query<(&Paddle, &mut Moving)>, inputs: Res<NetInputs>) {
     for (Paddle(player), mut paddle_moving) in paddle_query.iter_mut() {
-        paddle_moving.velocity = match player {
-            Player::Left => left_velocity,
-            Player::Right => right_velocity
-        };
+        paddle_moving.velocity = Vec3::new(0.0, inputs.direction(*player) * PADDLE_SPEED, 0.0);
     }
 }
 
-fn move_system(mut moving_query: Query<(&Moving, &mut Transform)>, time: Res<Time>) {
+fn move_system(mut moving_query: Query<(&Moving, &mut Transform)>,
+               time: Res<SimTime>,
+               accessibility: Res<AccessibilitySettings>) {
     for (moving, mut transform) in moving_query.iter_mut() {
-        transform.translation += moving.velocity * time.delta().as_secs_f32();
+        transform.translation += moving.velocity * time.delta_seconds() * accessibility.game_speed;
     }
 }
 
-fn bounce(moving: &mut Moving, collision: &Collision) {
+/// Returns whether the direction changed
+fn bounce(moving: &mut Moving, collision: &Collision) -> bool {
+    let before = moving.velocity;
     match collision {
         Collision::Top => moving.velocity.y = moving.velocity.y.abs(),
         Collision::Bottom => moving.velocity.y = -moving.velocity.y.abs(),
@@ -137,14 +336,32 @@ fn bounce(moving: &mut Moving, collision: &Collision) {
         Collision::Right => moving.velocity.x = moving.velocity.x.abs(),
         Collision::Inside => (),
     }
+    moving.velocity != before
+}
+
+/// Point on the edge of the ball that touches what it collided with
+fn contact_point(ball: Vec3, ball_size: Vec2, collision: &Collision) -> Vec3 {
+    let offset = match collision {
+        Collision::Top => Vec2::new(0.0, -ball_size.y),
+        Collision::Bottom => Vec2::new(0.0, ball_size.y),
+        Collision::Left => Vec2::new(ball_size.x, 0.0),
+        Collision::Right => Vec2::new(-ball_size.x, 0.0),
+        Collision::Inside => Vec2::ZERO,
+    };
+    ball + (offset / 2.0).extend(0.0)
 }
+
 fn ball_collide_system(mut ball_query: Query<(Entity, &Colliding, &mut Moving, &Transform), With<Ball>>,
                        colliding_query: Query<(Entity, &Colliding, &Transform)>,
+                       mut score: ResMut<Score>,
+                       mut next_state: ResMut<NextState>,
                        mut events: EventWriter<PongEvent>) {
-
+    // Walls last, a ball caught between a wall and the end of a paddle must not be sent through the wall
+    let mut others: Vec<_> = colliding_query.iter().collect();
+    others.sort_by_key(|(_, colliding, _)| colliding.kind == Collider::Wall);
 
     for (ball_entity, ball_colliding, mut ball_moving, ball_transform) in ball_query.iter_mut() {
-        for (other_entity, other_colliding, other_transform) in colliding_query.iter() {
+        for &(other_entity, other_colliding, other_transform) in &others {
             if ball_entity == other_entity {
                 continue;
             }
@@ -152,44 +369,42 @@ fn ball_collide_system(mut ball_query: Query<(Entity, &Colliding, &mut Moving, &
                                     other_transform.translation, other_colliding.size);
             if let Some(c) = collision {
                 match other_colliding.kind {
-                    Collider::Wall | Collider::Ball | Collider::Paddle => bounce(&mut ball_moving, &c),
-                    Collider::Goal(player) => events.send(PongEvent::Goal(player)),
+                    Collider::Wall | Collider::Ball | Collider::Paddle(_) => {
+                        if bounce(&mut ball_moving, &c) {
+                            let contact = contact_point(ball_transform.translation, ball_colliding.size, &c);
+                            events.send(PongEvent::Bounce(other_colliding.kind, contact));
+                        }
+                    },
+                    Collider::Goal(player) => {
+                        match player {
+                            Player::Left => score.left += 1,
+                            Player::Right => score.right += 1,
+                        }
+                        next_state.0 = Some(AppState::Goal);
+                        events.send(PongEvent::Goal(player));
+                    },
                 }
             }
         }
     }
 }
 
-fn event_listener_system(mut events: EventReader<PongEvent>,
-                         mut score: ResMut<Score>,
-                         mut app_state: ResMut<State<AppState>>) {
-    for e in events.iter() {
-        match e {
-            PongEvent::Goal(player) => {
-                match player {
-                    Player::Left => {
-                        score.left += 1;
-                    },
-                    Player::Right => {
-                        score.right += 1;
-                    }
-                };
-                app_state.set(AppState::Goal).unwrap();
-            },
-        }
+fn match_clock(mut score: ResMut<Score>, time: Res<SimTime>) {
+    score.time += time.delta_seconds();
+}
+
+fn time_limit_system(score: Res<Score>, rules: Res<MatchRules>, mut next_state: ResMut<NextState>) {
+    // A goal in the same step goes first, the match ends after it has been shown
+    if next_state.0.is_none() && rules.time_is_up(&score) && score.leader().is_some() {
+        next_state.0 = Some(AppState::Win);
     }
 }
 
-fn goal_enter(mut goal_text_query: Query<(&mut Transform, &mut Style), (With<GoalText>, Without<ScoreText>)>,
+fn goal_enter(mut goal_text_query: Query<(&mut Transform, &mut Style), With<GoalText>>,
               score: Res<Score>,
-              mut score_text_query: Query<(&ScoreText, &mut Text)>) {
+              mut phase_timer: ResMut<PhaseTimer>) {
     debug!("Goal {} - {}", score.left, score.right);
-    for (ScoreText(player), mut text) in score_text_query.iter_mut() {
-        match player {
-            Player::Left => text.sections[0].value = format!("{}", score.left),
-            Player::Right => text.sections[0].value = format!("{}", score.right),
-        }
-    }
+    phase_timer.0 = Timer::from_seconds(3.0, false);
     for (mut transform, mut style) in goal_text_query.iter_mut() {
         transform.scale = Vec3::ONE;
         style.display = Display::Flex;
@@ -197,21 +412,33 @@ fn goal_enter(mut goal_text_query: Query<(&mut Transform, &mut Style), (With<Goa
 }
 
 fn goal_update(mut goal_text_query: Query<&mut Transform, With<GoalText>>,
-               mut state: ResMut<State<AppState>>,
-               score: Res<Score>,
-               time: Res<Time>,
-               mut local_timer: Local<Option<Timer>>) {
-    let timer = local_timer.get_or_insert_with(|| Timer::from_seconds(3.0, false));
-    if timer.tick(time.delta()).just_finished() {
-        *local_timer = None;
-        if score.left >= GOALS_TO_WIN || score.right >= GOALS_TO_WIN {
-            state.set(AppState::Win).unwrap();
+               mut next_state: ResMut<NextState>,
+               mut score: ResMut<Score>,
+               rules: Res<MatchRules>,
+               time: Res<SimTime>,
+               mut phase_timer: ResMut<PhaseTimer>) {
+    if phase_timer.0.tick(time.delta).just_finished() {
+        let set_winner = [Player::Left, Player::Right].into_iter()
+            .find(|player| score.goals(*player) >= rules.goals_to_win);
+        match set_winner {
+            Some(Player::Left) => score.left_sets += 1,
+            Some(Player::Right) => score.right_sets += 1,
+            None => (),
+        }
+        let match_won = set_winner.is_some_and(|player| score.sets(player) >= rules.sets_to_win);
+        if match_won || (rules.time_is_up(&score) && score.leader().is_some()) {
+            next_state.0 = Some(AppState::Win);
         } else {
-            state.set(AppState::Ready).unwrap();
+            if set_winner.is_some() {
+                // Next set
+                score.left = 0;
+                score.right = 0;
+            }
+            next_state.0 = Some(AppState::Ready);
         }
     } else {
         for mut text_transform in goal_text_query.iter_mut() {
-            text_transform.scale += Vec3::splat(1.0 * time.delta().as_secs_f32());
+            text_transform.scale += Vec3::splat(1.0 * time.delta_seconds());
         }
     }
 }
@@ -222,36 +449,30 @@ pub fn goal_exit(mut goal_text_query: Query<&mut Style, With<GoalText>>) {
     }
 }
 
-fn win_enter(mut commands: Commands,
-             asset_server: Res<AssetServer>,
-             score: Res<Score>) {
+fn win_enter(mut win_text_query: Query<(&mut Text, &mut Style), With<WinText>>,
+             score: Res<Score>,
+             names: Res<PlayerNames>,
+             locale: Res<Locale>,
+             mut phase_timer: ResMut<PhaseTimer>) {
     debug!("Win");
-    let winner_text = if score.left > score.right { "Left" } else { "Right" };
-    commands.spawn_bundle(TextBundle {
-        text: Text::from_section(format!("{} player wins!", winner_text), TextStyle {
-            font: asset_server.load("fonts/DejaVuSansMono-Bold.ttf"),
-            font_size: 100.0,
-            color: Color::WHITE
-        }),
-        style: Style {
-            margin: UiRect::all(Val::Auto),
-            align_self: AlignSelf::Center,
-            ..Style::default()
-        },
-        ..Default::default()
-    })
-    .insert(WinText);
+    phase_timer.0 = Timer::from_seconds(3.0, false);
+    let winner = score.leader().unwrap_or(Player::Right);
+    for (mut text, mut style) in win_text_query.iter_mut() {
+        text.sections[0].value = locale.format("win", &[("name", names.get(winner))]);
+        style.display = Display::Flex;
+    }
 }
 
 fn win_update(mut win_text_query: Query<&mut Visibility, With<WinText>>,
-               mut state: ResMut<State<AppState>>,
-               time: Res<Time>,
-               mut local_timer: Local<Option<Timer>>) {
-    let timer = local_timer.get_or_insert_with(|| Timer::from_seconds(3.0, false));
-    if timer.tick(time.delta()).just_finished() {
-        *local_timer = None;
-        state.set(AppState::Title).unwrap();
-    } else {
+               mut next_state: ResMut<NextState>,
+               root: Res<RootState>,
+               time: Res<SimTime>,
+               accessibility: Res<AccessibilitySettings>,
+               mut phase_timer: ResMut<PhaseTimer>) {
+    let timer = &mut phase_timer.0;
+    if timer.tick(time.delta).just_finished() {
+        next_state.0 = Some(root.0.clone());
+    } else if accessibility.flashing {
         let blink_interval: f32 = 0.3;
         for mut text_visibility in win_text_query.iter_mut() {
             text_visibility.is_visible = timer.elapsed_secs().rem_euclid(blink_interval) > blink_interval / 2.0;
@@ -259,8 +480,8 @@ fn win_update(mut win_text_query: Query<&mut Visibility, With<WinText>>,
     }
 }
 
-pub fn win_exit(mut commands: Commands, win_text_query: Query<Entity, With<WinText>>) {
-    for e in win_text_query.iter() {
-        commands.entity(e).despawn_recursive();
+pub fn win_exit(mut win_text_query: Query<&mut Style, With<WinText>>) {
+    for mut style in win_text_query.iter_mut() {
+        style.display = Display::None;
     }
 }
@@ -0,0 +1,205 @@
+use std::collections::VecDeque;
+use bevy::prelude::*;
+use crate::camera::CameraSettings;
+use crate::locale::Locale;
+use crate::net::NetSession;
+use crate::replay::Playback;
+use crate::theme::{ActiveTheme, Themed};
+use crate::types::*;
+
+/// Seconds of play before a goal that are shown again
+const GOAL_REPLAY_SECONDS: f32 = 3.0;
+/// Speed of the goal replay relative to the match
+const SLOW_MOTION: f32 = 0.4;
+
+/// Where the ball and the paddles were at a frame of play
+struct Frame {
+    /// Seconds of play recorded before this frame
+    time: f32,
+    transforms: Vec<(Entity, Transform)>,
+}
+
+/// The last seconds of the current point, oldest first
+#[derive(Default)]
+pub struct GoalReplayBuffer {
+    frames: VecDeque<Frame>,
+    time: f32,
+}
+
+impl GoalReplayBuffer {
+    fn push(&mut self, delta: f32, transforms: Vec<(Entity, Transform)>) {
+        self.time += delta;
+        self.frames.push_back(Frame { time: self.time, transforms });
+        while self.frames.front().is_some_and(|frame| frame.time < self.time - GOAL_REPLAY_SECONDS) {
+            self.frames.pop_front();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.frames.clear();
+        self.time = 0.0;
+    }
+}
+
+/// The goal being shown again, over `AppState::Goal`
+pub struct GoalReplay {
+    frames: Vec<Frame>,
+    /// Seconds into the replay, counted in the time of the match
+    time: f32,
+    /// Where everything was at the goal, put back when the replay is over
+    goal: Vec<(Entity, Transform)>,
+}
+
+impl GoalReplay {
+    /// Where the ball or paddle was at the goal, it is somewhere before that while shown again
+    pub fn at_goal(&self, entity: Entity) -> Option<Transform> {
+        self.goal.iter().find(|(goal_entity, _)| *goal_entity == entity).map(|(_, transform)| *transform)
+    }
+
+    fn duration(&self) -> f32 {
+        match (self.frames.first(), self.frames.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    /// Transforms at the replay's time, in between the frames recorded around it
+    fn transforms(&self) -> Vec<(Entity, Transform)> {
+        let start = self.frames.first().map_or(0.0, |frame| frame.time);
+        let time = start + self.time;
+        let next = self.frames.iter().position(|frame| frame.time >= time).unwrap_or(self.frames.len() - 1);
+        let after = &self.frames[next];
+        let before = &self.frames[next.saturating_sub(1)];
+        let blend = if after.time > before.time { (time - before.time) / (after.time - before.time) } else { 1.0 };
+        before.transforms.iter()
+            .map(|(entity, transform)| {
+                let translation = after.transforms.iter()
+                    .find(|(after_entity, _)| after_entity == entity)
+                    .map_or(transform.translation, |(_, after)| transform.translation.lerp(after.translation, blend));
+                (*entity, Transform { translation, ..*transform })
+            })
+            .collect()
+    }
+}
+
+/// Shows the last seconds before a goal again in slow motion, from beside the goal
+pub struct GoalReplayPlugin;
+
+impl Plugin for GoalReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<GoalReplayBuffer>()
+            .add_system_set(SystemSet::on_enter(AppState::Ready).with_system(clear_buffer))
+            .add_system_set(SystemSet::on_update(AppState::InGame).with_system(record_frame))
+            .add_system_set(SystemSet::on_enter(AppState::Goal).with_system(start_goal_replay))
+            .add_system_set(SystemSet::on_update(AppState::Goal).with_system(show_goal_replay))
+            .add_system_set(SystemSet::on_enter(AppState::GoalReplay).with_system(goal_replay_enter))
+            .add_system_set(SystemSet::on_update(AppState::GoalReplay).with_system(goal_replay_update))
+            .add_system_set(SystemSet::on_exit(AppState::GoalReplay).with_system(goal_replay_exit));
+    }
+}
+
+fn transforms(query: &Query<(Entity, &Transform), Or<(With<Ball>, With<Paddle>)>>) -> Vec<(Entity, Transform)> {
+    query.iter().map(|(entity, transform)| (entity, *transform)).collect()
+}
+
+fn clear_buffer(mut buffer: ResMut<GoalReplayBuffer>) {
+    buffer.clear();
+}
+
+fn record_frame(mut buffer: ResMut<GoalReplayBuffer>,
+                time: Res<Time>,
+                query: Query<(Entity, &Transform), Or<(With<Ball>, With<Paddle>)>>) {
+    buffer.push(time.delta_seconds(), transforms(&query));
+}
+
+/// Online the other player would have to wait, and a match replay shows it as it was played
+fn start_goal_replay(mut commands: Commands,
+                     settings: Res<CameraSettings>,
+                     session: Option<Res<NetSession>>,
+                     playback: Option<Res<Playback>>,
+                     mut buffer: ResMut<GoalReplayBuffer>,
+                     query: Query<(Entity, &Transform), Or<(With<Ball>, With<Paddle>)>>) {
+    if !settings.goal_replay || session.is_some() || playback.is_some() || buffer.frames.is_empty() {
+        return;
+    }
+    // The frame of the goal itself
+    let goal = transforms(&query);
+    buffer.push(0.0, goal.clone());
+    let frames = buffer.frames.drain(..).collect();
+    commands.insert_resource(GoalReplay { frames, time: 0.0, goal });
+}
+
+fn show_goal_replay(mut state: ResMut<State<AppState>>, goal_replay: Option<Res<GoalReplay>>) {
+    if goal_replay.is_some() {
+        state.push(AppState::GoalReplay).unwrap();
+    }
+}
+
+fn goal_replay_enter(mut commands: Commands,
+                     theme: ActiveTheme,
+                     locale: Res<Locale>,
+                     mut goal_text_query: Query<&mut Style, With<GoalText>>) {
+    for mut style in goal_text_query.iter_mut() {
+        style.display = Display::None;
+    }
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::FlexEnd,
+            padding: UiRect::all(Val::Percent(3.0)),
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    })
+    .insert(GoalReplayText)
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle::from_sections([
+            TextSection::new(locale.get("goal-replay"), theme.text_style(50.0)),
+            TextSection::new(format!("\n{}", locale.get("goal-replay-skip")), theme.secondary_text_style(25.0)),
+        ])
+        .with_text_alignment(TextAlignment::BOTTOM_CENTER))
+        .insert(Themed::Text { primary: 1 });
+    });
+}
+
+fn goal_replay_update(mut commands: Commands,
+                      mut state: ResMut<State<AppState>>,
+                      mut goal_replay: ResMut<GoalReplay>,
+                      time: Res<Time>,
+                      keyboard_input: Res<Input<KeyCode>>,
+                      gamepads: Res<Gamepads>,
+                      gamepad_buttons: Res<Input<GamepadButton>>,
+                      mut transform_query: Query<&mut Transform>) {
+    let skip = keyboard_input.any_just_pressed([KeyCode::Space, KeyCode::Escape, KeyCode::Return])
+        || gamepads.iter().any(|gamepad| {
+            [GamepadButtonType::South, GamepadButtonType::East, GamepadButtonType::Start].into_iter()
+                .any(|button_type| gamepad_buttons.just_pressed(GamepadButton::new(*gamepad, button_type)))
+        });
+    goal_replay.time += time.delta_seconds() * SLOW_MOTION;
+    let over = skip || goal_replay.time >= goal_replay.duration();
+    let transforms = if over { goal_replay.goal.clone() } else { goal_replay.transforms() };
+    for (entity, transform) in transforms {
+        if let Ok(mut current) = transform_query.get_mut(entity) {
+            *current = transform;
+        }
+    }
+    if over {
+        commands.remove_resource::<GoalReplay>();
+        state.pop().unwrap();
+    }
+}
+
+fn goal_replay_exit(mut commands: Commands,
+                    goal_replay_text_query: Query<Entity, With<GoalReplayText>>,
+                    mut goal_text_query: Query<&mut Style, With<GoalText>>) {
+    for e in goal_replay_text_query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+    for mut style in goal_text_query.iter_mut() {
+        style.display = Display::Flex;
+    }
+}
@@ -0,0 +1,154 @@
+use bevy::prelude::*;
+use crate::locale::Locale;
+use crate::stats::MatchTracker;
+use crate::theme::{ActiveTheme, Themed};
+use crate::types::*;
+
+const SERVE_MARKER: &str = "●";
+
+/// Top bar with both players and the state of the match
+#[derive(Component)]
+struct Hud;
+
+#[derive(Component)]
+struct PlayerName(Player);
+
+/// Clock, sets and rally in the middle of the top bar
+#[derive(Component)]
+struct MatchInfo;
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_startup_system(setup_hud)
+            .add_system(show_hud)
+            .add_system(update_scores)
+            .add_system(update_names)
+            .add_system(update_match_info);
+    }
+}
+
+fn setup_hud(mut commands: Commands, theme: ActiveTheme, names: Res<PlayerNames>) {
+    let column = |align_items| NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items,
+            size: Size::new(Val::Percent(30.0), Val::Auto),
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    };
+
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect { top: Val::Px(0.0), left: Val::Px(0.0), ..Default::default() },
+            size: Size::new(Val::Percent(100.0), Val::Auto),
+            justify_content: JustifyContent::SpaceBetween,
+            align_items: AlignItems::FlexStart,
+            padding: UiRect::all(Val::Percent(1.0)),
+            display: Display::None,
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    })
+    .insert(Hud)
+    .with_children(|parent| {
+        for (player, align_items) in [(Player::Left, AlignItems::FlexStart), (Player::Right, AlignItems::FlexEnd)] {
+            parent.spawn_bundle(column(align_items)).with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(names.get(player), TextStyle {
+                    color: theme.get().paddle(player),
+                    ..theme.text_style(30.0)
+                }))
+                .insert(PlayerName(player))
+                .insert(Themed::PlayerText(player));
+                parent.spawn_bundle(TextBundle::from_section("0", theme.text_style(60.0)))
+                    .insert(ScoreText(player))
+                    .insert(Themed::Text { primary: 1 });
+            });
+            if player == Player::Left {
+                parent.spawn_bundle(column(AlignItems::Center)).with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_sections([
+                        TextSection::new("", theme.text_style(40.0)),
+                        TextSection::new("", theme.secondary_text_style(25.0)),
+                        TextSection::new("", theme.secondary_text_style(25.0)),
+                    ]).with_text_alignment(TextAlignment::TOP_CENTER))
+                    .insert(MatchInfo)
+                    .insert(Themed::Text { primary: 1 });
+                });
+            }
+        }
+    });
+}
+
+/// Shows the HUD during matches only
+fn show_hud(state: Res<State<AppState>>, mut hud_query: Query<&mut Style, With<Hud>>) {
+    if !state.is_changed() {
+        return;
+    }
+    let in_match = !matches!(state.current(), AppState::Idle | AppState::Title | AppState::Lobby | AppState::Records | AppState::LanGames | AppState::Replays | AppState::NewGame | AppState::Continue);
+    for mut style in hud_query.iter_mut() {
+        style.display = if in_match { Display::Flex } else { Display::None };
+    }
+}
+
+fn update_scores(score: Res<Score>, mut score_text_query: Query<(&ScoreText, &mut Text)>) {
+    if !score.is_changed() {
+        return;
+    }
+    for (ScoreText(player), mut text) in score_text_query.iter_mut() {
+        let goals = score.goals(*player).to_string();
+        if text.sections[0].value != goals {
+            text.sections[0].value = goals;
+        }
+    }
+}
+
+fn update_names(names: Res<PlayerNames>, serve: Res<Serve>, mut name_query: Query<(&PlayerName, &mut Text)>) {
+    if !names.is_changed() && !serve.is_changed() {
+        return;
+    }
+    for (PlayerName(player), mut text) in name_query.iter_mut() {
+        let name = names.get(*player);
+        text.sections[0].value = match (*player == serve.0, player) {
+            (false, _) => name.to_string(),
+            (true, Player::Left) => format!("{} {}", SERVE_MARKER, name),
+            (true, Player::Right) => format!("{} {}", name, SERVE_MARKER),
+        };
+    }
+}
+
+fn format_clock(seconds: f32) -> String {
+    let seconds = seconds.ceil().max(0.0) as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn update_match_info(score: Res<Score>,
+                     rules: Res<MatchRules>,
+                     tracker: Res<MatchTracker>,
+                     locale: Res<Locale>,
+                     mut info_query: Query<&mut Text, With<MatchInfo>>) {
+    if !score.is_changed() && !rules.is_changed() && !tracker.is_changed() {
+        return;
+    }
+    let clock = rules.time_limit.map_or(String::new(), |limit| format_clock(limit - score.time));
+    let sets = if rules.has_sets() {
+        format!("\n{}", locale.format("hud-sets", &[("left", &score.left_sets.to_string()),
+                                                  ("right", &score.right_sets.to_string())]))
+    } else {
+        String::new()
+    };
+    let rally = format!("\n{}", locale.format("hud-rally", &[("count", &tracker.rally.to_string())]));
+    for mut text in info_query.iter_mut() {
+        // Only touch the text when it changes, changed text is laid out again
+        for (section, value) in text.sections.iter_mut().zip([&clock, &sets, &rally]) {
+            if section.value != *value {
+                section.value = value.clone();
+            }
+        }
+    }
+}
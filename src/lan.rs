@@ -0,0 +1,479 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    time::Duration
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::config::Settings;
+use crate::locale::Locale;
+use crate::net::{NetSession, NetStatus, MAX_PACKET_SIZE, PROTOCOL_VERSION};
+use crate::theme::{ActiveTheme, Themed};
+use crate::types::*;
+
+/// Where hosts announce their games and the LAN games screen listens
+pub const DISCOVERY_PORT: u16 = 7778;
+/// Tells announcements apart from anything else sent to the discovery port
+const ANNOUNCE_GAME: &str = "bevy-pong";
+/// Bump whenever `Announcement` changes
+const ANNOUNCE_VERSION: u32 = 1;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+/// A game not heard from for this long is taken off the list
+const STALE_AFTER: Duration = Duration::from_secs(4);
+
+/// Sent by a host or server every `ANNOUNCE_INTERVAL` to everyone on the local network
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Announcement {
+    pub game: String,
+    pub version: u32,
+    /// Sends of the same game share this, it may arrive both over the network and over loopback
+    pub id: u64,
+    /// Network protocol of the host, it can only be joined with the same one
+    pub protocol: u32,
+    /// The host player's or the server's name
+    pub name: String,
+    /// Port of the game on the sender
+    pub port: u16,
+    pub rules: MatchRules,
+    pub players: u32,
+    /// No limit for a server
+    pub capacity: Option<u32>,
+}
+
+impl Announcement {
+    pub fn new(name: String, port: u16, rules: MatchRules, capacity: Option<u32>) -> Announcement {
+        Announcement {
+            game: ANNOUNCE_GAME.to_string(),
+            version: ANNOUNCE_VERSION,
+            id: RandomState::new().build_hasher().finish(),
+            protocol: PROTOCOL_VERSION,
+            name,
+            port,
+            rules,
+            players: 0,
+            capacity,
+        }
+    }
+}
+
+/// Only what every version of the announcement has, read first so that other changes cannot fail the check
+#[derive(Deserialize)]
+struct AnnounceHeader {
+    game: String,
+    version: u32,
+}
+
+/// Announces a game until dropped
+pub struct Announcer {
+    socket: UdpSocket,
+    announcement: Announcement,
+    /// Port the announcements are sent to, `DISCOVERY_PORT` except in tests
+    to_port: u16,
+    last_sent: Option<Duration>,
+}
+
+impl Announcer {
+    pub fn new(announcement: Announcement, to_port: u16) -> io::Result<Announcer> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(Announcer { socket, announcement, to_port, last_sent: None })
+    }
+
+    pub fn set_players(&mut self, players: u32) {
+        if self.announcement.players != players {
+            self.announcement.players = players;
+            // Others hear about the change right away
+            self.last_sent = None;
+        }
+    }
+
+    /// Sends the announcement when it is due.
+    /// `now` is any clock that keeps running, such as the time since startup.
+    pub fn poll(&mut self, now: Duration) {
+        if self.last_sent.is_some_and(|last_sent| now.saturating_sub(last_sent) < ANNOUNCE_INTERVAL) {
+            return;
+        }
+        self.last_sent = Some(now);
+        let packet = match ron::to_string(&self.announcement) {
+            Ok(packet) => packet,
+            Err(e) => {
+                warn!("Could not announce the game: {}", e);
+                return;
+            }
+        };
+        // Broadcasts do not come back to this machine everywhere, so it is told separately
+        for address in [Ipv4Addr::BROADCAST, Ipv4Addr::LOCALHOST] {
+            if let Err(e) = self.socket.send_to(packet.as_bytes(), (address, self.to_port)) {
+                debug!("Could not announce the game to {}: {}", address, e);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanGame {
+    /// Where to connect
+    pub address: SocketAddr,
+    /// `None` when the sender runs an incompatible version of the game, `address` is then only the sender
+    pub announcement: Option<Announcement>,
+    last_seen: Duration,
+}
+
+impl LanGame {
+    pub fn compatible(&self) -> bool {
+        self.announcement.as_ref().is_some_and(|announcement| announcement.protocol == PROTOCOL_VERSION)
+    }
+
+    pub fn full(&self) -> bool {
+        self.announcement.as_ref()
+            .is_some_and(|announcement| announcement.capacity.is_some_and(|capacity| announcement.players >= capacity))
+    }
+
+    /// Sends of the same game, or of the same incompatible sender
+    fn same(&self, other: &LanGame) -> bool {
+        match (&self.announcement, &other.announcement) {
+            (Some(announcement), Some(other)) => announcement.id == other.id,
+            (None, None) => self.address.ip() == other.address.ip(),
+            _ => false,
+        }
+    }
+}
+
+/// Collects the games announced on the local network
+pub struct LanBrowser {
+    socket: UdpSocket,
+    games: Vec<LanGame>,
+}
+
+impl LanBrowser {
+    pub fn bind(port: u16) -> io::Result<LanBrowser> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+        socket.set_nonblocking(true)?;
+        Ok(LanBrowser { socket, games: Vec::new() })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Games heard from lately, in the order they were first heard
+    pub fn games(&self) -> &[LanGame] {
+        &self.games
+    }
+
+    /// Receives what has arrived and forgets the games that stopped announcing.
+    /// `now` is any clock that keeps running, such as the time since startup.
+    pub fn poll(&mut self, now: Duration) {
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((size, from)) => {
+                    if let Some(game) = read_announcement(&buffer[..size], from, now) {
+                        // The latest announcement of a game has its current players and rules
+                        match self.games.iter_mut().find(|known| known.same(&game)) {
+                            Some(known) => *known = game,
+                            None => self.games.push(game),
+                        }
+                    }
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if matches!(e.kind(), io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionRefused) => (),
+                Err(e) => {
+                    warn!("Could not receive from the network: {}", e);
+                    break;
+                }
+            }
+        }
+        self.games.retain(|game| now.saturating_sub(game.last_seen) <= STALE_AFTER);
+    }
+}
+
+fn read_announcement(bytes: &[u8], from: SocketAddr, now: Duration) -> Option<LanGame> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    let header: AnnounceHeader = ron::from_str(text).ok()?;
+    if header.game != ANNOUNCE_GAME {
+        return None;
+    }
+    let announcement = match header.version {
+        ANNOUNCE_VERSION => Some(ron::from_str::<Announcement>(text).ok()?),
+        _ => None,
+    };
+    let address = match &announcement {
+        Some(announcement) => SocketAddr::new(from.ip(), announcement.port),
+        None => from,
+    };
+    Some(LanGame { address, announcement, last_seen: now })
+}
+
+/// Announces the game of a host while it waits for a player and plays
+pub struct AnnouncePlugin;
+
+impl Plugin for AnnouncePlugin {
+    fn build(&self, app: &mut App) {
+        let root = RootState::of(app);
+        app
+            .add_system(announce)
+            .add_system_set(SystemSet::on_enter(root).with_system(stop_announcing));
+    }
+}
+
+fn announce(announcer: Option<ResMut<Announcer>>, session: Option<Res<NetSession>>, time: Res<Time>) {
+    let mut announcer = match announcer {
+        Some(announcer) => announcer,
+        None => return,
+    };
+    if let Some(session) = session {
+        announcer.set_players(if session.status() == NetStatus::Connected { 2 } else { 1 });
+    }
+    announcer.poll(time.time_since_startup());
+}
+
+/// The match is over or was never played
+fn stop_announcing(mut commands: Commands) {
+    commands.remove_resource::<Announcer>();
+}
+
+/// The "Join LAN game" screen
+pub struct LanPlugin;
+
+impl Plugin for LanPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system_set(SystemSet::on_enter(AppState::LanGames).with_system(lan_enter))
+            .add_system_set(SystemSet::on_update(AppState::LanGames).with_system(lan_update))
+            .add_system_set(SystemSet::on_exit(AppState::LanGames).with_system(lan_exit));
+    }
+}
+
+fn rules_text(rules: &MatchRules, locale: &Locale) -> String {
+    let mut text = locale.format("lan-goals", &[("goals", &rules.goals_to_win.to_string())]);
+    if rules.has_sets() {
+        text += &format!(", {}", locale.format("lan-sets", &[("sets", &rules.sets_to_win.to_string())]));
+    }
+    if let Some(limit) = rules.time_limit {
+        text += &format!(", {}", locale.format("lan-time", &[("minutes", &format!("{:.0}", limit / 60.0))]));
+    }
+    text
+}
+
+fn game_text(game: &LanGame, locale: &Locale) -> String {
+    let announcement = match &game.announcement {
+        Some(announcement) if game.compatible() => announcement,
+        _ => return locale.format("lan-incompatible", &[("address", &game.address.ip().to_string())]),
+    };
+    let players = match announcement.capacity {
+        Some(capacity) => locale.format("lan-players", &[("players", &announcement.players.to_string()),
+                                                         ("capacity", &capacity.to_string())]),
+        None => locale.format("lan-players-unlimited", &[("players", &announcement.players.to_string())]),
+    };
+    format!("{}  {}  {}  {}", announcement.name, game.address, rules_text(&announcement.rules, locale), players)
+}
+
+/// The browser of the LAN games screen, or why there is none
+struct LanSearch(Result<LanBrowser, String>);
+
+fn list_text(search: &LanSearch, selected: usize, locale: &Locale) -> String {
+    let games = match &search.0 {
+        Ok(browser) => browser.games(),
+        Err(e) => return locale.format("lan-cannot-listen", &[("error", e)]),
+    };
+    if games.is_empty() {
+        return locale.get("lan-searching");
+    }
+    games.iter()
+        .enumerate()
+        .map(|(index, game)| format!("{} {}", if index == selected { ">" } else { " " }, game_text(game, locale)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Component)]
+struct LanListText;
+
+fn lan_enter(mut commands: Commands, theme: ActiveTheme, locale: Res<Locale>) {
+    let search = LanSearch(LanBrowser::bind(DISCOVERY_PORT).map_err(|e| e.to_string()));
+    if let Err(e) = &search.0 {
+        warn!("Cannot look for LAN games: {}", e);
+    }
+    let list = list_text(&search, 0, &locale);
+    commands.insert_resource(search);
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        },
+        color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+        ..Default::default()
+    })
+    .insert(LanText)
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: format!("{}\n", locale.get("lan")),
+                        style: theme.text_style(70.0)
+                    },
+                    TextSection {
+                        value: list,
+                        style: theme.text_style(30.0)
+                    },
+                    TextSection {
+                        value: format!("\n\n{}\n{}", locale.get("lan-join"), locale.get("lan-back")),
+                        style: theme.secondary_text_style(30.0)
+                    }
+                ],
+                alignment: TextAlignment::TOP_CENTER
+            },
+            ..Default::default()
+        })
+        .insert(LanListText)
+        .insert(Themed::Text { primary: 2 });
+    });
+}
+
+fn lan_update(mut commands: Commands,
+              mut state: ResMut<State<AppState>>,
+              mut search: ResMut<LanSearch>,
+              mut selected: Local<usize>,
+              time: Res<Time>,
+              locale: Res<Locale>,
+              settings: Res<Settings>,
+              keyboard_input: Res<Input<KeyCode>>,
+              gamepads: Res<Gamepads>,
+              gamepad_buttons: Res<Input<GamepadButton>>,
+              mut list_query: Query<&mut Text, With<LanListText>>) {
+    if let Ok(browser) = &mut search.0 {
+        browser.poll(time.time_since_startup());
+    }
+    let games = search.0.as_ref().map_or(&[][..], LanBrowser::games);
+
+    let mut up = keyboard_input.just_pressed(KeyCode::Up);
+    let mut down = keyboard_input.just_pressed(KeyCode::Down);
+    let mut join = keyboard_input.just_released(KeyCode::Space);
+    let mut watch = keyboard_input.just_released(KeyCode::W);
+    let mut back = keyboard_input.just_released(KeyCode::Escape);
+    for gamepad in gamepads.iter() {
+        let button = |button_type| GamepadButton::new(*gamepad, button_type);
+        up |= gamepad_buttons.just_pressed(button(GamepadButtonType::DPadUp));
+        down |= gamepad_buttons.just_pressed(button(GamepadButtonType::DPadDown));
+        join |= gamepad_buttons.just_released(button(GamepadButtonType::South));
+        watch |= gamepad_buttons.just_released(button(GamepadButtonType::North));
+        back |= gamepad_buttons.just_released(button(GamepadButtonType::East));
+    }
+    if up {
+        *selected = selected.saturating_sub(1);
+    }
+    if down {
+        *selected += 1;
+    }
+    *selected = (*selected).min(games.len().saturating_sub(1));
+
+    if back {
+        state.set(AppState::Title).unwrap();
+        return;
+    }
+    // A full game may still have room for spectators, the host tells
+    let game = games.get(*selected).filter(|game| game.compatible() && (watch || (join && !game.full())));
+    if let Some(game) = game {
+        let address = game.address.to_string();
+        let name = settings.players.right.clone();
+        let session = if watch { NetSession::spectate(&address, name) } else { NetSession::connect(&address, name) };
+        match session {
+            Ok(session) => {
+                commands.insert_resource(session);
+                state.set(AppState::Lobby).unwrap();
+                return;
+            },
+            Err(e) => warn!("Could not join the game at {}: {}", game.address, e),
+        }
+    }
+    for mut text in list_query.iter_mut() {
+        text.sections[1].value = list_text(&search, *selected, &locale);
+    }
+}
+
+fn lan_exit(mut commands: Commands, lan_text_query: Query<Entity, With<LanText>>) {
+    // Frees the discovery port
+    commands.remove_resource::<LanSearch>();
+    for e in lan_text_query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn announcement(name: &str) -> Announcement {
+        Announcement::new(name.to_string(), 7777, MatchRules::default(), Some(2))
+    }
+
+    /// Gives packets on loopback a moment to arrive
+    fn settle() {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    #[test]
+    fn announced_games_are_listed_and_expire() {
+        let mut browser = LanBrowser::bind(0).unwrap();
+        let port = browser.local_addr().unwrap().port();
+        let mut alice = Announcer::new(announcement("Alice"), port).unwrap();
+        let mut bob = Announcer::new(announcement("Bob"), port).unwrap();
+        alice.poll(Duration::ZERO);
+        bob.poll(Duration::ZERO);
+        settle();
+        browser.poll(Duration::ZERO);
+        let names: Vec<_> = browser.games().iter().filter_map(|game| game.announcement.as_ref().map(|a| a.name.as_str())).collect();
+        assert_eq!(names, ["Alice", "Bob"]);
+        assert_eq!(browser.games()[0].address.port(), 7777);
+        assert!(browser.games().iter().all(|game| game.compatible() && !game.full()));
+
+        // Not sent again before it is due, unless something changed
+        alice.poll(ANNOUNCE_INTERVAL / 2);
+        alice.set_players(2);
+        alice.poll(ANNOUNCE_INTERVAL / 2);
+        settle();
+        browser.poll(ANNOUNCE_INTERVAL / 2);
+        assert_eq!(browser.games().len(), 2);
+        let players: Vec<_> = browser.games().iter().filter_map(|game| game.announcement.as_ref().map(|a| a.players)).collect();
+        assert_eq!(players, [2, 0]);
+
+        // Only Alice keeps announcing
+        let later = STALE_AFTER + ANNOUNCE_INTERVAL;
+        alice.poll(later);
+        settle();
+        browser.poll(later);
+        let names: Vec<_> = browser.games().iter().filter_map(|game| game.announcement.as_ref().map(|a| a.name.as_str())).collect();
+        assert_eq!(names, ["Alice"]);
+    }
+
+    #[test]
+    fn other_versions_are_listed_as_incompatible() {
+        let mut browser = LanBrowser::bind(0).unwrap();
+        let address = browser.local_addr().unwrap();
+        let sender = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let to = (Ipv4Addr::LOCALHOST, address.port());
+
+        let newer_protocol = Announcement { protocol: PROTOCOL_VERSION + 1, ..announcement("Newer") };
+        sender.send_to(ron::to_string(&newer_protocol).unwrap().as_bytes(), to).unwrap();
+        // A later announcement format that this version cannot read beyond the header
+        sender.send_to(b"(game: \"bevy-pong\", version: 99, players: [\"Someone\"])", to).unwrap();
+        sender.send_to(b"(game: \"another-game\", version: 1)", to).unwrap();
+        sender.send_to(b"not an announcement", to).unwrap();
+        settle();
+        browser.poll(Duration::ZERO);
+
+        let games = browser.games();
+        assert_eq!(games.len(), 2);
+        assert!(games.iter().all(|game| !game.compatible()));
+        assert_eq!(games[1].announcement, None);
+        assert_eq!(games[1].address, sender.local_addr().unwrap());
+    }
+}
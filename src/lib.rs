@@ -0,0 +1,38 @@
+//! Pong with Bevy, shared by the game and the `pong-server` binary. `PongPlugin` adds the whole
+//! game to an app.
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+
+pub mod consts;
+pub mod types;
+pub mod config;
+pub mod locale;
+pub mod accessibility;
+pub mod setup;
+pub mod theme;
+pub mod scaling;
+pub mod camera;
+pub mod synth;
+#[cfg(feature = "audio")]
+pub mod sound;
+pub mod visuals;
+pub mod particles;
+pub mod trail;
+pub mod hud;
+pub mod title;
+pub mod stats;
+pub mod save;
+pub mod pause;
+pub mod mixer;
+pub mod records;
+pub mod net;
+pub mod lan;
+pub mod bot;
+pub mod replay;
+pub mod goal_replay;
+pub mod rollback;
+pub mod game;
+pub mod server;
+pub mod plugin;
+
+pub use plugin::PongPlugin;
+pub use types::{AppState, Ball, Controller, Controllers, MatchRules, Paddle, Player, PongEvent, RootState, Score};
@@ -0,0 +1,78 @@
+use std::{collections::{HashMap, HashSet}, sync::Mutex};
+use bevy::prelude::*;
+
+pub const FALLBACK_LANGUAGE: &str = "en";
+
+/// Built into the executable so that text is available before any asset has loaded
+const LOCALES: &[(&str, &str)] = &[
+    ("en", include_str!("../assets/locales/en.ron")),
+    ("fi", include_str!("../assets/locales/fi.ron")),
+];
+
+type StringTable = HashMap<String, String>;
+
+fn load_table(language: &str) -> Option<StringTable> {
+    let (_, source) = LOCALES.iter().find(|(name, _)| *name == language)?;
+    match ron::from_str(source) {
+        Ok(table) => Some(table),
+        Err(e) => {
+            error!("Invalid locale file for '{}': {}", language, e);
+            None
+        }
+    }
+}
+
+/// Language part of a locale name such as `fi_FI.UTF-8` or `en-US`
+fn language_of(locale: &str) -> String {
+    locale.split(['-', '_', '.']).next().unwrap_or_default().to_lowercase()
+}
+
+/// UI text in the chosen language
+pub struct Locale {
+    language: String,
+    strings: StringTable,
+    fallback: StringTable,
+    warned: Mutex<HashSet<String>>,
+}
+
+impl Locale {
+    /// Uses the requested locale, or the system locale if none is requested,
+    /// falling back to English if there is no translation for it.
+    pub fn new(requested: Option<&str>) -> Locale {
+        let fallback = load_table(FALLBACK_LANGUAGE).unwrap_or_default();
+        let requested = requested.map(str::to_string).or_else(sys_locale::get_locale);
+        let (language, strings) = requested.as_deref()
+            .map(language_of)
+            .and_then(|language| load_table(&language).map(|strings| (language, strings)))
+            .unwrap_or_else(|| {
+                if let Some(requested) = &requested {
+                    info!("No translation for locale '{}', using '{}'", requested, FALLBACK_LANGUAGE);
+                }
+                (FALLBACK_LANGUAGE.to_string(), fallback.clone())
+            });
+        Locale { language, strings, fallback, warned: Mutex::default() }
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// The text for `key`. Missing keys are logged once and fall back to English, or to the key itself.
+    pub fn get(&self, key: &str) -> String {
+        if let Some(text) = self.strings.get(key) {
+            return text.clone();
+        }
+        let mut warned = self.warned.lock().unwrap();
+        if warned.insert(key.to_string()) {
+            warn!("Missing text '{}' for locale '{}'", key, self.language);
+        }
+        self.fallback.get(key).cloned().unwrap_or_else(|| key.to_string())
+    }
+
+    /// The text for `key` with each `{name}` replaced by its value from `args`
+    pub fn format(&self, key: &str, args: &[(&str, &str)]) -> String {
+        args.iter().fold(self.get(key), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+    }
+}
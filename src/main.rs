@@ -1,24 +1,150 @@
-use bevy::prelude::*;
+use std::{collections::hash_map::RandomState, hash::{BuildHasher, Hasher}, process, time::Duration};
+use bevy::{
+    prelude::*,
+    app::{AppExit, ScheduleRunnerSettings},
+    input::InputPlugin,
+    log::LogPlugin,
+    window::{PresentMode, WindowMode}
+};
 
-mod consts;
-mod types;
-mod setup;
-mod title;
-mod game;
+mod cli;
 
-use types::*;
-use setup::setup;
-use title::TitlePlugin;
-use game::GamePlugin;
+use bevy_pong::{
+    types::*,
+    config::Settings,
+    net::{MatchSetup, NetSession},
+    bot::{BotServer, DEFAULT_BOT_PORT},
+    lan::{Announcement, Announcer, DISCOVERY_PORT},
+    synth,
+    PongPlugin
+};
+use cli::{Args, StartMode};
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .add_event::<PongEvent>()
-        .insert_resource(Score { left: 0, right: 0 })
-        .add_startup_system(setup)
-        .add_plugin(TitlePlugin)
-        .add_plugin(GamePlugin)
-        .add_state(AppState::Title)
-        .run();
+    let args = Args::parse_checked();
+    if let Some(dir) = &args.export_sounds {
+        if let Err(e) = synth::export(dir) {
+            eprintln!("error: could not write sounds to {}: {}", dir.display(), e);
+            process::exit(1);
+        }
+        return;
+    }
+    let mut settings = match Settings::load(args.config.as_deref()) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    };
+    args.apply(&mut settings);
+
+    // No seed given, pick an arbitrary one
+    let seed = args.seed.unwrap_or_else(|| RandomState::new().build_hasher().finish());
+    let mut announcer = None;
+    let session = if let Some(port) = args.host {
+        let announcement = Announcement::new(settings.players.left.clone(), port, settings.rules.clone(), Some(2));
+        announcer = Announcer::new(announcement, DISCOVERY_PORT)
+            .map_err(|e| eprintln!("warning: cannot announce the match on the local network: {}", e))
+            .ok();
+        let setup = MatchSetup {
+            seed,
+            rules: settings.rules.clone(),
+            names: settings.players.clone(),
+            game_speed: settings.accessibility.game_speed,
+            netcode: args.netcode,
+            input_delay: args.input_delay.unwrap_or_else(|| args.netcode.default_input_delay()),
+        };
+        Some(NetSession::host(port, setup).map(|mut session| {
+            session.set_max_spectators(args.max_spectators);
+            session
+        }))
+    } else if let Some(address) = &args.spectate {
+        Some(NetSession::spectate(address, settings.players.right.clone()))
+    } else {
+        args.connect.as_deref().map(|address| NetSession::connect(address, settings.players.right.clone()))
+    };
+    let session = match session.transpose() {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("error: could not start the online match: {}", e);
+            process::exit(2);
+        }
+    };
+    let uses_bots = [settings.controllers.left, settings.controllers.right].contains(&Controller::Bot);
+    let bot_port = args.bot_port.or(uses_bots.then_some(DEFAULT_BOT_PORT));
+    let bots = bot_port.map(|port| BotServer::bind(port).unwrap_or_else(|e| {
+        eprintln!("error: could not listen for bots on port {}: {}", port, e);
+        process::exit(2);
+    }));
+    let initial_state = match args.start() {
+        _ if session.is_some() => AppState::Lobby,
+        StartMode::Title => AppState::Title,
+        StartMode::Match => AppState::NewGame,
+    };
+
+    let mut app = App::new();
+    if args.headless {
+        app.insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(1.0 / 60.0)))
+            .add_plugins(MinimalPlugins)
+            .add_plugin(LogPlugin)
+            .add_plugin(TransformPlugin)
+            .add_plugin(HierarchyPlugin)
+            .add_plugin(InputPlugin)
+            .insert_resource(MaxTime(args.max_time as f32))
+            // There is nobody to press start on the title screen
+            .add_system_set(SystemSet::on_enter(AppState::Title).with_system(exit_headless))
+            .add_system(stop_headless);
+    } else {
+        app.insert_resource(WindowDescriptor {
+                title: "Pong".to_string(),
+                width: settings.window.width,
+                height: settings.window.height,
+                mode: if settings.window.fullscreen { WindowMode::BorderlessFullscreen } else { WindowMode::Windowed },
+                present_mode: if settings.window.vsync { PresentMode::AutoVsync } else { PresentMode::AutoNoVsync },
+                ..Default::default()
+            })
+            .add_plugins(DefaultPlugins);
+    }
+    if let Some(session) = session {
+        app.insert_resource(session);
+    }
+    if let Some(announcer) = announcer {
+        app.insert_resource(announcer);
+    }
+    if let Some(bots) = bots {
+        if let Ok(address) = bots.local_addr() {
+            info!("Listening for bots on port {}", address.port());
+        }
+        app.insert_resource(bots);
+    }
+
+    let mut pong = PongPlugin::new()
+        .settings(settings)
+        .seed(seed)
+        .start_state(initial_state);
+    if args.headless {
+        pong = pong.headless();
+    }
+    if let Some(path) = args.stats_file {
+        pong = pong.stats_file(path);
+    }
+    if let Some(path) = args.config {
+        pong = pong.config(path);
+    }
+    app.add_plugin(pong).run();
+}
+
+/// Seconds of play before a headless match is stopped
+struct MaxTime(f32);
+
+fn stop_headless(score: Res<Score>, max_time: Res<MaxTime>, mut exit_events: EventWriter<AppExit>) {
+    if score.time >= max_time.0 {
+        info!("Match stopped after {} seconds at {} - {}", max_time.0, score.left, score.right);
+        exit_events.send(AppExit);
+    }
+}
+
+fn exit_headless(score: Res<Score>, mut exit_events: EventWriter<AppExit>) {
+    info!("Match over {} - {}", score.left, score.right);
+    exit_events.send(AppExit);
 }
@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+use bevy::prelude::*;
+use crate::config::{AudioSettings, Settings};
+use crate::locale::Locale;
+use crate::theme::{ActiveTheme, Themed};
+use crate::types::*;
+
+const VOLUME_STEP: f32 = 0.1;
+
+/// Where the mixer levels are saved
+struct SettingsPath(Option<PathBuf>);
+
+#[derive(Component)]
+struct MixerText;
+
+/// Volume controls: F6 mutes anywhere, the pause screen has the levels
+pub struct MixerPlugin {
+    pub settings: AudioSettings,
+    /// Settings file given on the command line, the default one if `None`
+    pub config: Option<PathBuf>,
+}
+
+impl Plugin for MixerPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(self.settings)
+            .insert_resource(SettingsPath(self.config.clone()))
+            .add_system(mute_input)
+            .add_system(save_mixer.after(mute_input))
+            .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(mixer_enter))
+            .add_system_set(SystemSet::on_update(AppState::Paused)
+                .with_system(mixer_input)
+                .with_system(mixer_text.after(mixer_input)));
+    }
+}
+
+fn mute_input(mut mixer: ResMut<AudioSettings>, keyboard_input: Res<Input<KeyCode>>) {
+    if keyboard_input.just_pressed(KeyCode::F6) {
+        mixer.muted = !mixer.muted;
+        info!("Sound: {}", if mixer.muted { "muted" } else { "on" });
+    }
+}
+
+/// Keeps the levels in the settings file whenever they change
+fn save_mixer(mixer: Res<AudioSettings>, path: Res<SettingsPath>) {
+    if !mixer.is_changed() || mixer.is_added() {
+        return;
+    }
+    if let Err(e) = Settings::update(path.0.as_deref(), |settings| settings.audio = *mixer) {
+        warn!("Could not save the volume levels: {}", e);
+    }
+}
+
+fn mixer_enter(mut commands: Commands, theme: ActiveTheme) {
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect { bottom: Val::Percent(5.0), left: Val::Px(0.0), ..Default::default() },
+            size: Size::new(Val::Percent(100.0), Val::Auto),
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    })
+    // Removed with the rest of the pause screen
+    .insert(PauseText)
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle::from_sections([
+            TextSection::new("", theme.text_style(30.0)),
+            TextSection::new("", theme.secondary_text_style(25.0)),
+        ]).with_text_alignment(TextAlignment::TOP_CENTER))
+        .insert(MixerText)
+        .insert(Themed::Text { primary: 1 });
+    });
+}
+
+fn mixer_input(mut mixer: ResMut<AudioSettings>, keyboard_input: Res<Input<KeyCode>>) {
+    let keys = [
+        (KeyCode::Key1, KeyCode::Key2),
+        (KeyCode::Key3, KeyCode::Key4),
+        (KeyCode::Key5, KeyCode::Key6),
+    ];
+    for (index, (down, up)) in keys.into_iter().enumerate() {
+        let change = match (keyboard_input.just_pressed(down), keyboard_input.just_pressed(up)) {
+            (true, false) => -VOLUME_STEP,
+            (false, true) => VOLUME_STEP,
+            _ => continue,
+        };
+        let level = match index {
+            0 => &mut mixer.master,
+            1 => &mut mixer.music,
+            _ => &mut mixer.effects,
+        };
+        // Rounded so that repeated steps land on whole percentages
+        *level = ((*level + change).clamp(0.0, 1.0) * 10.0).round() / 10.0;
+    }
+    if keyboard_input.just_pressed(KeyCode::Key0) {
+        mixer.muted = !mixer.muted;
+    }
+}
+
+fn mixer_text(mixer: Res<AudioSettings>,
+              locale: Res<Locale>,
+              mut text_query: Query<(&mut Text, ChangeTrackers<MixerText>)>) {
+    let level = |value: f32| format!("{}%", (value * 100.0).round());
+    for (mut text, tracker) in text_query.iter_mut() {
+        if !mixer.is_changed() && !tracker.is_added() {
+            continue;
+        }
+        text.sections[0].value = if mixer.muted { locale.get("mixer-muted") } else { locale.get("mixer") };
+        text.sections[1].value = [
+            locale.format("mixer-master", &[("level", &level(mixer.master))]),
+            locale.format("mixer-music", &[("level", &level(mixer.music))]),
+            locale.format("mixer-effects", &[("level", &level(mixer.effects))]),
+            locale.get("mixer-mute"),
+        ].iter().map(|line| format!("\n{}", line)).collect();
+    }
+}
@@ -0,0 +1,1243 @@
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    time::Duration
+};
+use bevy::{
+    prelude::*,
+    app::AppExit
+};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use crate::accessibility::AccessibilitySettings;
+use crate::config::Settings;
+use crate::game::{frame_time, InputDevices};
+use crate::locale::Locale;
+use crate::rollback::Rollback;
+use crate::server::MatchSnapshot;
+use crate::theme::{ActiveTheme, Themed};
+use crate::types::*;
+
+/// Bump whenever `Message`, `MatchSetup`, `MatchSnapshot` or the simulation changes
+pub const PROTOCOL_VERSION: u32 = 7;
+/// Simulation ticks per second in online matches
+pub const TICK_RATE: u32 = 60;
+/// Ticks the simulation may run ahead of the other player's input, guessing it, with rollback
+const MAX_PREDICTION: u32 = 8;
+/// Ticks between the checksums compared to notice when the peers no longer play the same match
+const CHECKSUM_INTERVAL: u32 = 30;
+/// Checksums kept for comparing with the peer's, which may be this many intervals behind
+const CHECKSUMS_KEPT: usize = 16;
+/// The connection is given up after hearing nothing from the peer for this long
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+/// Ticks the match clock may fall behind and still catch up on in a single frame
+pub const MAX_CATCH_UP: u32 = 4;
+/// Inputs further ahead than this are not accepted from the peer
+const MAX_INPUTS_AHEAD: u32 = 10 * TICK_RATE;
+/// Sent several times as nobody waits for an answer
+pub const BYE_COPIES: usize = 3;
+pub const MAX_PACKET_SIZE: usize = 4096;
+/// Spectators a host or a server match takes unless told otherwise
+pub const DEFAULT_MAX_SPECTATORS: usize = 4;
+
+pub fn tick_duration() -> Duration {
+    Duration::from_secs(1) / TICK_RATE
+}
+
+/// How the peers cope with the time inputs take to arrive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum Netcode {
+    /// Wait for the other player's input, the input delay hides the wait
+    Lockstep,
+    /// Guess the other player's input and correct the game when it arrives
+    Rollback,
+    /// Played on a `pong-server`, which runs the game and sends what happens
+    #[value(skip)]
+    Server,
+}
+
+impl Netcode {
+    /// Ticks between reading a player's input and using it, time for the input to reach the other peer
+    pub fn default_input_delay(self) -> u32 {
+        match self {
+            Netcode::Lockstep => 3,
+            Netcode::Rollback => 1,
+            Netcode::Server => 0,
+        }
+    }
+
+    /// Ticks that may be played before the other player's input for them has arrived
+    fn window(self) -> u32 {
+        match self {
+            Netcode::Lockstep | Netcode::Server => 1,
+            Netcode::Rollback => MAX_PREDICTION,
+        }
+    }
+}
+
+/// Everything both peers need to play the same match, chosen by the host
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchSetup {
+    pub seed: u64,
+    pub rules: MatchRules,
+    pub names: PlayerNames,
+    pub game_speed: f32,
+    pub netcode: Netcode,
+    pub input_delay: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Message {
+    /// The client asking to join, repeated until the host answers
+    Hello { version: u32, name: String, spectator: bool },
+    /// The match is on, with the paddle the client plays, none for a spectator
+    Welcome { setup: MatchSetup, player: Option<Player> },
+    /// The answer while the match waits for a second player
+    Waiting,
+    /// The answer to a spectator when the match has no room for more
+    Full,
+    /// The answer to a `Hello` of another protocol version, `expected` is the one the host uses
+    WrongVersion { version: u32, expected: u32 },
+    /// A spectator telling it still watches, it has no inputs to send
+    Watching,
+    /// The sender's inputs from tick `start` on, everything the receiver has not acknowledged yet.
+    /// `ack` is the first tick of the receiver's inputs that the sender is still missing.
+    /// `checksum` is the sender's latest checksum of the game after a tick.
+    Inputs { ack: u32, start: u32, inputs: Vec<i8>, checksum: Option<(u32, u64)> },
+    /// A player's latest input to the server, a newer tick replaces it
+    Input { tick: u32, input: i8 },
+    /// The server's match after a tick
+    State(MatchSnapshot),
+    Bye,
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        ron::to_string(self).map(String::into_bytes).unwrap_or_default()
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Message> {
+        std::str::from_utf8(bytes).ok().and_then(|text| ron::from_str(text).ok())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// This side left
+    Left,
+    PeerLeft,
+    TimedOut,
+    /// The peers no longer play the same match
+    Desynced,
+    /// The server finished the match
+    MatchOver,
+    /// No room for another spectator
+    Full,
+    /// The host uses another network protocol, usually another version of the game
+    WrongVersion { local: u32, remote: u32 },
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CloseReason::Left => write!(f, "left the online match"),
+            CloseReason::PeerLeft => write!(f, "the other player left the online match"),
+            CloseReason::TimedOut => write!(f, "lost the connection to the other player"),
+            CloseReason::Desynced => write!(f, "the game went out of sync with the other player"),
+            CloseReason::MatchOver => write!(f, "the online match is over"),
+            CloseReason::Full => write!(f, "the online match has no room for more spectators"),
+            CloseReason::WrongVersion { local, remote } => write!(f, "the host uses network protocol {}, this game {}", remote, local),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetStatus {
+    /// The host waiting for a client, or the client waiting for the host to answer
+    Connecting,
+    Connected,
+    Closed(CloseReason),
+}
+
+enum Role {
+    Host,
+    Client { host: SocketAddr, name: String, spectator: bool },
+}
+
+struct Spectator {
+    address: SocketAddr,
+    last_heard: Duration,
+}
+
+/// One end of an online match. The host plays the left paddle and the client the right one,
+/// or the one a server gives it.
+///
+/// The local clock moves on with `step`, and `confirm` hands out the inputs of a tick once the
+/// input of both players is known. With rollback, the ticks in between are played with a guess
+/// of the other player's input from `predicted`. Every packet repeats all inputs the peer has
+/// not acknowledged, so lost packets are covered by the next one and reordered ones add nothing new.
+/// On a server only the latest input is sent and the game is shown from the server's snapshots.
+/// Spectators send no input at all, they are shown snapshots of the match by the host or server.
+pub struct NetSession {
+    socket: UdpSocket,
+    role: Role,
+    player: Player,
+    peer: Option<SocketAddr>,
+    setup: Option<MatchSetup>,
+    status: NetStatus,
+    /// When the peer was last heard from, or when the session started before that
+    last_heard: Option<Duration>,
+    /// First tick that is not confirmed, or that no snapshot has arrived for from a server
+    tick: u32,
+    /// First tick the local clock has not reached, the local input is known until `input_delay` ticks later
+    present: u32,
+    local: BTreeMap<u32, i8>,
+    /// First tick of the local inputs the peer has not acknowledged
+    acked: u32,
+    remote: BTreeMap<u32, i8>,
+    /// First tick of the remote inputs that has not arrived
+    remote_next: u32,
+    /// Remote input of the last confirmed tick, the guess for the ticks after it
+    last_remote: i8,
+    checksums: BTreeMap<u32, u64>,
+    remote_checksums: BTreeMap<u32, u64>,
+    /// Newest snapshot from the server that has not been shown
+    snapshot: Option<MatchSnapshot>,
+    /// State of the match in the newest snapshot
+    server_state: Option<AppState>,
+    /// Watching the host's match
+    spectators: Vec<Spectator>,
+    max_spectators: usize,
+}
+
+impl NetSession {
+    fn new(socket: UdpSocket, role: Role, setup: Option<MatchSetup>) -> io::Result<NetSession> {
+        socket.set_nonblocking(true)?;
+        let player = match role {
+            Role::Host => Player::Left,
+            Role::Client { .. } => Player::Right,
+        };
+        Ok(NetSession {
+            socket,
+            role,
+            player,
+            peer: None,
+            setup,
+            status: NetStatus::Connecting,
+            last_heard: None,
+            tick: 0,
+            present: 0,
+            local: BTreeMap::new(),
+            acked: 0,
+            remote: BTreeMap::new(),
+            remote_next: 0,
+            last_remote: 0,
+            checksums: BTreeMap::new(),
+            remote_checksums: BTreeMap::new(),
+            snapshot: None,
+            server_state: None,
+            spectators: Vec::new(),
+            max_spectators: DEFAULT_MAX_SPECTATORS,
+        })
+    }
+
+    /// Waits for a client on `port`. The client's name replaces the right player's name in `setup`.
+    pub fn host(port: u16, setup: MatchSetup) -> io::Result<NetSession> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+        NetSession::new(socket, Role::Host, Some(setup))
+    }
+
+    /// Joins the match hosted at `address`, e.g. `192.168.1.5:7777`, or a match on the server there
+    pub fn connect(address: &str, name: String) -> io::Result<NetSession> {
+        NetSession::join(address, name, false)
+    }
+
+    /// Watches the match hosted at `address`, or the newest match on the server there
+    pub fn spectate(address: &str, name: String) -> io::Result<NetSession> {
+        NetSession::join(address, name, true)
+    }
+
+    fn join(address: &str, name: String, spectator: bool) -> io::Result<NetSession> {
+        let host = address.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no address found for {}", address)))?;
+        let socket = match host {
+            SocketAddr::V4(_) => UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?,
+            SocketAddr::V6(_) => UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0))?,
+        };
+        NetSession::new(socket, Role::Client { host, name, spectator }, None)
+    }
+
+    /// Spectators the host takes at most, 0 for none
+    pub fn set_max_spectators(&mut self, max_spectators: usize) {
+        self.max_spectators = max_spectators;
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn local_player(&self) -> Player {
+        self.player
+    }
+
+    pub fn status(&self) -> NetStatus {
+        self.status
+    }
+
+    /// Known to the client once the host has answered
+    pub fn setup(&self) -> Option<&MatchSetup> {
+        self.setup.as_ref()
+    }
+
+    /// First tick that is not confirmed
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    fn input_delay(&self) -> u32 {
+        self.setup.as_ref().map_or(0, |setup| setup.input_delay)
+    }
+
+    pub fn netcode(&self) -> Netcode {
+        self.setup.as_ref().map_or(Netcode::Lockstep, |setup| setup.netcode)
+    }
+
+    pub fn spectating(&self) -> bool {
+        matches!(self.role, Role::Client { spectator: true, .. })
+    }
+
+    /// The match is shown as the host or server sends it instead of being simulated here
+    pub fn follows_snapshots(&self) -> bool {
+        self.netcode() == Netcode::Server || self.spectating()
+    }
+
+    pub fn has_spectators(&self) -> bool {
+        !self.spectators.is_empty()
+    }
+
+    /// Receives what has arrived, sends what the peer is missing and notices timeouts.
+    /// `now` is any clock that keeps running, such as the time since startup.
+    pub fn poll(&mut self, now: Duration) {
+        if matches!(self.status, NetStatus::Closed(_)) {
+            return;
+        }
+        let started = *self.last_heard.get_or_insert(now);
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((size, from)) => {
+                    if let Some(message) = Message::decode(&buffer[..size]) {
+                        self.receive(message, from, now);
+                    }
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                // Some systems report an earlier packet that nobody received, the timeout covers that
+                Err(e) if matches!(e.kind(), io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionRefused) => (),
+                Err(e) => {
+                    warn!("Could not receive from the network: {}", e);
+                    break;
+                }
+            }
+        }
+        if matches!(self.status, NetStatus::Closed(_)) {
+            return;
+        }
+
+        let quiet = |spectator: &Spectator| now.saturating_sub(spectator.last_heard) > TIMEOUT;
+        for spectator in self.spectators.iter().filter(|spectator| quiet(spectator)) {
+            info!("No answer from spectator {} in {} seconds", spectator.address, TIMEOUT.as_secs());
+        }
+        self.spectators.retain(|spectator| !quiet(spectator));
+
+        let waiting_for_client = matches!(self.role, Role::Host) && self.peer.is_none();
+        if !waiting_for_client && now.saturating_sub(self.last_heard.unwrap_or(started)) > TIMEOUT {
+            info!("No answer from the other player in {} seconds", TIMEOUT.as_secs());
+            self.status = NetStatus::Closed(CloseReason::TimedOut);
+            return;
+        }
+        match (&self.role, self.status) {
+            (Role::Client { host, name, spectator }, NetStatus::Connecting) => {
+                let hello = Message::Hello { version: PROTOCOL_VERSION, name: name.clone(), spectator: *spectator };
+                self.send(&hello, *host);
+            },
+            (Role::Client { host, spectator: true, .. }, NetStatus::Connected) => self.send(&Message::Watching, *host),
+            (_, NetStatus::Connected) if self.netcode() == Netcode::Server => {
+                if let (Some((tick, input)), Some(peer)) = (self.local.iter().next_back(), self.peer) {
+                    self.send(&Message::Input { tick: *tick, input: *input }, peer);
+                }
+            },
+            (_, NetStatus::Connected) => {
+                let inputs = Message::Inputs {
+                    ack: self.remote_next,
+                    start: self.acked,
+                    inputs: self.local.range(self.acked..).map(|(_, input)| *input).collect(),
+                    checksum: self.checksums.iter().next_back().map(|(tick, checksum)| (*tick, *checksum)),
+                };
+                if let Some(peer) = self.peer {
+                    self.send(&inputs, peer);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    fn send(&self, message: &Message, to: SocketAddr) {
+        if let Err(e) = self.socket.send_to(&message.encode(), to) {
+            // Lost like any other packet, sent again on the next poll
+            debug!("Could not send to {}: {}", to, e);
+        }
+    }
+
+    fn connected(&mut self, peer: SocketAddr) {
+        let delay = self.input_delay();
+        self.peer = Some(peer);
+        self.status = NetStatus::Connected;
+        // Nobody has any input before the delay has passed, those ticks are played without
+        self.acked = delay;
+        self.remote_next = delay;
+        info!("Connected to {}", peer);
+    }
+
+    fn receive(&mut self, message: Message, from: SocketAddr, now: Duration) {
+        let from_peer = self.peer == Some(from);
+        match (message, &self.role) {
+            (Message::Hello { version, .. }, Role::Host) if version != PROTOCOL_VERSION => {
+                warn!("{} uses network protocol {}, expected {}", from, version, PROTOCOL_VERSION);
+                self.send(&Message::WrongVersion { version, expected: PROTOCOL_VERSION }, from);
+                return;
+            },
+            (Message::Hello { spectator: true, name, .. }, Role::Host) => {
+                self.watch(from, name, now);
+                return;
+            },
+            (Message::Hello { name, .. }, Role::Host) => {
+                if self.peer.is_some() && !from_peer {
+                    debug!("Ignoring {}, already playing", from);
+                    return;
+                }
+                if self.peer.is_none() {
+                    if let Some(setup) = &mut self.setup {
+                        setup.names.right = name;
+                    }
+                    self.connected(from);
+                }
+                // Also repeated when the first welcome got lost
+                if let Some(setup) = self.setup.clone() {
+                    self.send(&Message::Welcome { setup, player: Some(Player::Right) }, from);
+                }
+            },
+            (Message::Welcome { setup, player }, Role::Client { host, .. }) if *host == from => {
+                if self.status == NetStatus::Connecting {
+                    self.setup = Some(setup);
+                    if let Some(player) = player {
+                        self.player = player;
+                    }
+                    self.connected(from);
+                }
+            },
+            (Message::Waiting, Role::Client { host, .. }) if *host == from => (),
+            (Message::Full, Role::Client { host, .. }) if *host == from && self.status == NetStatus::Connecting => {
+                self.status = NetStatus::Closed(CloseReason::Full);
+            },
+            (Message::WrongVersion { version, expected }, Role::Client { host, .. }) if *host == from && self.status == NetStatus::Connecting => {
+                self.status = NetStatus::Closed(CloseReason::WrongVersion { local: version, remote: expected });
+            },
+            (Message::Watching, Role::Host) => {
+                if let Some(spectator) = self.spectators.iter_mut().find(|spectator| spectator.address == from) {
+                    spectator.last_heard = now;
+                }
+                return;
+            },
+            (Message::State(snapshot), _) if from_peer && self.follows_snapshots() => {
+                if snapshot.tick >= self.tick {
+                    self.tick = snapshot.tick + 1;
+                    self.server_state = Some(snapshot.state.clone());
+                    self.snapshot = Some(snapshot);
+                }
+            },
+            (Message::Inputs { ack, start, inputs, checksum }, _) if from_peer && self.status == NetStatus::Connected
+                                                                    && start < self.tick + MAX_INPUTS_AHEAD => {
+                // Acknowledgements that arrive out of order never take back a newer one
+                let local_next = self.present + self.input_delay();
+                if ack > self.acked && ack <= local_next {
+                    self.acked = ack;
+                }
+                for (tick, input) in (start..).zip(inputs) {
+                    if tick >= self.remote_next && tick < self.tick + MAX_INPUTS_AHEAD {
+                        self.remote.insert(tick, input);
+                    }
+                }
+                while self.remote.contains_key(&self.remote_next) {
+                    self.remote_next += 1;
+                }
+                if let Some((tick, checksum)) = checksum {
+                    self.remote_checksums.insert(tick, checksum);
+                    prune(&mut self.remote_checksums);
+                    self.compare_checksums(tick);
+                }
+            },
+            (Message::Bye, Role::Host) if from_peer => self.status = NetStatus::Closed(CloseReason::PeerLeft),
+            (Message::Bye, Role::Host) => {
+                self.spectators.retain(|spectator| spectator.address != from);
+                return;
+            },
+            (Message::Bye, Role::Client { host, .. }) if *host == from => {
+                let over = matches!(self.server_state, Some(AppState::Win | AppState::Title));
+                self.status = NetStatus::Closed(if over { CloseReason::MatchOver } else { CloseReason::PeerLeft });
+            },
+            (_, _) => return,
+        }
+        // A server answers before the match starts, when it is not the peer yet
+        let from_host = matches!(&self.role, Role::Client { host, .. } if *host == from);
+        if self.peer == Some(from) || from_host {
+            self.last_heard = Some(now);
+        }
+    }
+
+    /// Lets a spectator watch once the match is on, if there is room
+    fn watch(&mut self, from: SocketAddr, name: String, now: Duration) {
+        let setup = match &self.setup {
+            Some(setup) if self.peer.is_some() => setup.clone(),
+            // The names are known once the other player is in
+            _ => {
+                self.send(&Message::Waiting, from);
+                return;
+            }
+        };
+        let watching = self.spectators.len();
+        match self.spectators.iter_mut().find(|spectator| spectator.address == from) {
+            Some(spectator) => spectator.last_heard = now,
+            None if watching >= self.max_spectators => {
+                debug!("Turning away spectator {}, already {} watching", from, watching);
+                self.send(&Message::Full, from);
+                return;
+            },
+            None => {
+                info!("{} watches the match from {}", name, from);
+                self.spectators.push(Spectator { address: from, last_heard: now });
+            },
+        }
+        // Also repeated when the first welcome got lost
+        self.send(&Message::Welcome { setup, player: None }, from);
+    }
+
+    /// Sends the confirmed match to everyone watching it
+    pub fn send_to_spectators(&self, snapshot: &MatchSnapshot) {
+        let message = Message::State(snapshot.clone());
+        for spectator in &self.spectators {
+            self.send(&message, spectator.address);
+        }
+    }
+
+    /// Moves the local clock on by a tick, with `local_input` as the input of the local player
+    /// `input_delay` ticks from now. Refused while too many ticks wait for the other player's input.
+    pub fn step(&mut self, local_input: i8) -> bool {
+        if self.status == NetStatus::Connected && self.follows_snapshots() {
+            // The server only needs the latest, a spectator's input goes nowhere
+            self.local.clear();
+            if !self.spectating() {
+                self.local.insert(self.present, local_input);
+            }
+            self.present += 1;
+            return true;
+        }
+        if self.status != NetStatus::Connected || self.present - self.tick >= self.netcode().window() {
+            return false;
+        }
+        self.local.insert(self.present + self.input_delay(), local_input);
+        self.present += 1;
+        true
+    }
+
+    /// Input of a tick, in the order of the left and right player
+    fn inputs(&self, local: i8, remote: i8) -> [i8; 2] {
+        match self.local_player() {
+            Player::Left => [local, remote],
+            Player::Right => [remote, local],
+        }
+    }
+
+    /// Confirms the next tick the local clock has reached if the inputs of both players are known,
+    /// returning those inputs
+    pub fn confirm(&mut self) -> Option<[i8; 2]> {
+        if self.status != NetStatus::Connected || self.follows_snapshots() || self.tick >= self.present {
+            return None;
+        }
+        // Nobody has any input before the delay has passed, those ticks are played without
+        let (local, remote) = if self.tick < self.input_delay() {
+            (0, 0)
+        } else {
+            (*self.local.get(&self.tick)?, *self.remote.get(&self.tick)?)
+        };
+        self.remote.remove(&self.tick);
+        self.tick += 1;
+        self.local = self.local.split_off(&self.tick.min(self.acked));
+        self.last_remote = remote;
+        Some(self.inputs(local, remote))
+    }
+
+    /// Inputs of the ticks the local clock has reached but that are not confirmed, guessing that
+    /// the other player keeps doing what they did last. Empty without rollback.
+    pub fn predicted(&self) -> Vec<[i8; 2]> {
+        if self.netcode() != Netcode::Rollback || self.spectating() {
+            return Vec::new();
+        }
+        (self.tick..self.present)
+            .map(|tick| {
+                let local = self.local.get(&tick).copied().unwrap_or(0);
+                self.inputs(local, self.last_remote)
+            })
+            .collect()
+    }
+
+    /// Newest snapshot from the server not shown yet
+    pub fn take_snapshot(&mut self) -> Option<MatchSnapshot> {
+        self.snapshot.take()
+    }
+
+    /// Keeps the checksum of the game after the confirmed `tick` to compare with the peer's
+    pub fn record_checksum(&mut self, tick: u32, checksum: impl FnOnce() -> u64) {
+        if tick.is_multiple_of(CHECKSUM_INTERVAL) {
+            self.checksums.insert(tick, checksum());
+            prune(&mut self.checksums);
+            self.compare_checksums(tick);
+        }
+    }
+
+    fn compare_checksums(&mut self, tick: u32) {
+        if let (Some(local), Some(remote)) = (self.checksums.get(&tick), self.remote_checksums.get(&tick)) {
+            if local != remote {
+                warn!("Checksums differ after tick {}: {:016x} here, {:016x} for the other player", tick, local, remote);
+                self.disconnect();
+                self.status = NetStatus::Closed(CloseReason::Desynced);
+            }
+        }
+    }
+
+    /// Tells the peer this side is leaving. Nothing is sent after this.
+    pub fn disconnect(&mut self) {
+        if matches!(self.status, NetStatus::Closed(_)) {
+            return;
+        }
+        let peer = match &self.role {
+            Role::Host => self.peer,
+            Role::Client { host, .. } => Some(*host),
+        };
+        let spectators = self.spectators.iter().map(|spectator| spectator.address);
+        for to in peer.into_iter().chain(spectators) {
+            for _ in 0..BYE_COPIES {
+                self.send(&Message::Bye, to);
+            }
+        }
+        self.status = NetStatus::Closed(CloseReason::Left);
+    }
+}
+
+fn prune(checksums: &mut BTreeMap<u32, u64>) {
+    while checksums.len() > CHECKSUMS_KEPT {
+        checksums.pop_first();
+    }
+}
+
+/// Paddle directions from -127 to 127, small enough to send every tick
+pub fn encode_direction(direction: f32) -> i8 {
+    (direction.clamp(-1.0, 1.0) * 127.0).round() as i8
+}
+
+/// Paddle directions of the tick being simulated, read from the local controllers or sent
+/// by the other player, a bot or a replay
+#[derive(Default)]
+pub struct NetInputs(pub [i8; 2]);
+
+impl NetInputs {
+    pub fn direction(&self, player: Player) -> f32 {
+        let input = match player {
+            Player::Left => self.0[0],
+            Player::Right => self.0[1],
+        };
+        input as f32 / 127.0
+    }
+}
+
+/// Online matches, active while there is a `NetSession` resource. Both peers run the same
+/// simulation in fixed ticks from the same seed, the inputs exchanged are all that differs.
+pub struct NetPlugin {
+    /// Show the lobby text, there is nothing to show it on when running headless
+    pub windowed: bool,
+}
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        let root = RootState::of(app);
+        app
+            .init_resource::<Rollback>()
+            .add_system_to_stage(CoreStage::PreUpdate, net_step.after(frame_time))
+            .add_system_set(SystemSet::on_update(AppState::Lobby).with_system(lobby_update))
+            .add_system(leave_input)
+            // Leaving for the title from a later stage than the game, so the two never both
+            // change the state in the same frame
+            .add_system_to_stage(CoreStage::PostUpdate, session_closed)
+            .add_system_set(SystemSet::on_enter(root).with_system(end_session))
+            .add_system_to_stage(CoreStage::Last, disconnect_on_exit);
+        if self.windowed {
+            app
+                .add_system_set(SystemSet::on_enter(AppState::Lobby).with_system(lobby_enter))
+                .add_system_set(SystemSet::on_update(AppState::Lobby).with_system(lobby_closed))
+                .add_system_set(SystemSet::on_exit(AppState::Lobby).with_system(lobby_exit));
+        }
+    }
+}
+
+/// Moves the match clock on in fixed ticks and sends the local player's input for them,
+/// the simulation stage then plays the ticks it can
+fn net_step(session: Option<ResMut<NetSession>>,
+            mut clock: Local<Duration>,
+            time: Res<Time>,
+            state: Res<State<AppState>>,
+            settings: Res<Settings>,
+            devices: InputDevices,
+            paddle_query: Query<(&Paddle, &Transform)>,
+            ball_query: Query<&Transform, With<Ball>>) {
+    let mut session = match session {
+        Some(session) => session,
+        None => return,
+    };
+    session.poll(time.time_since_startup());
+    // The match starts once the lobby has applied the host's setup
+    if session.status() != NetStatus::Connected || *state.current() == AppState::Lobby {
+        *clock = Duration::ZERO;
+        return;
+    }
+    let tick = tick_duration();
+    *clock = (*clock + time.delta()).min(tick * MAX_CATCH_UP);
+    if *clock < tick {
+        return;
+    }
+    let player = session.local_player();
+    let paddle_y = paddle_query.iter()
+        .find(|(Paddle(paddle_player), _)| *paddle_player == player)
+        .map_or(0.0, |(_, transform)| transform.translation.y);
+    let input = encode_direction(devices.direction(settings.controllers.get(player), player, paddle_y, &ball_query));
+    while *clock >= tick && session.step(input) {
+        *clock -= tick;
+    }
+}
+
+fn lobby_enter(mut commands: Commands, theme: ActiveTheme, locale: Res<Locale>, session: Res<NetSession>) {
+    let status = match &session.role {
+        Role::Host => {
+            let port = session.local_addr().map_or(0, |address| address.port());
+            locale.format("lobby-hosting", &[("port", &port.to_string())])
+        },
+        Role::Client { host, spectator: true, .. } => locale.format("lobby-spectating", &[("address", &host.to_string())]),
+        Role::Client { host, .. } => locale.format("lobby-connecting", &[("address", &host.to_string())]),
+    };
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    })
+    .insert(LobbyText)
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: locale.get("lobby"),
+                        style: theme.text_style(100.0)
+                    },
+                    TextSection {
+                        value: format!("\n{}", status),
+                        style: theme.secondary_text_style(50.0)
+                    },
+                    TextSection {
+                        value: format!("\n{}", locale.get("lobby-cancel")),
+                        style: theme.secondary_text_style(40.0)
+                    }
+                ],
+                alignment: TextAlignment::TOP_CENTER
+            },
+            ..Default::default()
+        })
+        .insert(Themed::Text { primary: 1 })
+        .insert(LobbyStatusText);
+    });
+}
+
+/// Starts the match once connected, with everything the host chose
+fn lobby_update(session: Option<Res<NetSession>>,
+                mut state: ResMut<State<AppState>>,
+                mut match_seed: ResMut<MatchSeed>,
+                mut rules: ResMut<MatchRules>,
+                mut names: ResMut<PlayerNames>,
+                mut controllers: ResMut<Controllers>,
+                mut accessibility: ResMut<AccessibilitySettings>) {
+    let session = match session {
+        Some(session) if session.status() == NetStatus::Connected => session,
+        _ => return,
+    };
+    if let Some(setup) = session.setup() {
+        info!("Online match {} - {}, random seed {}", setup.names.left, setup.names.right, setup.seed);
+        match_seed.next = setup.seed;
+        *rules = setup.rules.clone();
+        *names = setup.names.clone();
+        *controllers = Controllers { left: Controller::Network, right: Controller::Network };
+        accessibility.game_speed = setup.game_speed;
+        state.set(AppState::NewGame).unwrap();
+    }
+}
+
+/// Why the match could not start, `None` when this side left
+fn close_text(reason: CloseReason, locale: &Locale) -> Option<String> {
+    let key = match reason {
+        CloseReason::Left => return None,
+        CloseReason::WrongVersion { local, remote } => {
+            return Some(locale.format("lobby-wrong-version", &[("local", &local.to_string()), ("remote", &remote.to_string())]));
+        },
+        CloseReason::PeerLeft => "lobby-peer-left",
+        CloseReason::TimedOut => "lobby-timed-out",
+        CloseReason::Desynced => "lobby-desynced",
+        CloseReason::MatchOver => "lobby-match-over",
+        CloseReason::Full => "lobby-full",
+    };
+    Some(locale.get(key))
+}
+
+/// Shows why the connection closed before the match started, until the lobby is left with ESC
+fn lobby_closed(session: Option<Res<NetSession>>,
+                locale: Res<Locale>,
+                root: Res<RootState>,
+                mut state: ResMut<State<AppState>>,
+                keyboard_input: Res<Input<KeyCode>>,
+                gamepads: Res<Gamepads>,
+                gamepad_buttons: Res<Input<GamepadButton>>,
+                mut lobby_text_query: Query<&mut Text, With<LobbyStatusText>>) {
+    let text = match session.map(|session| session.status()) {
+        Some(NetStatus::Closed(reason)) => close_text(reason, &locale),
+        _ => None,
+    };
+    let text = match text {
+        Some(text) => text,
+        None => return,
+    };
+    for mut lobby_text in lobby_text_query.iter_mut() {
+        let status = format!("\n{}", text);
+        if lobby_text.sections[1].value != status {
+            lobby_text.sections[1].value = status;
+            lobby_text.sections[2].value = format!("\n{}", locale.get("lobby-back"));
+        }
+    }
+    let back = keyboard_input.just_released(KeyCode::Escape)
+        || gamepads.iter().any(|gamepad| gamepad_buttons.just_released(GamepadButton::new(*gamepad, GamepadButtonType::East)));
+    if back {
+        state.set(root.0.clone()).unwrap();
+    }
+}
+
+fn lobby_exit(mut commands: Commands, lobby_text_query: Query<Entity, With<LobbyText>>) {
+    for e in lobby_text_query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+/// There is no pausing an online match, ESC leaves it
+fn leave_input(session: Option<ResMut<NetSession>>,
+               keyboard_input: Res<Input<KeyCode>>,
+               gamepads: Res<Gamepads>,
+               gamepad_buttons: Res<Input<GamepadButton>>) {
+    let mut session = match session {
+        Some(session) => session,
+        None => return,
+    };
+    let leave = keyboard_input.just_released(KeyCode::Escape)
+        || gamepads.iter().any(|gamepad| gamepad_buttons.just_released(GamepadButton::new(*gamepad, GamepadButtonType::East)));
+    if leave {
+        session.disconnect();
+    }
+}
+
+fn session_closed(session: Option<Res<NetSession>>,
+                  root: Res<RootState>,
+                  mut state: ResMut<State<AppState>>,
+                  lobby_text_query: Query<(), With<LobbyStatusText>>) {
+    let session = match session {
+        Some(session) => session,
+        None => return,
+    };
+    if let NetStatus::Closed(reason) = session.status() {
+        // A lobby on screen tells why first, see `lobby_closed`
+        let shown = *state.current() == AppState::Lobby && reason != CloseReason::Left && !lobby_text_query.is_empty();
+        if *state.current() != root.0 && !shown {
+            info!("{} after {} ticks", reason, session.tick());
+            state.overwrite_set(root.0.clone()).unwrap();
+        }
+    }
+}
+
+/// Leaves the match if it is still on and goes back to playing on this machine as configured
+fn end_session(mut commands: Commands,
+               session: Option<ResMut<NetSession>>,
+               settings: Res<Settings>,
+               mut rules: ResMut<MatchRules>,
+               mut names: ResMut<PlayerNames>,
+               mut controllers: ResMut<Controllers>,
+               mut accessibility: ResMut<AccessibilitySettings>) {
+    let mut session = match session {
+        Some(session) => session,
+        None => return,
+    };
+    session.disconnect();
+    commands.remove_resource::<NetSession>();
+    commands.insert_resource(Rollback::default());
+    *rules = settings.rules.clone();
+    *names = settings.players.clone();
+    *controllers = settings.controllers;
+    accessibility.game_speed = settings.accessibility.game_speed;
+}
+
+/// Closing the window also ends in an exit request, while the game still runs
+fn disconnect_on_exit(exit_events: EventReader<AppExit>, session: Option<ResMut<NetSession>>) {
+    if let Some(mut session) = session {
+        if !exit_events.is_empty() {
+            session.disconnect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICKS: u32 = 200;
+    const MAX_ROUNDS: u32 = 10_000;
+
+    fn setup(input_delay: u32) -> MatchSetup {
+        MatchSetup {
+            seed: 7,
+            rules: MatchRules::default(),
+            names: PlayerNames::default(),
+            game_speed: 1.0,
+            netcode: Netcode::Lockstep,
+            input_delay,
+        }
+    }
+
+    fn rollback_setup(input_delay: u32) -> MatchSetup {
+        MatchSetup { netcode: Netcode::Rollback, ..setup(input_delay) }
+    }
+
+    /// Brings a host and a client to the point where both play
+    fn connected(setup: MatchSetup) -> (NetSession, NetSession) {
+        let mut host = NetSession::host(0, setup).unwrap();
+        let mut client = NetSession::connect(&host_address(&host), "Client".to_string()).unwrap();
+        client.poll(Duration::ZERO);
+        host.poll(Duration::ZERO);
+        client.poll(Duration::ZERO);
+        assert_eq!((host.status(), client.status()), (NetStatus::Connected, NetStatus::Connected));
+        (host, client)
+    }
+
+    fn host_address(host: &NetSession) -> String {
+        format!("127.0.0.1:{}", host.local_addr().unwrap().port())
+    }
+
+    /// A different input for every tick and player
+    fn scripted_input(player: Player, tick: u32) -> i8 {
+        let offset = match player {
+            Player::Left => 0,
+            Player::Right => 50,
+        };
+        ((tick * 7 + offset) % 255) as i8
+    }
+
+    /// Passes packets between the host and the client, losing and reordering some of them
+    struct BadLink {
+        socket: UdpSocket,
+        host: SocketAddr,
+        client: Option<SocketAddr>,
+        held: Option<(Vec<u8>, SocketAddr)>,
+        count: u32,
+    }
+
+    impl BadLink {
+        fn new(host: SocketAddr) -> BadLink {
+            let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+            socket.set_nonblocking(true).unwrap();
+            BadLink { socket, host, client: None, held: None, count: 0 }
+        }
+
+        fn address(&self) -> String {
+            format!("127.0.0.1:{}", self.socket.local_addr().unwrap().port())
+        }
+
+        fn pass(&mut self) {
+            let mut buffer = [0; MAX_PACKET_SIZE];
+            while let Ok((size, from)) = self.socket.recv_from(&mut buffer) {
+                let to = if from == self.host {
+                    match self.client {
+                        Some(client) => client,
+                        None => continue,
+                    }
+                } else {
+                    self.client = Some(from);
+                    self.host
+                };
+                self.count += 1;
+                match self.count % 4 {
+                    // Lost
+                    0 => (),
+                    // Held back and sent after the next one
+                    1 => self.held = Some((buffer[..size].to_vec(), to)),
+                    _ => {
+                        self.socket.send_to(&buffer[..size], to).unwrap();
+                        if let Some((held, held_to)) = self.held.take() {
+                            self.socket.send_to(&held, held_to).unwrap();
+                        }
+                    },
+                }
+            }
+        }
+    }
+
+    /// Plays `TICKS` ticks on both ends and returns the inputs each end got
+    fn play(host: &mut NetSession, client: &mut NetSession, mut link: Option<&mut BadLink>) -> (Vec<[i8; 2]>, Vec<[i8; 2]>) {
+        let (mut host_inputs, mut client_inputs) = (Vec::new(), Vec::new());
+        let mut now = Duration::ZERO;
+        for _ in 0..MAX_ROUNDS {
+            now += tick_duration();
+            client.poll(now);
+            if let Some(link) = link.as_deref_mut() {
+                link.pass();
+            }
+            host.poll(now);
+            if let Some(link) = link.as_deref_mut() {
+                link.pass();
+            }
+            if host_inputs.len() < TICKS as usize {
+                let tick = host.present + host.input_delay();
+                host.step(scripted_input(Player::Left, tick));
+                host_inputs.extend(host.confirm());
+            }
+            if client_inputs.len() < TICKS as usize {
+                let tick = client.present + client.input_delay();
+                client.step(scripted_input(Player::Right, tick));
+                client_inputs.extend(client.confirm());
+            }
+            if host_inputs.len() == TICKS as usize && client_inputs.len() == TICKS as usize {
+                break;
+            }
+        }
+        (host_inputs, client_inputs)
+    }
+
+    fn expected_inputs(input_delay: u32) -> Vec<[i8; 2]> {
+        (0..TICKS)
+            .map(|tick| if tick < input_delay {
+                [0, 0]
+            } else {
+                [scripted_input(Player::Left, tick), scripted_input(Player::Right, tick)]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lockstep_over_loopback() {
+        let mut host = NetSession::host(0, setup(3)).unwrap();
+        let mut client = NetSession::connect(&host_address(&host), "Client".to_string()).unwrap();
+        let (host_inputs, client_inputs) = play(&mut host, &mut client, None);
+
+        assert_eq!(host_inputs, expected_inputs(3));
+        assert_eq!(client_inputs, expected_inputs(3));
+        assert_eq!(client.setup().map(|setup| setup.names.right.as_str()), Some("Client"));
+        assert_eq!(client.setup(), host.setup());
+    }
+
+    #[test]
+    fn lockstep_survives_loss_and_reordering() {
+        let mut host = NetSession::host(0, setup(2)).unwrap();
+        let mut link = BadLink::new(host_address(&host).parse().unwrap());
+        let mut client = NetSession::connect(&link.address(), "Client".to_string()).unwrap();
+        let (host_inputs, client_inputs) = play(&mut host, &mut client, Some(&mut link));
+
+        assert_eq!(host_inputs, expected_inputs(2));
+        assert_eq!(client_inputs, expected_inputs(2));
+    }
+
+    #[test]
+    fn rollback_confirms_the_same_inputs() {
+        let mut host = NetSession::host(0, rollback_setup(1)).unwrap();
+        let mut client = NetSession::connect(&host_address(&host), "Client".to_string()).unwrap();
+        let (host_inputs, client_inputs) = play(&mut host, &mut client, None);
+
+        assert_eq!(host_inputs, expected_inputs(1));
+        assert_eq!(client_inputs, expected_inputs(1));
+    }
+
+    #[test]
+    fn rollback_predicts_ahead_of_the_peer() {
+        let (mut host, mut client) = connected(rollback_setup(1));
+        // The client has sent nothing, its paddle is guessed to keep still
+        for tick in 0..MAX_PREDICTION + 2 {
+            let stepped = host.step(10);
+            assert_eq!(stepped, tick < MAX_PREDICTION);
+        }
+        assert_eq!(host.confirm(), Some([0, 0]));
+        assert_eq!(host.confirm(), None);
+        assert_eq!(host.predicted(), vec![[10, 0]; MAX_PREDICTION as usize - 1]);
+
+        client.step(-20);
+        client.step(-30);
+        client.poll(Duration::ZERO);
+        host.poll(Duration::ZERO);
+        assert_eq!(host.confirm(), Some([10, -20]));
+        // The guess follows the last input that arrived
+        assert_eq!(host.predicted(), vec![[10, -20]; MAX_PREDICTION as usize - 2]);
+
+        let (mut lockstep, _client) = connected(setup(1));
+        assert!(lockstep.step(10));
+        assert!(!lockstep.step(10));
+        assert!(lockstep.predicted().is_empty());
+    }
+
+    #[test]
+    fn differing_checksums_end_the_match() {
+        let (mut host, mut client) = connected(rollback_setup(0));
+        host.record_checksum(CHECKSUM_INTERVAL, || 1);
+        client.record_checksum(CHECKSUM_INTERVAL, || 1);
+        client.poll(Duration::ZERO);
+        host.poll(Duration::ZERO);
+        assert_eq!((host.status(), client.status()), (NetStatus::Connected, NetStatus::Connected));
+
+        host.record_checksum(CHECKSUM_INTERVAL * 2, || 2);
+        client.record_checksum(CHECKSUM_INTERVAL * 2, || 3);
+        client.poll(Duration::from_millis(100));
+        host.poll(Duration::from_millis(100));
+        assert_eq!(host.status(), NetStatus::Closed(CloseReason::Desynced));
+        client.poll(Duration::from_millis(200));
+        assert_ne!(client.status(), NetStatus::Connected);
+    }
+
+    #[test]
+    fn disconnect_reaches_the_peer() {
+        let (mut host, mut client) = connected(setup(0));
+        client.disconnect();
+        host.poll(Duration::from_millis(10));
+        assert_eq!(client.status(), NetStatus::Closed(CloseReason::Left));
+        assert_eq!(host.status(), NetStatus::Closed(CloseReason::PeerLeft));
+    }
+
+    #[test]
+    fn host_shows_spectators_the_match() {
+        let mut host = NetSession::host(0, setup(0)).unwrap();
+        host.set_max_spectators(1);
+        let mut carol = NetSession::spectate(&host_address(&host), "Carol".to_string()).unwrap();
+        carol.poll(Duration::ZERO);
+        host.poll(Duration::ZERO);
+        carol.poll(Duration::ZERO);
+        // Nothing to watch before the other player is in
+        assert_eq!(carol.status(), NetStatus::Connecting);
+
+        let mut client = NetSession::connect(&host_address(&host), "Client".to_string()).unwrap();
+        client.poll(Duration::ZERO);
+        carol.poll(Duration::ZERO);
+        host.poll(Duration::ZERO);
+        client.poll(Duration::ZERO);
+        carol.poll(Duration::ZERO);
+        assert_eq!(carol.status(), NetStatus::Connected);
+        assert!(carol.follows_snapshots());
+        assert_eq!(carol.setup(), host.setup());
+
+        let mut dave = NetSession::spectate(&host_address(&host), "Dave".to_string()).unwrap();
+        dave.poll(Duration::ZERO);
+        host.poll(Duration::ZERO);
+        dave.poll(Duration::ZERO);
+        assert_eq!(dave.status(), NetStatus::Closed(CloseReason::Full));
+
+        // A spectator's input goes nowhere
+        assert!(carol.step(100));
+        assert_eq!(carol.confirm(), None);
+        carol.poll(Duration::ZERO);
+        host.poll(Duration::ZERO);
+        assert!(host.remote.is_empty());
+
+        let snapshot = MatchSnapshot {
+            tick: 12,
+            state: AppState::InGame,
+            score: Score::default(),
+            balls: Vec::new(),
+            paddles: Vec::new(),
+            events: vec![PongEvent::Countdown(0)],
+        };
+        host.send_to_spectators(&snapshot);
+        carol.poll(Duration::ZERO);
+        client.poll(Duration::ZERO);
+        assert_eq!(carol.take_snapshot().map(|snapshot| snapshot.tick), Some(12));
+        assert!(client.take_snapshot().is_none());
+
+        host.disconnect();
+        carol.poll(Duration::ZERO);
+        assert_eq!(carol.status(), NetStatus::Closed(CloseReason::PeerLeft));
+    }
+
+    #[test]
+    fn another_protocol_version_is_turned_away() {
+        let mut host = NetSession::host(0, setup(0)).unwrap();
+        let older = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        older.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let hello = Message::Hello { version: PROTOCOL_VERSION - 1, name: "Older".to_string(), spectator: false };
+        older.send_to(&hello.encode(), host_address(&host)).unwrap();
+        host.poll(Duration::ZERO);
+        assert_eq!(host.status(), NetStatus::Connecting);
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        let (size, _) = older.recv_from(&mut buffer).unwrap();
+        let answer = Message::decode(&buffer[..size]);
+        assert!(matches!(answer, Some(Message::WrongVersion { version, expected })
+                         if version == PROTOCOL_VERSION - 1 && expected == PROTOCOL_VERSION));
+
+        // And the client is told why
+        let newer = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        newer.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let address = format!("127.0.0.1:{}", newer.local_addr().unwrap().port());
+        let mut client = NetSession::connect(&address, "Client".to_string()).unwrap();
+        client.poll(Duration::ZERO);
+        let (_, from) = newer.recv_from(&mut buffer).unwrap();
+        let answer = Message::WrongVersion { version: PROTOCOL_VERSION, expected: PROTOCOL_VERSION + 1 };
+        newer.send_to(&answer.encode(), from).unwrap();
+        client.poll(Duration::ZERO);
+        let reason = CloseReason::WrongVersion { local: PROTOCOL_VERSION, remote: PROTOCOL_VERSION + 1 };
+        assert_eq!(client.status(), NetStatus::Closed(reason));
+    }
+
+    #[test]
+    fn silent_peer_times_out() {
+        // Bound but never answering
+        let silent = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = format!("127.0.0.1:{}", silent.local_addr().unwrap().port());
+        let mut client = NetSession::connect(&address, "Client".to_string()).unwrap();
+        client.poll(Duration::ZERO);
+        client.poll(TIMEOUT / 2);
+        assert_eq!(client.status(), NetStatus::Connecting);
+        client.poll(TIMEOUT * 2);
+        assert_eq!(client.status(), NetStatus::Closed(CloseReason::TimedOut));
+
+        // A host keeps waiting for its client, but not for one that went quiet
+        let mut host = NetSession::host(0, setup(0)).unwrap();
+        host.poll(Duration::ZERO);
+        host.poll(TIMEOUT * 2);
+        assert_eq!(host.status(), NetStatus::Connecting);
+        let mut client = NetSession::connect(&host_address(&host), "Client".to_string()).unwrap();
+        client.poll(TIMEOUT * 2);
+        host.poll(TIMEOUT * 2);
+        assert_eq!(host.status(), NetStatus::Connected);
+        host.poll(TIMEOUT * 4);
+        assert_eq!(host.status(), NetStatus::Closed(CloseReason::TimedOut));
+    }
+}
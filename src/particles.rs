@@ -0,0 +1,282 @@
+use std::{fs, io, path::{Path, PathBuf}};
+use bevy::{
+    prelude::*,
+    render::texture::DEFAULT_IMAGE_HANDLE
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::config::config_dir;
+use crate::theme::ActiveTheme;
+use crate::types::*;
+
+/// Built into the executable like the locales and themes, used when there is no settings file
+const PARTICLE_SETTINGS: &str = include_str!("../assets/particles.ron");
+/// In the settings directory, read at startup so effects can be tuned without a rebuild
+const PARTICLES_FILE: &str = "particles.ron";
+/// Keeps particles just above the sprites of the 2D view
+const PARTICLE_Z: f32 = 0.1;
+
+/// How the particles of one kind of effect look and move
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmitterSettings {
+    /// Particles per burst
+    pub count: u32,
+    /// Shortest and longest life in seconds
+    pub lifetime: (f32, f32),
+    /// Slowest and fastest starting speed
+    pub speed: (f32, f32),
+    /// Largest angle in degrees between a particle and the direction of the burst
+    pub spread: f32,
+    /// Fraction of the speed lost per second
+    pub drag: f32,
+    /// Size at birth and at death
+    pub size: (f32, f32),
+    /// Colors spread evenly over the life of a particle, multiplied by the tint of the burst
+    pub colors: Vec<Color>,
+}
+
+impl EmitterSettings {
+    fn color(&self, life: f32, tint: Color) -> Color {
+        let color = match self.colors.len() {
+            0 => Vec4::ONE,
+            1 => Vec4::from(self.colors[0]),
+            keyframes => {
+                let position = life.clamp(0.0, 1.0) * (keyframes - 1) as f32;
+                let index = (position as usize).min(keyframes - 2);
+                Vec4::from(self.colors[index]).lerp(Vec4::from(self.colors[index + 1]), position - index as f32)
+            }
+        };
+        Color::from(color * Vec4::from(tint))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParticleSettings {
+    /// Size of the particle pool, the oldest particles are reused when it runs out
+    pub max_particles: usize,
+    pub paddle_hit: EmitterSettings,
+    pub wall_hit: EmitterSettings,
+    pub goal: EmitterSettings,
+}
+
+#[derive(Clone, Copy)]
+enum Emitter {
+    PaddleHit,
+    WallHit,
+    Goal,
+}
+
+impl ParticleSettings {
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(PARTICLES_FILE))
+    }
+
+    fn builtin() -> ParticleSettings {
+        ron::from_str(PARTICLE_SETTINGS).unwrap_or_else(|e| panic!("Invalid built-in particle settings: {}", e))
+    }
+
+    /// Loads the settings from `path`. A missing file gives the built-in settings, and so does
+    /// one that cannot be read, effects are never worth keeping the game from starting.
+    pub fn load(path: Option<&Path>) -> ParticleSettings {
+        let path = match path {
+            Some(path) => path,
+            None => return ParticleSettings::builtin(),
+        };
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return ParticleSettings::builtin(),
+            Err(e) => {
+                warn!("Could not read particle settings from {}, using the built-in ones: {}", path.display(), e);
+                return ParticleSettings::builtin();
+            }
+        };
+        match ron::from_str(&content) {
+            Ok(settings) => {
+                info!("Using particle settings from {}", path.display());
+                settings
+            },
+            Err(e) => {
+                warn!("Invalid particle settings in {}, using the built-in ones: {}", path.display(), e);
+                ParticleSettings::builtin()
+            }
+        }
+    }
+
+    fn emitter(&self, emitter: Emitter) -> &EmitterSettings {
+        match emitter {
+            Emitter::PaddleHit => &self.paddle_hit,
+            Emitter::WallHit => &self.wall_hit,
+            Emitter::Goal => &self.goal,
+        }
+    }
+}
+
+/// A pooled particle, hidden while not in use
+#[derive(Component)]
+struct Particle {
+    emitter: Emitter,
+    tint: Color,
+    velocity: Vec3,
+    age: f32,
+    lifetime: f32,
+}
+
+/// All particle entities, handed out in turn so that the oldest one is reused first
+struct ParticlePool {
+    entities: Vec<Entity>,
+    next: usize,
+}
+
+pub struct ParticlesPlugin;
+
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(ParticleSettings::load(ParticleSettings::default_path().as_deref()))
+            .add_startup_system(setup_particles)
+            .add_system(emit_particles)
+            .add_system(update_particles.after(emit_particles))
+            // Nothing of the game is shown to the app's cameras while idle
+            .add_system_set(SystemSet::on_enter(AppState::Idle).with_system(hide_particles));
+    }
+}
+
+fn setup_particles(mut commands: Commands,
+                   settings: Res<ParticleSettings>,
+                   mut meshes: ResMut<Assets<Mesh>>,
+                   mut materials: ResMut<Assets<StandardMaterial>>) {
+    let mesh = meshes.add(Mesh::from(shape::Cube { size: 1.0 }));
+    let entities = (0..settings.max_particles)
+        .map(|_| {
+            // Each particle fades on its own, so each needs its own material
+            let material = materials.add(StandardMaterial {
+                unlit: true,
+                alpha_mode: AlphaMode::Blend,
+                ..Default::default()
+            });
+            commands.spawn_bundle(PbrBundle {
+                mesh: mesh.clone(),
+                material,
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert_bundle((Sprite { custom_size: Some(Vec2::ONE), ..Default::default() },
+                            DEFAULT_IMAGE_HANDLE.typed::<Image>()))
+            .insert(Particle { emitter: Emitter::WallHit, tint: Color::WHITE, velocity: Vec3::ZERO, age: 0.0, lifetime: 0.0 })
+            .id()
+        })
+        .collect();
+    commands.insert_resource(ParticlePool { entities, next: 0 });
+}
+
+fn random_in(rng: &mut impl Rng, (min, max): (f32, f32)) -> f32 {
+    if max > min { rng.gen_range(min..max) } else { min }
+}
+
+fn emit_particles(mut events: EventReader<PongEvent>,
+                  settings: Res<ParticleSettings>,
+                  mut pool: ResMut<ParticlePool>,
+                  theme: ActiveTheme,
+                  ball_query: Query<(&Transform, &Moving), With<Ball>>,
+                  mut particle_query: Query<(&mut Particle, &mut Transform, &mut Visibility), Without<Ball>>) {
+    let theme = theme.get();
+    // Purely cosmetic, so the seeded game randomness is left alone
+    let mut rng = rand::thread_rng();
+
+    for e in events.iter() {
+        let (emitter, tint, position, direction) = match e {
+            PongEvent::Bounce(kind, contact) => {
+                let (emitter, tint) = match kind {
+                    Collider::Paddle(player) => (Emitter::PaddleHit, theme.paddle(*player)),
+                    Collider::Wall => (Emitter::WallHit, theme.wall),
+                    Collider::Ball | Collider::Goal(_) => continue,
+                };
+                // Sparks fly off the way the ball bounced
+                let direction = ball_query.iter().next().map_or(Vec3::X, |(_, moving)| moving.velocity.normalize_or_zero());
+                (emitter, tint, *contact, direction)
+            },
+            PongEvent::Goal(player) => {
+                let position = ball_query.iter().next().map_or(Vec3::ZERO, |(transform, _)| transform.translation);
+                // Back into the field, away from the goal
+                (Emitter::Goal, theme.goal(*player), position, Vec3::new(-position.x.signum(), 0.0, 0.0))
+            },
+            PongEvent::Countdown(_) => continue,
+        };
+
+        let emitter_settings = settings.emitter(emitter);
+        let spread = emitter_settings.spread.to_radians();
+        for _ in 0..emitter_settings.count.min(pool.entities.len() as u32) {
+            let entity = pool.entities[pool.next];
+            pool.next = (pool.next + 1) % pool.entities.len();
+            if let Ok((mut particle, mut transform, mut visibility)) = particle_query.get_mut(entity) {
+                let angle = random_in(&mut rng, (-spread, spread));
+                *particle = Particle {
+                    emitter,
+                    tint,
+                    velocity: Quat::from_rotation_z(angle) * direction * random_in(&mut rng, emitter_settings.speed),
+                    age: 0.0,
+                    lifetime: random_in(&mut rng, emitter_settings.lifetime),
+                };
+                *transform = Transform::from_translation(position.truncate().extend(PARTICLE_Z))
+                    .with_scale(Vec3::splat(emitter_settings.size.0));
+                visibility.is_visible = true;
+            }
+        }
+    }
+}
+
+fn hide_particles(mut particle_query: Query<&mut Visibility, With<Particle>>) {
+    for mut visibility in particle_query.iter_mut() {
+        visibility.is_visible = false;
+    }
+}
+
+fn update_particles(time: Res<Time>,
+                    settings: Res<ParticleSettings>,
+                    mut materials: ResMut<Assets<StandardMaterial>>,
+                    mut particle_query: Query<(&mut Particle, &mut Transform, &mut Visibility,
+                                               &mut Sprite, &Handle<StandardMaterial>)>) {
+    let dt = time.delta_seconds();
+    for (mut particle, mut transform, mut visibility, mut sprite, material) in particle_query.iter_mut() {
+        if !visibility.is_visible {
+            continue;
+        }
+        particle.age += dt;
+        if particle.age >= particle.lifetime {
+            visibility.is_visible = false;
+            continue;
+        }
+        let emitter_settings = settings.emitter(particle.emitter);
+        let life = particle.age / particle.lifetime;
+        particle.velocity *= (1.0 - emitter_settings.drag * dt).max(0.0);
+        transform.translation += particle.velocity * dt;
+        transform.scale = Vec3::splat(emitter_settings.size.0 + (emitter_settings.size.1 - emitter_settings.size.0) * life);
+
+        let color = emitter_settings.color(life, particle.tint);
+        sprite.color = color;
+        if let Some(material) = materials.get_mut(material) {
+            material.base_color = color;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn particle_settings_fall_back_to_the_built_in_ones() {
+        let dir = std::env::temp_dir().join(format!("bevy-pong-particles-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(PARTICLES_FILE);
+        let builtin = ParticleSettings::builtin().max_particles;
+
+        assert_eq!(ParticleSettings::load(None).max_particles, builtin);
+        assert_eq!(ParticleSettings::load(Some(&path)).max_particles, builtin);
+        fs::write(&path, PARTICLE_SETTINGS.replace("max_particles: 400", "max_particles: 50")).unwrap();
+        assert_eq!(ParticleSettings::load(Some(&path)).max_particles, 50);
+        fs::write(&path, "(max_particles: 50, paddle_hit: (").unwrap();
+        assert_eq!(ParticleSettings::load(Some(&path)).max_particles, builtin);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,116 @@
+use bevy::prelude::*;
+use crate::locale::Locale;
+use crate::net::NetSession;
+use crate::replay::Playback;
+use crate::save::{match_phase, MatchToSave, SaveFile};
+use crate::theme::{ActiveTheme, Themed};
+use crate::types::*;
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system_set(SystemSet::on_update(AppState::InGame).with_system(pause_input))
+            .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(pause_enter))
+            .add_system_set(SystemSet::on_update(AppState::Paused).with_system(pause_update))
+            .add_system_set(SystemSet::on_exit(AppState::Paused).with_system(pause_exit));
+    }
+}
+
+fn pause_input(mut state: ResMut<State<AppState>>,
+               mut keyboard_input: ResMut<Input<KeyCode>>,
+               gamepads: Res<Gamepads>,
+               mut gamepad_buttons: ResMut<Input<GamepadButton>>,
+               session: Option<Res<NetSession>>,
+               playback: Option<Res<Playback>>) {
+    // The other player would have to wait, online matches leave on ESC instead. Replays have controls of their own.
+    if session.is_some() || playback.is_some() {
+        return;
+    }
+    let pause = keyboard_input.just_released(KeyCode::Escape)
+        || gamepads.iter().any(|gamepad| gamepad_buttons.just_released(GamepadButton::new(*gamepad, GamepadButtonType::Start)));
+    if pause {
+        state.push(AppState::Paused).unwrap();
+        // The pause menu runs in this same frame and would take ESC for quitting
+        keyboard_input.clear();
+        gamepad_buttons.clear();
+    }
+}
+
+fn pause_enter(mut commands: Commands, theme: ActiveTheme, locale: Res<Locale>) {
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        },
+        color: Color::rgba(0.0, 0.0, 0.0, 0.5).into(),
+        ..Default::default()
+    })
+    .insert(PauseText)
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: locale.get("paused"),
+                        style: theme.text_style(100.0)
+                    },
+                    TextSection {
+                        value: ["paused-resume", "paused-save", "paused-quit"].iter()
+                            .map(|key| format!("\n{}", locale.get(key)))
+                            .collect(),
+                        style: theme.secondary_text_style(40.0)
+                    }
+                ],
+                alignment: TextAlignment::TOP_CENTER
+            },
+            ..Default::default()
+        })
+        .insert(Themed::Text { primary: 1 });
+    });
+}
+
+fn pause_update(mut state: ResMut<State<AppState>>,
+                mut keyboard_input: ResMut<Input<KeyCode>>,
+                gamepads: Res<Gamepads>,
+                mut gamepad_buttons: ResMut<Input<GamepadButton>>,
+                save_file: Res<SaveFile>,
+                root: Res<RootState>,
+                match_to_save: MatchToSave) {
+    let mut resume = keyboard_input.just_released(KeyCode::Space);
+    let mut save = keyboard_input.just_released(KeyCode::S);
+    let mut quit = keyboard_input.just_released(KeyCode::Escape);
+
+    for gamepad in gamepads.iter() {
+        let button = |button_type| GamepadButton::new(*gamepad, button_type);
+        resume |= gamepad_buttons.any_just_released([button(GamepadButtonType::South), button(GamepadButtonType::Start)]);
+        save |= gamepad_buttons.just_released(button(GamepadButtonType::West));
+        quit |= gamepad_buttons.just_released(button(GamepadButtonType::East));
+    }
+
+    if resume {
+        state.pop().unwrap();
+    } else if save || quit {
+        if save {
+            if let Some(phase) = match_phase(&state) {
+                save_file.write(&match_to_save.snapshot(phase));
+            }
+        }
+        state.replace(root.0.clone()).unwrap();
+    }
+    // The next screen runs in this same frame too, the title would quit on the same ESC
+    if resume || save || quit {
+        keyboard_input.clear();
+        gamepad_buttons.clear();
+    }
+}
+
+fn pause_exit(mut commands: Commands, pause_text_query: Query<Entity, With<PauseText>>) {
+    for e in pause_text_query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
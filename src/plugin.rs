@@ -0,0 +1,191 @@
+use std::path::PathBuf;
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use crate::types::*;
+use crate::config::Settings;
+use crate::locale::Locale;
+use crate::accessibility::AccessibilityPlugin;
+use crate::setup::setup;
+use crate::theme::ThemePlugin;
+use crate::scaling::ScalingPlugin;
+use crate::camera::CameraPlugin;
+use crate::visuals::VisualsPlugin;
+use crate::particles::ParticlesPlugin;
+use crate::trail::TrailPlugin;
+use crate::hud::HudPlugin;
+use crate::title::TitlePlugin;
+use crate::stats::StatsPlugin;
+use crate::records::RecordsPlugin;
+use crate::save::SavePlugin;
+use crate::pause::PausePlugin;
+use crate::mixer::MixerPlugin;
+use crate::net::NetPlugin;
+use crate::bot::BotPlugin;
+use crate::lan::{AnnouncePlugin, LanPlugin};
+use crate::replay::ReplayPlugin;
+use crate::goal_replay::GoalReplayPlugin;
+use crate::game::GamePlugin;
+#[cfg(feature = "audio")]
+use crate::sound::SoundPlugin;
+
+/// The whole game, for the `bevy-pong` binary or an app of one's own. Needs `DefaultPlugins`,
+/// or the plugins the game uses from them when headless. The game's states are `AppState`s of
+/// its own, next to any states of the app.
+///
+/// The game spawns its field, lights and cameras at startup. An app with screens of its own
+/// starts and ends matches in `AppState::Idle`, see `root_state`, where all of them are hidden.
+/// The game only moves, letterboxes and scales its own cameras and text, never the app's.
+///
+/// ```no_run
+/// use bevy::prelude::*;
+/// use bevy_pong::{AppState, Controller, Controllers, MatchRules, PongPlugin};
+///
+/// App::new()
+///     .add_plugins(DefaultPlugins)
+///     .add_plugin(PongPlugin::new()
+///         .rules(MatchRules { goals_to_win: 5, ..Default::default() })
+///         .controllers(Controllers { left: Controller::Keyboard, right: Controller::Cpu })
+///         .theme("neon")
+///         .start_state(AppState::NewGame))
+///     .run();
+/// ```
+pub struct PongPlugin {
+    settings: Settings,
+    seed: Option<u64>,
+    root_state: AppState,
+    start_state: Option<AppState>,
+    windowed: bool,
+    stats_file: Option<PathBuf>,
+    config: Option<PathBuf>,
+}
+
+impl Default for PongPlugin {
+    fn default() -> Self {
+        PongPlugin {
+            settings: Settings::default(),
+            seed: None,
+            root_state: AppState::Title,
+            start_state: None,
+            windowed: true,
+            stats_file: None,
+            config: None,
+        }
+    }
+}
+
+impl PongPlugin {
+    pub fn new() -> PongPlugin {
+        PongPlugin::default()
+    }
+
+    /// Everything a settings file has, replaces the rules, controllers and theme set before
+    pub fn settings(mut self, settings: Settings) -> PongPlugin {
+        self.settings = settings;
+        self
+    }
+
+    pub fn rules(mut self, rules: MatchRules) -> PongPlugin {
+        self.settings.rules = rules;
+        self
+    }
+
+    pub fn controllers(mut self, controllers: Controllers) -> PongPlugin {
+        self.settings.controllers = controllers;
+        self
+    }
+
+    /// Built-in theme name or asset path of a `.theme.ron` file
+    pub fn theme(mut self, theme: impl Into<String>) -> PongPlugin {
+        self.settings.theme = Some(theme.into());
+        self
+    }
+
+    /// Seed of the first match, an arbitrary one if not given
+    pub fn seed(mut self, seed: u64) -> PongPlugin {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// State the game goes back to when a match is over or left, see `RootState`
+    pub fn root_state(mut self, state: AppState) -> PongPlugin {
+        self.root_state = state;
+        self
+    }
+
+    /// State the game starts in, the root state if not given
+    pub fn start_state(mut self, state: AppState) -> PongPlugin {
+        self.start_state = Some(state);
+        self
+    }
+
+    /// Nothing shown or played, the match runs on its own and still counts in the statistics
+    pub fn headless(mut self) -> PongPlugin {
+        self.windowed = false;
+        self
+    }
+
+    /// Where match statistics are kept instead of the data directory
+    pub fn stats_file(mut self, path: PathBuf) -> PongPlugin {
+        self.stats_file = Some(path);
+        self
+    }
+
+    /// Settings file the volume changes made in the game are saved to
+    pub fn config(mut self, path: PathBuf) -> PongPlugin {
+        self.config = Some(path);
+        self
+    }
+}
+
+impl Plugin for PongPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = &self.settings;
+        // Before the plugins, some add systems that run on entering it
+        app.insert_resource(RootState(self.root_state.clone()));
+        if self.windowed {
+            app
+                .add_plugin(ThemePlugin { theme: settings.theme.clone().unwrap_or_else(|| "default".to_string()) })
+                .add_plugin(VisualsPlugin { view: settings.window.view })
+                .add_plugin(ScalingPlugin { ui_scale: settings.window.ui_scale })
+                .add_plugin(AccessibilityPlugin)
+                .add_plugin(CameraPlugin { settings: settings.camera })
+                .add_plugin(ParticlesPlugin)
+                .add_plugin(TrailPlugin)
+                .add_plugin(TitlePlugin)
+                .add_plugin(HudPlugin)
+                .add_plugin(RecordsPlugin)
+                .add_plugin(LanPlugin)
+                .add_plugin(SavePlugin)
+                .add_plugin(PausePlugin)
+                .add_plugin(GoalReplayPlugin)
+                .add_plugin(MixerPlugin { settings: settings.audio, config: self.config.clone() });
+            #[cfg(feature = "audio")]
+            app.add_plugin(SoundPlugin);
+        }
+        let seed = self.seed.unwrap_or_else(rand::random);
+        info!("Random seed {}", seed);
+        let locale = Locale::new(settings.locale.as_deref());
+        info!("Using locale '{}'", locale.language());
+
+        app.add_event::<PongEvent>()
+            .init_resource::<Score>()
+            .insert_resource(settings.rules.clone())
+            .insert_resource(settings.controllers)
+            .insert_resource(settings.players.clone())
+            .insert_resource(locale)
+            .insert_resource(settings.accessibility.clone())
+            .insert_resource(GameRng(ChaCha8Rng::seed_from_u64(seed)))
+            .insert_resource(MatchSeed::new(seed))
+            .insert_resource(settings.clone())
+            .add_startup_system(setup)
+            .add_plugin(GamePlugin)
+            // Headless and embedded matches count too
+            .add_plugin(StatsPlugin { path: self.stats_file.clone() })
+            .add_plugin(NetPlugin { windowed: self.windowed })
+            .add_plugin(AnnouncePlugin)
+            .add_plugin(BotPlugin)
+            .add_plugin(ReplayPlugin { windowed: self.windowed })
+            .add_state(self.start_state.clone().unwrap_or_else(|| self.root_state.clone()));
+    }
+}
@@ -0,0 +1,100 @@
+use bevy::prelude::*;
+use crate::locale::Locale;
+use crate::stats::Stats;
+use crate::theme::{ActiveTheme, Themed};
+use crate::types::*;
+
+const LEADERBOARD_SIZE: usize = 10;
+
+pub struct RecordsPlugin;
+
+impl Plugin for RecordsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system_set(SystemSet::on_enter(AppState::Records).with_system(records_enter))
+            .add_system_set(SystemSet::on_update(AppState::Records).with_system(records_update))
+            .add_system_set(SystemSet::on_exit(AppState::Records).with_system(records_exit));
+    }
+}
+
+pub fn format_duration(seconds: f32) -> String {
+    format!("{}:{:02}", seconds as u32 / 60, seconds as u32 % 60)
+}
+
+fn leaderboard_text(stats: &Stats, locale: &Locale) -> String {
+    let leaderboard = stats.leaderboard();
+    if leaderboard.is_empty() {
+        return locale.get("records-empty");
+    }
+    let mut text = format!("{:<12}{:>5}{:>6}{:>7}{:>7}{:>9}\n",
+                           locale.get("records-player"), locale.get("records-won"), locale.get("records-lost"),
+                           locale.get("records-goals"), locale.get("records-rally"), locale.get("records-fastest"));
+    for profile in leaderboard.iter().take(LEADERBOARD_SIZE) {
+        let name: String = profile.name.chars().take(11).collect();
+        let fastest = profile.fastest_win.map_or("-".to_string(), format_duration);
+        text += &format!("\n{:<12}{:>5}{:>6}{:>7}{:>7}{:>9}",
+                         name, profile.wins, profile.losses, profile.goals, profile.longest_rally, fastest);
+    }
+    text += &format!("\n\n{}", locale.format("records-matches", &[("count", &stats.matches.len().to_string())]));
+    text
+}
+
+fn records_enter(mut commands: Commands, theme: ActiveTheme, stats: Res<Stats>, locale: Res<Locale>) {
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        },
+        color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+        ..Default::default()
+    })
+    .insert(RecordsText)
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: format!("{}\n", locale.get("records")),
+                        style: theme.text_style(70.0)
+                    },
+                    TextSection {
+                        value: leaderboard_text(&stats, &locale),
+                        style: theme.text_style(30.0)
+                    },
+                    TextSection {
+                        value: format!("\n\n{}", locale.get("records-back")),
+                        style: theme.secondary_text_style(30.0)
+                    }
+                ],
+                alignment: TextAlignment::TOP_CENTER
+            },
+            ..Default::default()
+        })
+        .insert(Themed::Text { primary: 2 });
+    });
+}
+
+fn records_update(mut state: ResMut<State<AppState>>,
+                  keyboard_input: Res<Input<KeyCode>>,
+                  gamepads: Res<Gamepads>,
+                  gamepad_buttons: Res<Input<GamepadButton>>) {
+    let mut back = keyboard_input.any_just_released([KeyCode::Space, KeyCode::Escape]);
+    for gamepad in gamepads.iter() {
+        if gamepad_buttons.any_just_released([GamepadButton::new(*gamepad, GamepadButtonType::South),
+                                              GamepadButton::new(*gamepad, GamepadButtonType::East)]) {
+            back = true;
+        }
+    }
+    if back {
+        state.set(AppState::Title).unwrap();
+    }
+}
+
+fn records_exit(mut commands: Commands, records_text_query: Query<Entity, With<RecordsText>>) {
+    for e in records_text_query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
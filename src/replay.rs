@@ -0,0 +1,755 @@
+use std::{fmt, fs, io, path::{Path, PathBuf}, time::Duration};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::accessibility::AccessibilitySettings;
+use crate::config::{data_dir, Settings};
+use crate::game::{apply_next_state, play_tick};
+use crate::locale::Locale;
+use crate::net::{tick_duration, NetSession, MAX_CATCH_UP, TICK_RATE};
+use crate::records::format_duration;
+use crate::stats::unix_time;
+use crate::theme::{ActiveTheme, Themed};
+use crate::types::*;
+
+const REPLAY_DIR: &str = "replays";
+/// Bump whenever `Replay` or the simulation changes so that old inputs play a different match
+pub const REPLAY_VERSION: u32 = 2;
+/// The oldest replays are removed beyond this many
+const REPLAYS_KEPT: usize = 50;
+const SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+const NORMAL_SPEED: usize = 2;
+const SEEK_TICKS: usize = 5 * TICK_RATE as usize;
+
+/// Everything needed to play a match again: the simulation is deterministic, so the seed,
+/// the setup and the inputs of every tick give the same match
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    /// Seconds since the Unix epoch
+    pub recorded_at: u64,
+    pub seed: u64,
+    pub rules: MatchRules,
+    pub names: PlayerNames,
+    pub game_speed: f32,
+    /// How the match ended, for the list of replays
+    pub score: Score,
+    /// Paddle inputs of the left and right player from the first tick on, each repeated for the given number of ticks
+    inputs: Vec<(u32, [i8; 2])>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, rules: MatchRules, names: PlayerNames, game_speed: f32) -> Replay {
+        Replay {
+            version: REPLAY_VERSION,
+            recorded_at: 0,
+            seed,
+            rules,
+            names,
+            game_speed,
+            score: Score::default(),
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, inputs: [i8; 2]) {
+        match self.inputs.last_mut() {
+            Some((count, last)) if *last == inputs => *count += 1,
+            _ => self.inputs.push((1, inputs)),
+        }
+    }
+
+    /// The inputs of every tick
+    pub fn inputs(&self) -> Vec<[i8; 2]> {
+        self.inputs.iter()
+            .flat_map(|(count, inputs)| std::iter::repeat_n(*inputs, *count as usize))
+            .collect()
+    }
+
+    pub fn ticks(&self) -> usize {
+        self.inputs.iter().map(|(count, _)| *count as usize).sum()
+    }
+}
+
+/// Only the version, read first so that other changes to the format cannot fail the check
+#[derive(Deserialize)]
+struct ReplayHeader {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    Version(u32),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "could not read the replay: {}", e),
+            ReplayError::Parse(e) => write!(f, "the replay is damaged: {}", e),
+            ReplayError::Version(version) => write!(f, "the replay was recorded with an incompatible version of the game (replay version {}, this version plays {})", version, REPLAY_VERSION),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+    let content = fs::read_to_string(path).map_err(ReplayError::Io)?;
+    let header: ReplayHeader = ron::from_str(&content).map_err(ReplayError::Parse)?;
+    if header.version != REPLAY_VERSION {
+        return Err(ReplayError::Version(header.version));
+    }
+    ron::from_str(&content).map_err(ReplayError::Parse)
+}
+
+/// A replay file, or why it cannot be played
+pub struct ReplayEntry {
+    pub path: PathBuf,
+    pub replay: Result<Replay, ReplayError>,
+}
+
+/// Where replays are kept, one file each
+pub struct ReplayDir {
+    path: Option<PathBuf>,
+}
+
+impl ReplayDir {
+    pub fn new(path: Option<PathBuf>) -> ReplayDir {
+        ReplayDir { path }
+    }
+
+    /// Replay files, newest first
+    fn files(&self) -> Vec<PathBuf> {
+        let entries = match self.path.as_ref().map(fs::read_dir) {
+            Some(Ok(entries)) => entries,
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => {
+                warn!("Could not list the replays: {}", e);
+                return Vec::new();
+            },
+            _ => return Vec::new(),
+        };
+        let mut files: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "ron"))
+            .collect();
+        // The names start with the time of recording
+        files.sort();
+        files.reverse();
+        files
+    }
+
+    pub fn list(&self) -> Vec<ReplayEntry> {
+        self.files().into_iter()
+            .map(|path| {
+                let replay = load(&path);
+                ReplayEntry { path, replay }
+            })
+            .collect()
+    }
+
+    /// Writes the replay and removes the oldest ones beyond `REPLAYS_KEPT`
+    pub fn save(&self, replay: &Replay) -> Option<PathBuf> {
+        let dir = self.path.as_ref()?;
+        let path = (0..)
+            .map(|index| dir.join(format!("replay-{:012}-{:03}.ron", replay.recorded_at, index)))
+            .find(|path| !path.exists())?;
+        let result = ron::to_string(replay)
+            .map_err(io::Error::other)
+            .and_then(|content| {
+                fs::create_dir_all(dir)?;
+                fs::write(&path, content)
+            });
+        if let Err(e) = result {
+            warn!("Could not save the replay to {}: {}", path.display(), e);
+            return None;
+        }
+        info!("Replay saved to {}", path.display());
+        for old in self.files().into_iter().skip(REPLAYS_KEPT) {
+            if let Err(e) = fs::remove_file(&old) {
+                warn!("Could not remove old replay {}: {}", old.display(), e);
+            }
+        }
+        Some(path)
+    }
+}
+
+/// The replay of the match being played
+pub struct Recorder(Replay);
+
+/// Adds the inputs of a tick of the match to its replay, if it is being recorded
+pub fn record_tick(world: &mut World, inputs: [i8; 2]) {
+    if let Some(mut recorder) = world.get_resource_mut::<Recorder>() {
+        recorder.0.push(inputs);
+    }
+}
+
+/// A replay being played, the simulation takes its inputs from here instead of the players
+pub struct Playback {
+    replay: Replay,
+    inputs: Vec<[i8; 2]>,
+    /// Ticks played so far
+    tick: usize,
+    paused: bool,
+    /// Index to `SPEEDS`
+    speed: usize,
+    /// Tick to play up to regardless of the clock, when seeking or stepping
+    target: Option<usize>,
+    clock: Duration,
+    /// Played to the end, the last tick stays on screen
+    ended: bool,
+    /// Start over from the new game to seek back
+    restart: bool,
+    /// The seed of the next match once the replay is over
+    seed_after: u64,
+}
+
+impl Playback {
+    pub fn new(replay: Replay, seed_after: u64) -> Playback {
+        let inputs = replay.inputs();
+        Playback {
+            replay,
+            inputs,
+            tick: 0,
+            paused: false,
+            speed: NORMAL_SPEED,
+            target: None,
+            clock: Duration::ZERO,
+            ended: false,
+            restart: false,
+            seed_after,
+        }
+    }
+
+    pub fn ended(&self) -> bool {
+        self.ended
+    }
+
+    /// Plays up to `target` as fast as possible. There is no going back in the simulation,
+    /// for an earlier tick the replay starts over.
+    pub fn seek(&mut self, target: usize) {
+        let target = target.min(self.inputs.len());
+        self.restart = target < self.tick;
+        self.target = Some(target);
+        self.ended = false;
+    }
+}
+
+/// Plays the ticks of the replay that its clock has reached, or that seeking asks for. Ticks
+/// end at state changes like in `game::run_local`, so the replay plays just as the match did.
+pub fn run_playback(stage: &mut SystemStage, world: &mut World) {
+    if apply_next_state(world) {
+        return;
+    }
+    let delta = world.resource::<Time>().delta();
+    world.resource_scope(|world, mut playback: Mut<Playback>| {
+        if playback.restart {
+            playback.restart = false;
+            playback.tick = 0;
+            world.resource_mut::<MatchSeed>().next = playback.replay.seed;
+            world.resource_mut::<NextState>().0 = None;
+            let mut state = world.resource_mut::<State<AppState>>();
+            if *state.current() == AppState::NewGame {
+                state.overwrite_restart();
+            } else {
+                state.overwrite_set(AppState::NewGame).unwrap();
+            }
+            return;
+        }
+        if playback.ended {
+            return;
+        }
+        // The new game only sets the match up, its tick is not recorded
+        if *world.resource::<State<AppState>>().current() == AppState::NewGame {
+            play_tick(stage, world, [0, 0]);
+            apply_next_state(world);
+            return;
+        }
+        let tick = tick_duration();
+        if playback.target.is_none() {
+            if playback.paused {
+                return;
+            }
+            let speed = SPEEDS[playback.speed];
+            playback.clock = (playback.clock + delta.mul_f32(speed)).min(tick.mul_f32(speed) * MAX_CATCH_UP);
+        }
+        // Seeking is not worth hearing or seeing, unlike a single step
+        let quiet = playback.target.is_some_and(|target| target > playback.tick + 1);
+        loop {
+            let due = match playback.target {
+                Some(target) => playback.tick < target,
+                None => playback.clock >= tick,
+            };
+            let inputs = match playback.inputs.get(playback.tick) {
+                Some(inputs) if due => *inputs,
+                Some(_) => break,
+                None => {
+                    playback.ended = true;
+                    break;
+                }
+            };
+            if playback.target.is_none() {
+                playback.clock -= tick;
+            }
+            playback.tick += 1;
+            play_tick(stage, world, inputs);
+            if quiet {
+                world.resource_mut::<Events<PongEvent>>().clear();
+            }
+            // The match is over, but the replay stays until left
+            if world.resource::<NextState>().0 == Some(world.resource::<RootState>().0.clone()) {
+                world.resource_mut::<NextState>().0 = None;
+                playback.ended = true;
+                break;
+            }
+            if apply_next_state(world) {
+                break;
+            }
+        }
+        if playback.ended || playback.target.is_some_and(|target| playback.tick >= target) {
+            playback.target = None;
+        }
+    });
+}
+
+/// Records every match played here, and plays replays back
+pub struct ReplayPlugin {
+    /// Add the replays screen, there is nothing to show it on when running headless
+    pub windowed: bool,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let root = RootState::of(app);
+        app
+            .insert_resource(ReplayDir::new(data_dir().map(|dir| dir.join(REPLAY_DIR))))
+            .add_system_set(SystemSet::on_exit(AppState::NewGame).with_system(start_recording))
+            .add_system_set(SystemSet::on_enter(root).with_system(save_recording));
+        if self.windowed {
+            app
+                .add_system_set(SystemSet::on_enter(AppState::Replays).with_system(replays_enter))
+                .add_system_set(SystemSet::on_update(AppState::Replays).with_system(replays_update))
+                .add_system_set(SystemSet::on_exit(AppState::Replays).with_system(replays_exit))
+                .add_system(playback_input)
+                .add_system(playback_text.after(playback_input));
+        }
+    }
+}
+
+fn finish_recording(recorder: &Recorder, score: &Score, replay_dir: &ReplayDir) {
+    if recorder.0.ticks() == 0 {
+        return;
+    }
+    let replay = Replay { recorded_at: unix_time(), score: score.clone(), ..recorder.0.clone() };
+    replay_dir.save(&replay);
+}
+
+/// Starts once the new game has set the match up. A spectator only sees snapshots, there are no inputs to record.
+fn start_recording(mut commands: Commands,
+                   recorder: Option<Res<Recorder>>,
+                   replay_dir: Res<ReplayDir>,
+                   playback: Option<Res<Playback>>,
+                   session: Option<Res<NetSession>>,
+                   match_seed: Res<MatchSeed>,
+                   score: Res<Score>,
+                   rules: Res<MatchRules>,
+                   names: Res<PlayerNames>,
+                   accessibility: Res<AccessibilitySettings>) {
+    // Started over, the match so far is kept as it was left
+    if let Some(recorder) = recorder {
+        finish_recording(&recorder, &score, &replay_dir);
+        commands.remove_resource::<Recorder>();
+    }
+    if playback.is_some() || session.is_some_and(|session| session.follows_snapshots()) {
+        return;
+    }
+    let replay = Replay::new(match_seed.current, rules.clone(), names.clone(), accessibility.game_speed);
+    commands.insert_resource(Recorder(replay));
+}
+
+fn save_recording(mut commands: Commands, recorder: Option<Res<Recorder>>, score: Res<Score>, replay_dir: Res<ReplayDir>) {
+    if let Some(recorder) = recorder {
+        finish_recording(&recorder, &score, &replay_dir);
+        commands.remove_resource::<Recorder>();
+    }
+}
+
+/// The replays of the replays screen
+struct ReplayList(Vec<ReplayEntry>);
+
+fn entry_text(entry: &ReplayEntry, locale: &Locale) -> String {
+    let replay = match &entry.replay {
+        Ok(replay) => replay,
+        Err(e) => {
+            let file = entry.path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+            return locale.format("replays-unplayable", &[("file", &file), ("error", &e.to_string())]);
+        }
+    };
+    let mut score = format!("{} - {}", replay.score.left, replay.score.right);
+    if replay.rules.has_sets() {
+        score = format!("{} ({} - {})", score, replay.score.left_sets, replay.score.right_sets);
+    }
+    let length = replay.ticks() as f32 / TICK_RATE as f32;
+    format!("{} {} {}  {}", replay.names.left, score, replay.names.right, format_duration(length))
+}
+
+fn list_text(list: &ReplayList, selected: usize, locale: &Locale) -> String {
+    if list.0.is_empty() {
+        return locale.get("replays-empty");
+    }
+    list.0.iter()
+        .enumerate()
+        .map(|(index, entry)| format!("{} {}", if index == selected { ">" } else { " " }, entry_text(entry, locale)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Component)]
+struct ReplayListText;
+
+/// Also where a replay returns to, with the match setup and the seed as they were before it
+fn replays_enter(mut commands: Commands,
+                 theme: ActiveTheme,
+                 locale: Res<Locale>,
+                 replay_dir: Res<ReplayDir>,
+                 playback: Option<Res<Playback>>,
+                 settings: Res<Settings>,
+                 mut match_seed: ResMut<MatchSeed>,
+                 mut rules: ResMut<MatchRules>,
+                 mut names: ResMut<PlayerNames>,
+                 mut controllers: ResMut<Controllers>,
+                 mut accessibility: ResMut<AccessibilitySettings>) {
+    if let Some(playback) = playback {
+        match_seed.next = playback.seed_after;
+        commands.remove_resource::<Playback>();
+        *rules = settings.rules.clone();
+        *names = settings.players.clone();
+        *controllers = settings.controllers;
+        accessibility.game_speed = settings.accessibility.game_speed;
+    }
+    let list = ReplayList(replay_dir.list());
+    let text = list_text(&list, 0, &locale);
+    commands.insert_resource(list);
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        },
+        color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+        ..Default::default()
+    })
+    .insert(ReplaysText)
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: format!("{}\n", locale.get("replays")),
+                        style: theme.text_style(70.0)
+                    },
+                    TextSection {
+                        value: text,
+                        style: theme.text_style(30.0)
+                    },
+                    TextSection {
+                        value: format!("\n\n{}\n{}", locale.get("replays-play"), locale.get("replays-back")),
+                        style: theme.secondary_text_style(30.0)
+                    }
+                ],
+                alignment: TextAlignment::TOP_CENTER
+            },
+            ..Default::default()
+        })
+        .insert(ReplayListText)
+        .insert(Themed::Text { primary: 2 });
+    });
+}
+
+fn replays_update(mut commands: Commands,
+                  mut state: ResMut<State<AppState>>,
+                  list: Res<ReplayList>,
+                  mut selected: Local<usize>,
+                  locale: Res<Locale>,
+                  keyboard_input: Res<Input<KeyCode>>,
+                  gamepads: Res<Gamepads>,
+                  gamepad_buttons: Res<Input<GamepadButton>>,
+                  mut match_seed: ResMut<MatchSeed>,
+                  mut rules: ResMut<MatchRules>,
+                  mut names: ResMut<PlayerNames>,
+                  mut controllers: ResMut<Controllers>,
+                  mut accessibility: ResMut<AccessibilitySettings>,
+                  mut list_query: Query<&mut Text, With<ReplayListText>>) {
+    let mut up = keyboard_input.just_pressed(KeyCode::Up);
+    let mut down = keyboard_input.just_pressed(KeyCode::Down);
+    let mut play = keyboard_input.just_released(KeyCode::Space);
+    let mut back = keyboard_input.just_released(KeyCode::Escape);
+    for gamepad in gamepads.iter() {
+        let button = |button_type| GamepadButton::new(*gamepad, button_type);
+        up |= gamepad_buttons.just_pressed(button(GamepadButtonType::DPadUp));
+        down |= gamepad_buttons.just_pressed(button(GamepadButtonType::DPadDown));
+        play |= gamepad_buttons.just_released(button(GamepadButtonType::South));
+        back |= gamepad_buttons.just_released(button(GamepadButtonType::East));
+    }
+    if up {
+        *selected = selected.saturating_sub(1);
+    }
+    if down {
+        *selected += 1;
+    }
+    *selected = (*selected).min(list.0.len().saturating_sub(1));
+
+    if back {
+        state.set(AppState::Title).unwrap();
+        return;
+    }
+    let replay = list.0.get(*selected).and_then(|entry| entry.replay.as_ref().ok());
+    if let Some(replay) = replay.filter(|_| play) {
+        info!("Playing replay {} - {}, random seed {}", replay.names.left, replay.names.right, replay.seed);
+        *rules = replay.rules.clone();
+        *names = replay.names.clone();
+        *controllers = Controllers { left: Controller::Network, right: Controller::Network };
+        accessibility.game_speed = replay.game_speed;
+        commands.insert_resource(Playback::new(replay.clone(), match_seed.next));
+        match_seed.next = replay.seed;
+        state.set(AppState::NewGame).unwrap();
+        return;
+    }
+    for mut text in list_query.iter_mut() {
+        text.sections[1].value = list_text(&list, *selected, &locale);
+    }
+}
+
+fn replays_exit(mut commands: Commands, replays_text_query: Query<Entity, With<ReplaysText>>) {
+    commands.remove_resource::<ReplayList>();
+    for e in replays_text_query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn playback_input(playback: Option<ResMut<Playback>>,
+                  mut state: ResMut<State<AppState>>,
+                  mut next_state: ResMut<NextState>,
+                  keyboard_input: Res<Input<KeyCode>>,
+                  gamepads: Res<Gamepads>,
+                  gamepad_buttons: Res<Input<GamepadButton>>) {
+    let mut playback = match playback {
+        Some(playback) if *state.current() != AppState::Replays => playback,
+        _ => return,
+    };
+    let mut pause = keyboard_input.just_released(KeyCode::Space);
+    let mut step = keyboard_input.just_released(KeyCode::S);
+    let mut back = keyboard_input.just_pressed(KeyCode::Left);
+    let mut forward = keyboard_input.just_pressed(KeyCode::Right);
+    let mut faster = keyboard_input.just_pressed(KeyCode::Up);
+    let mut slower = keyboard_input.just_pressed(KeyCode::Down);
+    let mut leave = keyboard_input.just_released(KeyCode::Escape);
+    for gamepad in gamepads.iter() {
+        let button = |button_type| GamepadButton::new(*gamepad, button_type);
+        pause |= gamepad_buttons.just_released(button(GamepadButtonType::South));
+        step |= gamepad_buttons.just_released(button(GamepadButtonType::West));
+        back |= gamepad_buttons.just_pressed(button(GamepadButtonType::DPadLeft));
+        forward |= gamepad_buttons.just_pressed(button(GamepadButtonType::DPadRight));
+        faster |= gamepad_buttons.just_pressed(button(GamepadButtonType::DPadUp));
+        slower |= gamepad_buttons.just_pressed(button(GamepadButtonType::DPadDown));
+        leave |= gamepad_buttons.just_released(button(GamepadButtonType::East));
+    }
+
+    if leave {
+        next_state.0 = None;
+        state.overwrite_set(AppState::Replays).unwrap();
+        return;
+    }
+    if pause {
+        playback.paused = !playback.paused;
+        playback.clock = Duration::ZERO;
+    }
+    if faster {
+        playback.speed = (playback.speed + 1).min(SPEEDS.len() - 1);
+    }
+    if slower {
+        playback.speed = playback.speed.saturating_sub(1);
+    }
+    // Steps go on from a seek that has not finished
+    let from = playback.target.unwrap_or(playback.tick);
+    if step {
+        playback.paused = true;
+        playback.seek(from + 1);
+    } else if back {
+        playback.seek(from.saturating_sub(SEEK_TICKS));
+    } else if forward {
+        playback.seek(from + SEEK_TICKS);
+    }
+}
+
+fn playback_text(mut commands: Commands,
+                 theme: ActiveTheme,
+                 locale: Res<Locale>,
+                 playback: Option<Res<Playback>>,
+                 state: Res<State<AppState>>,
+                 mut text_query: Query<(Entity, &mut Text), With<PlaybackText>>) {
+    let playback = match playback {
+        Some(playback) if *state.current() != AppState::Replays => playback,
+        _ => {
+            for (e, _) in text_query.iter() {
+                commands.entity(e).despawn_recursive();
+            }
+            return;
+        }
+    };
+    let seconds = |ticks: usize| ticks as f32 / TICK_RATE as f32;
+    let mut status = locale.format("playback", &[("time", &format_duration(seconds(playback.tick))),
+                                                 ("length", &format_duration(seconds(playback.inputs.len()))),
+                                                 ("speed", &SPEEDS[playback.speed].to_string())]);
+    if playback.ended {
+        status += &format!("  {}", locale.get("playback-ended"));
+    } else if playback.paused {
+        status += &format!("  {}", locale.get("playback-paused"));
+    }
+    if let Some((_, mut text)) = text_query.iter_mut().next() {
+        text.sections[0].value = status;
+        return;
+    }
+    commands.spawn_bundle(TextBundle::from_sections([
+        TextSection::new(status, theme.text_style(25.0)),
+        TextSection::new(format!("\n{}", locale.get("playback-controls")), theme.secondary_text_style(20.0)),
+    ])
+    .with_text_alignment(TextAlignment::BOTTOM_CENTER)
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        position: UiRect { bottom: Val::Percent(2.0), left: Val::Percent(2.0), ..Default::default() },
+        ..Default::default()
+    }))
+    .insert(PlaybackText)
+    .insert(Themed::Text { primary: 1 });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use bevy::input::InputPlugin;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::game::GamePlugin;
+    use crate::locale::FALLBACK_LANGUAGE;
+    use crate::net::NetInputs;
+    use crate::setup::setup;
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bevy-pong-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// The game without anything to show it, each update playing one tick
+    fn match_app(controllers: Controllers, seed: u64) -> App {
+        let mut app = App::new();
+        app.insert_resource(Time::default())
+            .add_plugin(InputPlugin)
+            .add_event::<PongEvent>()
+            .init_resource::<Score>()
+            .insert_resource(MatchRules { goals_to_win: 1, ..Default::default() })
+            .insert_resource(controllers)
+            .insert_resource(PlayerNames::default())
+            .insert_resource(Locale::new(Some(FALLBACK_LANGUAGE)))
+            .insert_resource(AccessibilitySettings::default())
+            .insert_resource(GameRng(ChaCha8Rng::seed_from_u64(seed)))
+            .insert_resource(MatchSeed::new(seed))
+            .add_startup_system(setup)
+            .add_plugin(GamePlugin)
+            .add_plugin(ReplayPlugin { windowed: false })
+            .add_state(AppState::NewGame);
+        app
+    }
+
+    /// Plays until `done`, returning the final score and where everything is
+    fn play(app: &mut App, mut each_tick: impl FnMut(&mut App, u32), done: impl Fn(&mut App) -> bool) -> (Score, Vec<Vec3>) {
+        let started = Instant::now();
+        app.world.resource_mut::<Time>().update_with_instant(started);
+        for tick in 0..60 * TICK_RATE {
+            each_tick(app, tick);
+            app.world.resource_mut::<Time>().update_with_instant(started + tick_duration() * (tick + 1));
+            app.update();
+            if done(app) {
+                let mut positions: Vec<_> = app.world.query_filtered::<&Transform, Or<(With<Ball>, With<Paddle>)>>()
+                    .iter(&app.world)
+                    .map(|transform| transform.translation)
+                    .collect();
+                positions.sort_by(|a, b| a.to_array().partial_cmp(&b.to_array()).unwrap());
+                return (app.world.resource::<Score>().clone(), positions);
+            }
+        }
+        panic!("the match did not end");
+    }
+
+    #[test]
+    fn inputs_are_kept_in_runs() {
+        let mut replay = Replay::new(1, MatchRules::default(), PlayerNames::default(), 1.0);
+        let inputs = [[0, 0], [0, 0], [127, 0], [127, 0], [127, 0], [0, -127], [0, 0]];
+        for tick_inputs in inputs {
+            replay.push(tick_inputs);
+        }
+        assert_eq!(replay.inputs.len(), 4);
+        assert_eq!(replay.ticks(), inputs.len());
+        assert_eq!(replay.inputs(), inputs);
+    }
+
+    #[test]
+    fn replays_of_other_versions_are_refused() {
+        let dir = temp_dir("old-replays");
+        let replay_dir = ReplayDir::new(Some(dir.clone()));
+        let mut replay = Replay::new(1, MatchRules::default(), PlayerNames::default(), 1.0);
+        replay.push([0, 0]);
+        let path = replay_dir.save(&replay).unwrap();
+        let old = fs::read_to_string(&path).unwrap().replace(&format!("version:{}", REPLAY_VERSION), "version:0");
+        fs::write(&path, old).unwrap();
+
+        let entries = replay_dir.list();
+        assert_eq!(entries.len(), 1);
+        match &entries[0].replay {
+            Err(e @ ReplayError::Version(0)) => assert!(e.to_string().contains("incompatible version")),
+            other => panic!("expected a version error, got {:?}", other.as_ref().map(|_| ())),
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn playback_plays_the_recorded_match() {
+        let dir = temp_dir("replays");
+        let mut recording = match_app(Controllers { left: Controller::Cpu, right: Controller::Network }, 5);
+        recording.insert_resource(ReplayDir::new(Some(dir.clone())));
+        // Someone on the right who does not quite keep up
+        let recorded = play(&mut recording,
+                            |app, tick| app.world.resource_mut::<NetInputs>().0[1] = if tick % 90 < 45 { 100 } else { -60 },
+                            |app| *app.world.resource::<State<AppState>>().current() == AppState::Title);
+
+        let entries = ReplayDir::new(Some(dir.clone())).list();
+        assert_eq!(entries.len(), 1);
+        let replay = entries.into_iter().next().unwrap().replay.unwrap();
+        assert_eq!(replay.score, recorded.0);
+
+        // Started from a different seed, the replay brings its own
+        let mut playback = match_app(Controllers { left: Controller::Network, right: Controller::Network }, 99);
+        playback.insert_resource(ReplayDir::new(Some(dir.clone())));
+        playback.world.resource_mut::<MatchSeed>().next = replay.seed;
+        playback.insert_resource(Playback::new(replay, 99));
+        let played = play(&mut playback, |_, _| (), |app| app.world.resource::<Playback>().ended());
+        assert_eq!(played, recorded);
+
+        // Seeking back starts over and plays up to the tick sought
+        let mut replay = playback.world.resource_mut::<Playback>();
+        let target = replay.tick / 2;
+        replay.seek(target);
+        let sought = play(&mut playback, |_, _| (), |app| app.world.resource::<Playback>().target.is_none());
+        assert_ne!(sought, recorded);
+        let played_again = play(&mut playback, |_, _| (), |app| app.world.resource::<Playback>().ended());
+        assert_eq!(played_again, recorded);
+        // Nothing new was recorded
+        assert_eq!(ReplayDir::new(Some(dir.clone())).list().len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
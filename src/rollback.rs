@@ -0,0 +1,281 @@
+use bevy::{
+    prelude::*,
+    ecs::event::ManualEventReader
+};
+use serde::Serialize;
+use crate::bot::send_bot_state;
+use crate::game::{apply_next_state, play_tick};
+use crate::net::NetSession;
+use crate::replay::record_tick;
+use crate::server::MatchSnapshot;
+use crate::types::*;
+
+/// Everything a tick of the simulation can change, enough to play ticks again from here
+#[derive(Clone)]
+pub struct Snapshot {
+    /// Transform and velocity of the ball and the paddles, in that order
+    bodies: Vec<(Entity, Transform, Vec3)>,
+    score: Score,
+    serve: Player,
+    rng: GameRng,
+    phase_timer: Timer,
+    state: AppState,
+}
+
+impl Snapshot {
+    pub fn capture(world: &mut World) -> Snapshot {
+        let mut bodies: Vec<_> = world.query::<(Entity, &Transform, &Moving, Option<&Paddle>)>()
+            .iter(world)
+            .map(|(entity, transform, moving, paddle)| {
+                let order = match paddle {
+                    None => 0,
+                    Some(Paddle(Player::Left)) => 1,
+                    Some(Paddle(Player::Right)) => 2,
+                };
+                (order, (entity, *transform, moving.velocity))
+            })
+            .collect();
+        bodies.sort_by_key(|(order, _)| *order);
+        Snapshot {
+            bodies: bodies.into_iter().map(|(_, body)| body).collect(),
+            score: world.resource::<Score>().clone(),
+            serve: world.resource::<Serve>().0,
+            rng: world.resource::<GameRng>().clone(),
+            phase_timer: world.resource::<PhaseTimer>().0.clone(),
+            state: world.resource::<State<AppState>>().current().clone(),
+        }
+    }
+
+    /// Puts the world back as it was, dropping any state change the simulation asked for since
+    pub fn restore(&self, world: &mut World) {
+        for (entity, transform, velocity) in &self.bodies {
+            if let Some(mut current) = world.get_mut::<Transform>(*entity) {
+                *current = *transform;
+            }
+            if let Some(mut moving) = world.get_mut::<Moving>(*entity) {
+                moving.velocity = *velocity;
+            }
+        }
+        *world.resource_mut::<Score>() = self.score.clone();
+        world.resource_mut::<Serve>().0 = self.serve;
+        *world.resource_mut::<GameRng>() = self.rng.clone();
+        world.resource_mut::<PhaseTimer>().0 = self.phase_timer.clone();
+        world.resource_mut::<NextState>().0 = None;
+        let mut state = world.resource_mut::<State<AppState>>();
+        if *state.current() != self.state {
+            state.overwrite_set(self.state.clone()).unwrap();
+        }
+    }
+
+    /// Same on both peers as long as they play the same match, whatever they were built with.
+    /// Entities are left out, their ids depend on what else each peer has spawned.
+    pub fn checksum(&self) -> u64 {
+        let checked = Checked {
+            bodies: self.bodies.iter()
+                .map(|(_, transform, velocity)| {
+                    let values = transform.translation.to_array().into_iter().chain(velocity.to_array());
+                    values.map(f32::to_bits).collect()
+                })
+                .collect(),
+            score: [self.score.left, self.score.right, self.score.left_sets, self.score.right_sets],
+            time: self.score.time.to_bits(),
+            serve: self.serve,
+            rng_word_pos: self.rng.get_word_pos() as u64,
+            phase_elapsed: self.phase_timer.elapsed().as_nanos() as u64,
+            state: &self.state,
+        };
+        fnv1a(ron::to_string(&checked).unwrap_or_default().as_bytes())
+    }
+}
+
+/// What the checksum covers, floats by their bits
+#[derive(Serialize)]
+struct Checked<'a> {
+    bodies: Vec<Vec<u32>>,
+    score: [u32; 4],
+    time: u32,
+    serve: Player,
+    rng_word_pos: u64,
+    phase_elapsed: u64,
+    state: &'a AppState,
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// 64-bit FNV-1a. Unlike the standard library's hashers it gives the same hash on every
+/// platform and Rust release, peers built differently still agree.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME))
+}
+
+/// The last confirmed tick, while the ticks after it are shown with a guess of the other player's input
+#[derive(Default)]
+pub struct Rollback {
+    confirmed: Option<Snapshot>,
+    /// Events of the confirmed ticks that spectators have been sent
+    spectator_events: ManualEventReader<PongEvent>,
+}
+
+/// Plays the ticks of an online match. The confirmed ticks, with the inputs of both players,
+/// go first from the last confirmed state. With rollback the ticks the local clock has reached
+/// after them are then played with a guess of the other player's input and thrown away again
+/// next frame. Events and state changes only come from confirmed ticks, so everything outside
+/// the simulation sees each of them once. A match on a server, or watched as a spectator, is
+/// shown as the server or host sends it.
+pub fn run_online(stage: &mut SystemStage, world: &mut World) {
+    if world.resource::<NetSession>().follows_snapshots() {
+        // The lobby applies the match setup first
+        if *world.resource::<State<AppState>>().current() != AppState::Lobby {
+            if let Some(snapshot) = world.resource_mut::<NetSession>().take_snapshot() {
+                snapshot.apply(world);
+            }
+        }
+        return;
+    }
+    if let Some(confirmed) = world.resource_mut::<Rollback>().confirmed.take() {
+        confirmed.restore(world);
+    }
+    let mut confirmed_tick = None;
+    let mut state_changed = false;
+    loop {
+        let (tick, inputs) = {
+            let mut session = world.resource_mut::<NetSession>();
+            let tick = session.tick();
+            match session.confirm() {
+                Some(inputs) => (tick, inputs),
+                None => break,
+            }
+        };
+        record_tick(world, inputs);
+        play_tick(stage, world, inputs);
+        send_bot_state(world);
+        world.resource_scope(|world, mut session: Mut<NetSession>| {
+            session.record_checksum(tick, || Snapshot::capture(world).checksum());
+        });
+        confirmed_tick = Some(tick);
+        // The enter and exit systems run on the confirmed state, there is no guessing past them
+        if apply_next_state(world) {
+            state_changed = true;
+            break;
+        }
+    }
+    if let Some(tick) = confirmed_tick {
+        send_to_spectators(world, tick);
+    }
+    if state_changed {
+        return;
+    }
+
+    // Only the paddles depend on the input, nothing is worth guessing outside of play
+    let predicted = world.resource::<NetSession>().predicted();
+    if predicted.is_empty() || *world.resource::<State<AppState>>().current() != AppState::InGame {
+        return;
+    }
+    let confirmed = Snapshot::capture(world);
+    let events = std::mem::take(&mut *world.resource_mut::<Events<PongEvent>>());
+    for inputs in predicted {
+        let before = Snapshot::capture(world);
+        play_tick(stage, world, inputs);
+        if world.resource::<NextState>().0.is_some() {
+            before.restore(world);
+            break;
+        }
+    }
+    *world.resource_mut::<Events<PongEvent>>() = events;
+    world.resource_mut::<Rollback>().confirmed = Some(confirmed);
+}
+
+/// Spectators see the match as of the last confirmed tick, never a guess
+fn send_to_spectators(world: &mut World, tick: u32) {
+    let events: Vec<PongEvent> = world.resource_scope(|world, mut rollback: Mut<Rollback>| {
+        rollback.spectator_events.iter(world.resource::<Events<PongEvent>>()).cloned().collect()
+    });
+    if world.resource::<NetSession>().has_spectators() {
+        let snapshot = MatchSnapshot::capture(world, tick, events);
+        world.resource::<NetSession>().send_to_spectators(&snapshot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::accessibility::AccessibilitySettings;
+    use crate::game::simulation_stage;
+    use crate::net::NetInputs;
+    use crate::setup::setup;
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.insert_resource(State::new(AppState::InGame));
+        world.insert_resource(Input::<KeyCode>::default());
+        world.insert_resource(Gamepads::default());
+        world.insert_resource(Axis::<GamepadAxis>::default());
+        world.insert_resource(Controllers { left: Controller::Network, right: Controller::Network });
+        world.insert_resource(NetInputs::default());
+        world.insert_resource(SimTime::default());
+        world.insert_resource(AccessibilitySettings::default());
+        world.insert_resource(MatchRules::default());
+        world.insert_resource(Score::default());
+        world.insert_resource(Serve(Player::Left));
+        world.insert_resource(GameRng(ChaCha8Rng::seed_from_u64(3)));
+        world.insert_resource(PhaseTimer::default());
+        world.insert_resource(NextState::default());
+        world.insert_resource(Events::<PongEvent>::default());
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(setup);
+        stage.run(&mut world);
+        world
+    }
+
+    fn input(tick: usize) -> [i8; 2] {
+        [if tick % 20 < 10 { 127 } else { -127 }, if tick % 14 < 7 { -90 } else { 60 }]
+    }
+
+    #[test]
+    fn fnv1a_is_the_standard_one() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn restore_undoes_ticks() {
+        let mut world = world();
+        let mut stage = simulation_stage();
+        let before = Snapshot::capture(&mut world);
+        for tick in 0..30 {
+            play_tick(&mut stage, &mut world, input(tick));
+        }
+        assert_ne!(Snapshot::capture(&mut world).checksum(), before.checksum());
+        before.restore(&mut world);
+        assert_eq!(Snapshot::capture(&mut world).checksum(), before.checksum());
+    }
+
+    #[test]
+    fn replaying_after_a_wrong_guess_matches_playing_straight() {
+        let mut straight = world();
+        let mut stage = simulation_stage();
+        let expected: Vec<_> = (0..40)
+            .map(|tick| {
+                play_tick(&mut stage, &mut straight, input(tick));
+                Snapshot::capture(&mut straight).checksum()
+            })
+            .collect();
+
+        let mut rolled_back = world();
+        let mut stage = simulation_stage();
+        for (tick, expected) in expected.into_iter().enumerate() {
+            // Guess wrong a few ticks ahead, then go back and play the real input
+            let confirmed = Snapshot::capture(&mut rolled_back);
+            for guess in 0..4 {
+                play_tick(&mut stage, &mut rolled_back, input(tick + guess + 5));
+            }
+            confirmed.restore(&mut rolled_back);
+            play_tick(&mut stage, &mut rolled_back, input(tick));
+            assert_eq!(Snapshot::capture(&mut rolled_back).checksum(), expected, "tick {}", tick);
+        }
+    }
+}
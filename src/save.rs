@@ -0,0 +1,332 @@
+use std::{fmt, fs, io, path::PathBuf, time::Duration};
+use bevy::{
+    prelude::*,
+    app::AppExit,
+    ecs::system::SystemParam,
+    window::WindowCloseRequested
+};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use crate::config::data_dir;
+use crate::goal_replay::GoalReplay;
+use crate::net::NetSession;
+use crate::replay::Playback;
+use crate::stats::MatchTracker;
+use crate::types::*;
+
+const SAVE_FILE: &str = "save.ron";
+/// Bump whenever `SavedMatch` changes in an incompatible way
+const SAVE_VERSION: u32 = 2;
+
+/// Saves the match in progress when the game is closed and restores it on `AppState::Continue`
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(SaveFile::new(data_dir().map(|dir| dir.join(SAVE_FILE))))
+            .add_system_set(SystemSet::on_update(AppState::Continue).with_system(continue_match))
+            // After the enter systems of the phase continued from, which start it afresh
+            .add_system_to_stage(CoreStage::PostUpdate, restore_phase)
+            .add_system_set(SystemSet::on_enter(AppState::Win).with_system(remove_save))
+            // Last, so exit requests sent from any other system this frame are seen
+            .add_system_to_stage(CoreStage::Last, save_on_exit);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedBody {
+    pub position: [f32; 3],
+    pub velocity: [f32; 3],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedMatch {
+    pub version: u32,
+    /// The state the match continues from
+    pub phase: AppState,
+    pub score: Score,
+    pub rules: MatchRules,
+    pub names: PlayerNames,
+    pub controllers: Controllers,
+    pub seed: MatchSeed,
+    /// Random numbers of the match used so far, `GameRng` goes on after them
+    pub rng_word_pos: u64,
+    /// Player who served the ball in play
+    pub serve: Player,
+    /// Seconds into the phase, see `PhaseTimer`
+    pub phase_elapsed: f32,
+    pub balls: Vec<SavedBody>,
+    pub paddles: Vec<(Player, SavedBody)>,
+    /// Goals and rallies for the statistics
+    pub tracker: MatchTracker,
+}
+
+impl SavedMatch {
+    /// Values continuing the match would fail on
+    fn check(&self) -> Result<(), SaveError> {
+        if !matches!(self.phase, AppState::Ready | AppState::InGame | AppState::Goal | AppState::GoalReplay | AppState::Win) {
+            return Err(SaveError::Invalid(format!("{:?} is not a phase of a match", self.phase)));
+        }
+        if !(self.phase_elapsed.is_finite() && self.phase_elapsed >= 0.0) {
+            return Err(SaveError::Invalid(format!("{} seconds into the phase", self.phase_elapsed)));
+        }
+        Ok(())
+    }
+}
+
+/// Only the version, read first so that other changes to the format cannot fail the check
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    Version(u32),
+    /// Read, but nothing a match can continue from
+    Invalid(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "could not read the saved match: {}", e),
+            SaveError::Parse(e) => write!(f, "the saved match is damaged: {}", e),
+            SaveError::Version(version) => write!(f, "the saved match is from an incompatible version of the game (format {}, expected {})", version, SAVE_VERSION),
+            SaveError::Invalid(reason) => write!(f, "the saved match is damaged: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+pub struct SaveFile {
+    path: Option<PathBuf>,
+}
+
+impl SaveFile {
+    pub fn new(path: Option<PathBuf>) -> SaveFile {
+        SaveFile { path }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.as_ref().is_some_and(|path| path.exists())
+    }
+
+    /// Whether there is a saved match that can be continued, warns about one that cannot
+    pub fn can_continue(&self) -> bool {
+        match self.load() {
+            Ok(saved) => saved.is_some(),
+            Err(e) => {
+                warn!("Cannot continue: {}", e);
+                false
+            }
+        }
+    }
+
+    /// `Ok(None)` if there is no saved match
+    pub fn load(&self) -> Result<Option<SavedMatch>, SaveError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(SaveError::Io(e)),
+        };
+        let header: SaveHeader = ron::from_str(&content).map_err(SaveError::Parse)?;
+        if header.version != SAVE_VERSION {
+            return Err(SaveError::Version(header.version));
+        }
+        let saved: SavedMatch = ron::from_str(&content).map_err(SaveError::Parse)?;
+        saved.check()?;
+        Ok(Some(saved))
+    }
+
+    pub fn write(&self, saved: &SavedMatch) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        let result = ron::ser::to_string_pretty(saved, ron::ser::PrettyConfig::default())
+            .map_err(io::Error::other)
+            .and_then(|content| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(path, content)
+            });
+        match result {
+            Ok(()) => info!("Match saved to {}", path.display()),
+            Err(e) => warn!("Could not save the match to {}: {}", path.display(), e),
+        }
+    }
+
+    pub fn remove(&self) {
+        if let Some(path) = &self.path {
+            if let Err(e) = fs::remove_file(path) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("Could not remove saved match {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+}
+
+/// The phase of the match being played, looking past the pause menu. `None` outside of a match.
+pub fn match_phase(state: &State<AppState>) -> Option<AppState> {
+    let current = match state.current() {
+        AppState::Paused | AppState::GoalReplay => state.inactives().last()?,
+        current => current,
+    };
+    match current {
+        AppState::Ready | AppState::InGame | AppState::Goal => Some(current.clone()),
+        _ => None,
+    }
+}
+
+/// The match in progress, as it is saved
+#[derive(SystemParam)]
+pub struct MatchToSave<'w, 's> {
+    score: Res<'w, Score>,
+    rules: Res<'w, MatchRules>,
+    names: Res<'w, PlayerNames>,
+    controllers: Res<'w, Controllers>,
+    seed: Res<'w, MatchSeed>,
+    rng: Res<'w, GameRng>,
+    serve: Res<'w, Serve>,
+    phase_timer: Res<'w, PhaseTimer>,
+    tracker: Option<Res<'w, MatchTracker>>,
+    goal_replay: Option<Res<'w, GoalReplay>>,
+    ball_query: Query<'w, 's, (Entity, &'static Transform, &'static Moving), With<Ball>>,
+    paddle_query: Query<'w, 's, (Entity, &'static Paddle, &'static Transform, &'static Moving)>,
+}
+
+impl<'w, 's> MatchToSave<'w, 's> {
+    pub fn snapshot(&self, phase: AppState) -> SavedMatch {
+        // While a goal is shown again the ball and the paddles are somewhere before it
+        let body = |entity: Entity, transform: &Transform, moving: &Moving| SavedBody {
+            position: self.goal_replay.as_ref()
+                .and_then(|goal_replay| goal_replay.at_goal(entity))
+                .map_or(transform.translation, |transform| transform.translation)
+                .to_array(),
+            velocity: moving.velocity.to_array(),
+        };
+        SavedMatch {
+            version: SAVE_VERSION,
+            phase,
+            score: self.score.clone(),
+            rules: self.rules.clone(),
+            names: self.names.clone(),
+            controllers: *self.controllers,
+            seed: *self.seed,
+            rng_word_pos: self.rng.get_word_pos() as u64,
+            serve: self.serve.0,
+            phase_elapsed: self.phase_timer.0.elapsed_secs(),
+            balls: self.ball_query.iter().map(|(entity, transform, moving)| body(entity, transform, moving)).collect(),
+            paddles: self.paddle_query.iter()
+                .map(|(entity, Paddle(player), transform, moving)| (*player, body(entity, transform, moving)))
+                .collect(),
+            tracker: self.tracker.as_deref().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+fn save_on_exit(exit_events: EventReader<AppExit>,
+                close_events: EventReader<WindowCloseRequested>,
+                state: Res<State<AppState>>,
+                save_file: Res<SaveFile>,
+                match_to_save: MatchToSave,
+                session: Option<Res<NetSession>>,
+                playback: Option<Res<Playback>>,
+                mut saved: Local<bool>) {
+    // An online match cannot be continued alone, nor a replay
+    if *saved || session.is_some() || playback.is_some() || (exit_events.is_empty() && close_events.is_empty()) {
+        return;
+    }
+    if let Some(phase) = match_phase(&state) {
+        save_file.write(&match_to_save.snapshot(phase));
+        *saved = true;
+    }
+}
+
+/// The saved match being continued, until its phase has been entered
+struct Continued(SavedMatch);
+
+fn continue_match(mut commands: Commands,
+                  mut state: ResMut<State<AppState>>,
+                  root: Res<RootState>,
+                  save_file: Res<SaveFile>,
+                  mut score: ResMut<Score>,
+                  mut rules: ResMut<MatchRules>,
+                  mut names: ResMut<PlayerNames>,
+                  mut controllers: ResMut<Controllers>,
+                  mut seed: ResMut<MatchSeed>,
+                  tracker: Option<ResMut<MatchTracker>>) {
+    let saved = match save_file.load() {
+        Ok(Some(saved)) => saved,
+        Ok(None) => {
+            warn!("No saved match to continue");
+            state.set(root.0.clone()).unwrap();
+            return;
+        }
+        Err(e) => {
+            warn!("Cannot continue: {}", e);
+            state.set(root.0.clone()).unwrap();
+            return;
+        }
+    };
+    debug!("Continue {:?} {} - {}", saved.phase, saved.score.left, saved.score.right);
+    *score = saved.score.clone();
+    *rules = saved.rules.clone();
+    *names = saved.names.clone();
+    *controllers = saved.controllers;
+    *seed = saved.seed;
+    if let Some(mut tracker) = tracker {
+        *tracker = saved.tracker.clone();
+    }
+    state.set(saved.phase.clone()).unwrap();
+    commands.insert_resource(Continued(saved));
+}
+
+/// Puts the ball, the paddles and the phase back where they were saved. Entering `Ready`
+/// serves the ball again and every phase starts its timer over, this runs after them.
+fn restore_phase(mut commands: Commands,
+                 continued: Option<Res<Continued>>,
+                 mut rng: ResMut<GameRng>,
+                 mut serve: ResMut<Serve>,
+                 mut phase_timer: ResMut<PhaseTimer>,
+                 mut ball_query: Query<(&mut Transform, &mut Moving), (With<Ball>, Without<Paddle>)>,
+                 mut paddle_query: Query<(&Paddle, &mut Transform, &mut Moving)>) {
+    let saved = match &continued {
+        Some(continued) => &continued.0,
+        None => return,
+    };
+    rng.0 = ChaCha8Rng::seed_from_u64(saved.seed.current);
+    rng.0.set_word_pos(saved.rng_word_pos.into());
+    serve.0 = saved.serve;
+    phase_timer.0.set_elapsed(Duration::from_secs_f32(saved.phase_elapsed));
+    for ((mut transform, mut moving), body) in ball_query.iter_mut().zip(&saved.balls) {
+        transform.translation = Vec3::from(body.position);
+        moving.velocity = Vec3::from(body.velocity);
+    }
+    for (Paddle(player), mut transform, mut moving) in paddle_query.iter_mut() {
+        if let Some((_, body)) = saved.paddles.iter().find(|(saved_player, _)| saved_player == player) {
+            transform.translation = Vec3::from(body.position);
+            moving.velocity = Vec3::from(body.velocity);
+        }
+    }
+    commands.remove_resource::<Continued>();
+}
+
+/// The saved match is over, unless the match won was a replay
+fn remove_save(save_file: Res<SaveFile>, playback: Option<Res<Playback>>) {
+    if playback.is_none() {
+        save_file.remove();
+    }
+}
@@ -0,0 +1,101 @@
+use bevy::{
+    prelude::*,
+    render::camera::Viewport
+};
+use crate::theme::Themed;
+use crate::visuals::{PongCamera2d, PongCamera3d};
+
+/// Window size the camera framing and the UI sizes were designed for
+pub const REFERENCE_WIDTH: f32 = 1280.0;
+pub const REFERENCE_HEIGHT: f32 = 720.0;
+
+/// Factor applied to every font size, from the window size and the user setting
+pub struct UiScale(pub f32);
+
+/// Font sizes of a text as it was spawned, before scaling
+#[derive(Component)]
+struct BaseFontSizes(Vec<f32>);
+
+pub struct ScalingPlugin {
+    /// The user's UI scale on top of the one from the window size
+    pub ui_scale: f32,
+}
+
+/// The user setting, kept apart from the computed `UiScale`
+struct UserUiScale(f32);
+
+impl Plugin for ScalingPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(UserUiScale(self.ui_scale))
+            .insert_resource(UiScale(self.ui_scale))
+            .add_system(fit_viewports)
+            .add_system(update_ui_scale)
+            .add_system(scale_text.after(update_ui_scale));
+    }
+}
+
+/// The largest rectangle with the reference aspect ratio centered in a window of `size`
+fn letterbox(size: UVec2) -> Viewport {
+    let aspect = REFERENCE_WIDTH / REFERENCE_HEIGHT;
+    let width = (size.x as f32).min(size.y as f32 * aspect);
+    let height = (size.y as f32).min(size.x as f32 / aspect);
+    let physical_size = UVec2::new(width.round() as u32, height.round() as u32).max(UVec2::ONE);
+    Viewport {
+        physical_position: (size.max(physical_size) - physical_size) / 2,
+        physical_size,
+        ..Default::default()
+    }
+}
+
+/// Keeps the whole playfield in view whatever the shape of the window, with bars on the sides
+/// or at the top and bottom. The UI camera is left out so text can use the whole window, and
+/// so are the cameras of an app embedding the game.
+fn fit_viewports(windows: Res<Windows>, mut camera_query: Query<&mut Camera, Or<(With<PongCamera3d>, With<PongCamera2d>)>>) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let viewport = letterbox(UVec2::new(window.physical_width(), window.physical_height()));
+    for mut camera in camera_query.iter_mut() {
+        let unchanged = camera.viewport.as_ref().is_some_and(|current| {
+            current.physical_position == viewport.physical_position && current.physical_size == viewport.physical_size
+        });
+        if !unchanged {
+            camera.viewport = Some(viewport.clone());
+        }
+    }
+}
+
+fn update_ui_scale(windows: Res<Windows>, user_scale: Res<UserUiScale>, mut ui_scale: ResMut<UiScale>) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let scale = (window.width() / REFERENCE_WIDTH).min(window.height() / REFERENCE_HEIGHT) * user_scale.0;
+    // Avoid laying out all text again for no visible difference
+    if (scale - ui_scale.0).abs() > 0.001 {
+        ui_scale.0 = scale;
+    }
+}
+
+/// Scales the game's text, all of it themed. Text of an app embedding the game is left alone.
+fn scale_text(mut commands: Commands,
+              ui_scale: Res<UiScale>,
+              mut new_text_query: Query<(Entity, &mut Text), (With<Themed>, Without<BaseFontSizes>)>,
+              mut text_query: Query<(&BaseFontSizes, &mut Text)>) {
+    for (entity, mut text) in new_text_query.iter_mut() {
+        let sizes: Vec<f32> = text.sections.iter().map(|section| section.style.font_size).collect();
+        for (section, size) in text.sections.iter_mut().zip(&sizes) {
+            section.style.font_size = size * ui_scale.0;
+        }
+        commands.entity(entity).insert(BaseFontSizes(sizes));
+    }
+    if ui_scale.is_changed() {
+        for (BaseFontSizes(sizes), mut text) in text_query.iter_mut() {
+            for (section, size) in text.sections.iter_mut().zip(sizes) {
+                section.style.font_size = size * ui_scale.0;
+            }
+        }
+    }
+}
@@ -0,0 +1,589 @@
+use std::{
+    fmt, io,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant}
+};
+use bevy::{
+    prelude::*,
+    ecs::event::ManualEventReader,
+    input::InputPlugin
+};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use crate::accessibility::AccessibilitySettings;
+use crate::game::{apply_next_state, GamePlugin};
+use crate::locale::{Locale, FALLBACK_LANGUAGE};
+use crate::net::{tick_duration, MatchSetup, Message, NetInputs, Netcode, BYE_COPIES, MAX_PACKET_SIZE, PROTOCOL_VERSION, TIMEOUT};
+use crate::save::SavedBody;
+use crate::setup::setup;
+use crate::types::*;
+
+/// The server's match after a tick, everything a client needs to show it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchSnapshot {
+    pub tick: u32,
+    pub state: AppState,
+    pub score: Score,
+    pub balls: Vec<SavedBody>,
+    pub paddles: Vec<(Player, SavedBody)>,
+    /// What happened during the tick, for the sounds and effects. Lost with the packet, like the snapshot.
+    pub events: Vec<PongEvent>,
+}
+
+impl MatchSnapshot {
+    pub fn capture(world: &mut World, tick: u32, events: Vec<PongEvent>) -> MatchSnapshot {
+        let body = |transform: &Transform, moving: &Moving| SavedBody {
+            position: transform.translation.to_array(),
+            velocity: moving.velocity.to_array(),
+        };
+        let balls = world.query_filtered::<(&Transform, &Moving), With<Ball>>()
+            .iter(world)
+            .map(|(transform, moving)| body(transform, moving))
+            .collect();
+        let paddles = world.query::<(&Paddle, &Transform, &Moving)>()
+            .iter(world)
+            .map(|(Paddle(player), transform, moving)| (*player, body(transform, moving)))
+            .collect();
+        MatchSnapshot {
+            tick,
+            state: world.resource::<State<AppState>>().current().clone(),
+            score: world.resource::<Score>().clone(),
+            balls,
+            paddles,
+            events,
+        }
+    }
+
+    /// Shows the server's match in place of the local simulation
+    pub fn apply(self, world: &mut World) {
+        let mut ball_query = world.query_filtered::<(&mut Transform, &mut Moving), (With<Ball>, Without<Paddle>)>();
+        for ((mut transform, mut moving), body) in ball_query.iter_mut(world).zip(&self.balls) {
+            transform.translation = Vec3::from(body.position);
+            moving.velocity = Vec3::from(body.velocity);
+        }
+        let mut paddle_query = world.query::<(&Paddle, &mut Transform, &mut Moving)>();
+        for (Paddle(player), mut transform, mut moving) in paddle_query.iter_mut(world) {
+            if let Some((_, body)) = self.paddles.iter().find(|(saved_player, _)| saved_player == player) {
+                transform.translation = Vec3::from(body.position);
+                moving.velocity = Vec3::from(body.velocity);
+            }
+        }
+        *world.resource_mut::<Score>() = self.score;
+        let mut events = world.resource_mut::<Events<PongEvent>>();
+        for event in self.events {
+            events.send(event);
+        }
+        if *world.resource::<State<AppState>>().current() != self.state {
+            world.resource_mut::<NextState>().0 = Some(self.state);
+            apply_next_state(world);
+        }
+    }
+}
+
+/// How a finished match went, logged by the server
+#[derive(Debug, Clone)]
+pub struct MatchResult {
+    pub id: u32,
+    pub names: PlayerNames,
+    pub rules: MatchRules,
+    pub score: Score,
+    /// False when a player left before the end
+    pub completed: bool,
+}
+
+impl fmt::Display for MatchResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Match {}: {} {} - {} {}", self.id, self.names.left, self.score.left, self.score.right, self.names.right)?;
+        if self.rules.has_sets() {
+            write!(f, ", sets {} - {}", self.score.left_sets, self.score.right_sets)?;
+        }
+        match self.score.leader() {
+            _ if !self.completed => write!(f, ", abandoned"),
+            Some(winner) => write!(f, ", {} wins", self.names.get(winner)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What every match on the server is played with
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub rules: MatchRules,
+    pub game_speed: f32,
+    /// Seed of the first match, the next ones count up from it
+    pub seed: u64,
+    /// Spectators a match takes at most
+    pub max_spectators: usize,
+}
+
+struct Client {
+    address: SocketAddr,
+    name: String,
+    last_heard: Duration,
+    /// Tick and value of the newest input, always 0 for spectators
+    input: (u32, i8),
+}
+
+struct ServerMatch {
+    id: u32,
+    setup: MatchSetup,
+    /// The left player first
+    players: Vec<Client>,
+    spectators: Vec<Client>,
+    /// Created once both players are in
+    app: Option<App>,
+    started: Instant,
+    tick: u32,
+    events: ManualEventReader<PongEvent>,
+}
+
+impl ServerMatch {
+    fn clients(&self) -> impl Iterator<Item = &Client> {
+        self.players.iter().chain(&self.spectators)
+    }
+
+    fn clients_mut(&mut self) -> impl Iterator<Item = &mut Client> {
+        self.players.iter_mut().chain(&mut self.spectators)
+    }
+
+    fn has(&self, address: SocketAddr) -> bool {
+        self.clients().any(|client| client.address == address)
+    }
+
+    fn player(&self, address: SocketAddr) -> Option<Player> {
+        match self.players.iter().position(|client| client.address == address)? {
+            0 => Some(Player::Left),
+            _ => Some(Player::Right),
+        }
+    }
+
+    fn result(&self) -> MatchResult {
+        let score = self.app.as_ref().map_or_else(Score::default, |app| app.world.resource::<Score>().clone());
+        let completed = self.app.as_ref()
+            .is_some_and(|app| *app.world.resource::<State<AppState>>().current() == AppState::Title);
+        MatchResult { id: self.id, names: self.setup.names.clone(), rules: self.setup.rules.clone(), score, completed }
+    }
+}
+
+/// The game without anything to show it, played in fixed ticks
+fn match_app(match_setup: &MatchSetup) -> App {
+    let mut app = App::new();
+    app.insert_resource(Time::default())
+        .add_plugin(InputPlugin)
+        .add_event::<PongEvent>()
+        .init_resource::<Score>()
+        .insert_resource(match_setup.rules.clone())
+        .insert_resource(Controllers { left: Controller::Network, right: Controller::Network })
+        .insert_resource(match_setup.names.clone())
+        .insert_resource(Locale::new(Some(FALLBACK_LANGUAGE)))
+        .insert_resource(AccessibilitySettings { game_speed: match_setup.game_speed, ..Default::default() })
+        .insert_resource(GameRng(ChaCha8Rng::seed_from_u64(match_setup.seed)))
+        .insert_resource(MatchSeed::new(match_setup.seed))
+        .add_startup_system(setup)
+        .add_plugin(GamePlugin)
+        .add_state(AppState::NewGame);
+    app
+}
+
+/// Runs online matches for players who connect with `--connect`. Each match is a separate game
+/// that the server alone simulates: players send their paddle input and every client gets a
+/// snapshot of the match after each tick. Any number of spectators may watch a match.
+pub struct Server {
+    socket: UdpSocket,
+    config: ServerConfig,
+    matches: Vec<ServerMatch>,
+    next_id: u32,
+    results: Vec<MatchResult>,
+}
+
+impl Server {
+    pub fn bind(port: u16, config: ServerConfig) -> io::Result<Server> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+        socket.set_nonblocking(true)?;
+        Ok(Server { socket, config, matches: Vec::new(), next_id: 1, results: Vec::new() })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Matches being played, not counting one waiting for a second player
+    pub fn running_matches(&self) -> usize {
+        self.matches.iter().filter(|server_match| server_match.app.is_some()).count()
+    }
+
+    /// Players in all matches, including one waiting for an opponent
+    pub fn players(&self) -> usize {
+        self.matches.iter().map(|server_match| server_match.players.len()).sum()
+    }
+
+    /// Matches that ended since the last call
+    pub fn take_results(&mut self) -> Vec<MatchResult> {
+        std::mem::take(&mut self.results)
+    }
+
+    fn send(&self, message: &Message, to: SocketAddr) {
+        if let Err(e) = self.socket.send_to(&message.encode(), to) {
+            debug!("Could not send to {}: {}", to, e);
+        }
+    }
+
+    fn say_bye(&self, to: SocketAddr) {
+        for _ in 0..BYE_COPIES {
+            self.send(&Message::Bye, to);
+        }
+    }
+
+    /// Receives what has arrived and drops clients that went quiet.
+    /// `now` is any clock that keeps running, such as the time since the server started.
+    pub fn poll(&mut self, now: Duration) {
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((size, from)) => {
+                    if let Some(message) = Message::decode(&buffer[..size]) {
+                        self.receive(message, from, now);
+                    }
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if matches!(e.kind(), io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionRefused) => (),
+                Err(e) => {
+                    warn!("Could not receive from the network: {}", e);
+                    break;
+                }
+            }
+        }
+        let quiet: Vec<SocketAddr> = self.matches.iter()
+            .flat_map(ServerMatch::clients)
+            .filter(|client| now.saturating_sub(client.last_heard) > TIMEOUT)
+            .map(|client| client.address)
+            .collect();
+        for address in quiet {
+            info!("No answer from {} in {} seconds", address, TIMEOUT.as_secs());
+            self.leave(address);
+        }
+    }
+
+    fn receive(&mut self, message: Message, from: SocketAddr, now: Duration) {
+        let index = self.matches.iter().position(|server_match| server_match.has(from));
+        if let Some(client) = index.and_then(|index| self.matches[index].clients_mut().find(|client| client.address == from)) {
+            client.last_heard = now;
+        }
+        match (message, index) {
+            (Message::Hello { version, .. }, None) if version != PROTOCOL_VERSION => {
+                warn!("{} uses network protocol {}, expected {}", from, version, PROTOCOL_VERSION);
+                self.send(&Message::WrongVersion { version, expected: PROTOCOL_VERSION }, from);
+            },
+            (Message::Hello { name, spectator, .. }, None) => {
+                let client = Client { address: from, name, last_heard: now, input: (0, 0) };
+                if let Some(index) = self.join(client, spectator) {
+                    self.answer(index, from);
+                }
+            },
+            // Also repeated when the first answer got lost
+            (Message::Hello { .. }, Some(index)) => self.answer(index, from),
+            (Message::Input { tick, input }, Some(index)) => {
+                if let Some(client) = self.matches[index].players.iter_mut().find(|client| client.address == from) {
+                    if tick >= client.input.0 {
+                        client.input = (tick, input);
+                    }
+                }
+            },
+            (Message::Bye, Some(_)) => self.leave(from),
+            _ => (),
+        }
+    }
+
+    /// Puts a player in the match waiting for one, or starts a new match. A spectator watches
+    /// the newest match being played, or waits for one to start. Returns `None` for a spectator
+    /// when that match already has as many as it takes.
+    fn join(&mut self, client: Client, spectator: bool) -> Option<usize> {
+        let running = self.matches.iter().rposition(|server_match| server_match.app.is_some());
+        if let Some(index) = running.filter(|_| spectator) {
+            return self.watch(index, client);
+        }
+        let index = match self.matches.iter().position(|server_match| server_match.app.is_none()) {
+            Some(index) => index,
+            None => {
+                let id = self.next_id;
+                self.next_id += 1;
+                let setup = MatchSetup {
+                    seed: self.config.seed.wrapping_add(id as u64),
+                    rules: self.config.rules.clone(),
+                    names: PlayerNames::default(),
+                    game_speed: self.config.game_speed,
+                    netcode: Netcode::Server,
+                    input_delay: 0,
+                };
+                self.matches.push(ServerMatch {
+                    id,
+                    setup,
+                    players: Vec::new(),
+                    spectators: Vec::new(),
+                    app: None,
+                    started: Instant::now(),
+                    tick: 0,
+                    events: ManualEventReader::default(),
+                });
+                self.matches.len() - 1
+            }
+        };
+        if spectator {
+            return self.watch(index, client);
+        }
+        let server_match = &mut self.matches[index];
+        match server_match.players.len() {
+            0 => server_match.setup.names.left = client.name.clone(),
+            _ => server_match.setup.names.right = client.name.clone(),
+        }
+        server_match.players.push(client);
+        if server_match.players.len() == 2 {
+            info!("Match {} started: {} - {}, random seed {}", server_match.id,
+                  server_match.setup.names.left, server_match.setup.names.right, server_match.setup.seed);
+            let mut app = match_app(&server_match.setup);
+            server_match.started = Instant::now();
+            // Every update then plays exactly one tick
+            app.world.resource_mut::<Time>().update_with_instant(server_match.started);
+            server_match.app = Some(app);
+            let addresses: Vec<_> = server_match.clients().map(|client| client.address).collect();
+            for address in addresses {
+                self.answer(index, address);
+            }
+        }
+        Some(index)
+    }
+
+    fn watch(&mut self, index: usize, client: Client) -> Option<usize> {
+        let server_match = &self.matches[index];
+        if server_match.spectators.len() >= self.config.max_spectators {
+            debug!("Turning away spectator {}, match {} is full", client.address, server_match.id);
+            self.send(&Message::Full, client.address);
+            return None;
+        }
+        info!("{} watches match {}", client.name, server_match.id);
+        self.matches[index].spectators.push(client);
+        Some(index)
+    }
+
+    fn answer(&self, index: usize, to: SocketAddr) {
+        let server_match = &self.matches[index];
+        if server_match.app.is_some() {
+            let welcome = Message::Welcome { setup: server_match.setup.clone(), player: server_match.player(to) };
+            self.send(&welcome, to);
+        } else {
+            self.send(&Message::Waiting, to);
+        }
+    }
+
+    /// A player leaving ends their match, the others are told
+    fn leave(&mut self, address: SocketAddr) {
+        let index = match self.matches.iter().position(|server_match| server_match.has(address)) {
+            Some(index) => index,
+            None => return,
+        };
+        let server_match = &mut self.matches[index];
+        if server_match.player(address).is_none() {
+            server_match.spectators.retain(|client| client.address != address);
+            return;
+        }
+        if server_match.app.is_none() {
+            // Nothing started yet, the match waits for someone else
+            server_match.players.retain(|client| client.address != address);
+            server_match.setup.names = PlayerNames::default();
+            if let Some(client) = server_match.players.first() {
+                server_match.setup.names.left = client.name.clone();
+            }
+            return;
+        }
+        self.end(index);
+    }
+
+    fn end(&mut self, index: usize) {
+        let server_match = self.matches.remove(index);
+        let result = server_match.result();
+        info!("{}", result);
+        for client in server_match.clients() {
+            self.say_bye(client.address);
+        }
+        self.results.push(result);
+    }
+
+    /// Plays a tick of every running match and sends the clients what happened
+    pub fn tick(&mut self) {
+        let mut finished = Vec::new();
+        for (index, server_match) in self.matches.iter_mut().enumerate() {
+            let app = match &mut server_match.app {
+                Some(app) => app,
+                None => continue,
+            };
+            let inputs = [0, 1].map(|player| server_match.players.get(player).map_or(0, |client| client.input.1));
+            app.world.resource_mut::<NetInputs>().0 = inputs;
+            let now = server_match.started + tick_duration() * (server_match.tick + 1);
+            app.world.resource_mut::<Time>().update_with_instant(now);
+            app.update();
+
+            let events = server_match.events.iter(app.world.resource::<Events<PongEvent>>()).cloned().collect();
+            let snapshot = MatchSnapshot::capture(&mut app.world, server_match.tick, events);
+            server_match.tick += 1;
+            let over = snapshot.state == AppState::Title;
+            let message = Message::State(snapshot).encode();
+            for client in server_match.clients() {
+                if let Err(e) = self.socket.send_to(&message, client.address) {
+                    debug!("Could not send to {}: {}", client.address, e);
+                }
+            }
+            if over {
+                finished.push(index);
+            }
+        }
+        for index in finished.into_iter().rev() {
+            self.end(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::{CloseReason, NetSession, NetStatus, TICK_RATE};
+    use super::*;
+
+    fn server(goals_to_win: u32) -> Server {
+        let rules = MatchRules { goals_to_win, ..Default::default() };
+        Server::bind(0, ServerConfig { rules, game_speed: 1.0, seed: 11, max_spectators: 1 }).unwrap()
+    }
+
+    fn connect(server: &Server, name: &str) -> NetSession {
+        let address = format!("127.0.0.1:{}", server.local_addr().unwrap().port());
+        NetSession::connect(&address, name.to_string()).unwrap()
+    }
+
+    /// Lets the clients and the server hear each other a few times, `now` being the same for all
+    fn exchange(server: &mut Server, clients: &mut [&mut NetSession], now: Duration) {
+        for _ in 0..3 {
+            for client in clients.iter_mut() {
+                client.poll(now);
+            }
+            server.poll(now);
+        }
+    }
+
+    /// Long enough for a match to one goal
+    const MATCH_SECONDS: u32 = 30;
+
+    #[test]
+    fn players_are_paired_and_see_the_same_match() {
+        let mut server = server(3);
+        let mut alice = connect(&server, "Alice");
+        exchange(&mut server, &mut [&mut alice], Duration::ZERO);
+        assert_eq!(alice.status(), NetStatus::Connecting);
+        assert_eq!(server.running_matches(), 0);
+
+        // Still there after the time a silent host would take to time out
+        exchange(&mut server, &mut [&mut alice], TIMEOUT * 2);
+        assert_eq!(alice.status(), NetStatus::Connecting);
+
+        let mut bob = connect(&server, "Bob");
+        exchange(&mut server, &mut [&mut alice, &mut bob], TIMEOUT * 2);
+        assert_eq!((alice.status(), bob.status()), (NetStatus::Connected, NetStatus::Connected));
+        assert_eq!((alice.local_player(), bob.local_player()), (Player::Left, Player::Right));
+        assert_eq!(alice.setup(), bob.setup());
+        assert_eq!(alice.setup().map(|setup| setup.names.clone()),
+                   Some(PlayerNames { left: "Alice".to_string(), right: "Bob".to_string() }));
+
+        for _ in 0..10 {
+            server.tick();
+        }
+        exchange(&mut server, &mut [&mut alice, &mut bob], TIMEOUT * 2);
+        let (alice_view, bob_view) = (alice.take_snapshot().unwrap(), bob.take_snapshot().unwrap());
+        assert_eq!(alice_view.tick, 9);
+        assert_eq!(bob_view.tick, 9);
+        assert_eq!(alice_view.state, AppState::Ready);
+        assert_eq!(alice_view.balls.len(), 1);
+        assert_eq!(alice_view.paddles.len(), 2);
+    }
+
+    #[test]
+    fn matches_run_side_by_side_and_end_with_a_result() {
+        let mut server = server(1);
+        let mut clients: Vec<_> = ["A", "B", "C", "D"].iter().map(|name| connect(&server, name)).collect();
+        exchange(&mut server, &mut clients.iter_mut().collect::<Vec<_>>(), Duration::ZERO);
+        assert_eq!(server.running_matches(), 2);
+
+        let mut results = Vec::new();
+        for tick in 0..TICK_RATE * MATCH_SECONDS {
+            // Every paddle runs away from the ball, the first serve decides each match
+            for client in &mut clients {
+                client.step(127);
+            }
+            let now = tick_duration() * tick;
+            exchange(&mut server, &mut clients.iter_mut().collect::<Vec<_>>(), now);
+            server.tick();
+            results.extend(server.take_results());
+            if results.len() == 2 {
+                break;
+            }
+        }
+        assert_eq!(results.len(), 2);
+        for result in &results {
+            assert!(result.completed);
+            assert_eq!(result.score.left + result.score.right, 1);
+            assert!(result.to_string().ends_with(" wins"), "{}", result);
+        }
+        exchange(&mut server, &mut clients.iter_mut().collect::<Vec<_>>(), tick_duration() * TICK_RATE * MATCH_SECONDS);
+        for client in &clients {
+            assert_eq!(client.status(), NetStatus::Closed(CloseReason::MatchOver));
+        }
+    }
+
+    #[test]
+    fn spectators_watch_and_leaving_ends_the_match() {
+        let mut server = server(3);
+        let mut alice = connect(&server, "Alice");
+        let mut bob = connect(&server, "Bob");
+        exchange(&mut server, &mut [&mut alice, &mut bob], Duration::ZERO);
+
+        let spectator = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let hello = Message::Hello { version: PROTOCOL_VERSION, name: "Carol".to_string(), spectator: true };
+        spectator.send_to(&hello.encode(), server.local_addr().unwrap()).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        server.poll(Duration::ZERO);
+        server.tick();
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        let (size, _) = spectator.recv_from(&mut buffer).unwrap();
+        assert!(matches!(Message::decode(&buffer[..size]), Some(Message::Welcome { player: None, .. })));
+        let (size, _) = spectator.recv_from(&mut buffer).unwrap();
+        assert!(matches!(Message::decode(&buffer[..size]), Some(Message::State(_))));
+
+        // One spectator is all the match takes
+        let address = format!("127.0.0.1:{}", server.local_addr().unwrap().port());
+        let mut dave = NetSession::spectate(&address, "Dave".to_string()).unwrap();
+        exchange(&mut server, &mut [&mut dave], Duration::from_millis(10));
+        assert_eq!(dave.status(), NetStatus::Closed(CloseReason::Full));
+
+        bob.disconnect();
+        exchange(&mut server, &mut [&mut alice], Duration::from_millis(10));
+        assert_eq!(alice.status(), NetStatus::Closed(CloseReason::PeerLeft));
+        assert_eq!(server.running_matches(), 0);
+        let results = server.take_results();
+        assert_eq!(results.len(), 1);
+        assert!(!results[0].completed);
+        assert_eq!(results[0].to_string(), "Match 1: Alice 0 - 0 Bob, abandoned");
+    }
+
+    #[test]
+    fn another_protocol_version_is_turned_away() {
+        let mut server = server(3);
+        let older = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        older.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let hello = Message::Hello { version: PROTOCOL_VERSION - 1, name: "Older".to_string(), spectator: false };
+        older.send_to(&hello.encode(), server.local_addr().unwrap()).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        server.poll(Duration::ZERO);
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        let (size, _) = older.recv_from(&mut buffer).unwrap();
+        assert!(matches!(Message::decode(&buffer[..size]), Some(Message::WrongVersion { version, expected })
+                         if version == PROTOCOL_VERSION - 1 && expected == PROTOCOL_VERSION));
+        assert_eq!(server.players(), 0);
+    }
+}
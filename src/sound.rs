@@ -0,0 +1,169 @@
+use std::{collections::HashMap, sync::Arc};
+use bevy::{
+    prelude::*,
+    asset::LoadState,
+    audio::{Audio, AudioSink, AudioSource, PlaybackSettings}
+};
+use crate::config::AudioSettings;
+use crate::synth::{to_wav, SoundEffect};
+use crate::types::*;
+
+/// Seconds to fade from one piece of music to the next
+const CROSSFADE: f32 = 1.5;
+
+fn music_volume(mixer: &AudioSettings) -> f32 {
+    if mixer.muted { 0.0 } else { mixer.master * mixer.music }
+}
+
+fn effects_volume(mixer: &AudioSettings) -> f32 {
+    if mixer.muted { 0.0 } else { mixer.master * mixer.effects }
+}
+
+/// Every sound effect, synthesized at startup
+struct SoundEffects(HashMap<SoundEffect, Handle<AudioSource>>);
+
+impl SoundEffects {
+    fn play(&self, audio: &Audio, mixer: &AudioSettings, effect: SoundEffect) {
+        let volume = effects_volume(mixer);
+        if let (Some(handle), true) = (self.0.get(&effect), volume > 0.0) {
+            audio.play_with_settings(handle.clone(), PlaybackSettings::ONCE.with_volume(volume));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum MusicTrack {
+    Title,
+    Game,
+    Win,
+}
+
+impl MusicTrack {
+    const ALL: [MusicTrack; 3] = [MusicTrack::Title, MusicTrack::Game, MusicTrack::Win];
+
+    /// Music is optional, the game runs without these files
+    fn path(self) -> &'static str {
+        match self {
+            MusicTrack::Title => "music/title.ogg",
+            MusicTrack::Game => "music/game.ogg",
+            MusicTrack::Win => "music/win.ogg",
+        }
+    }
+
+    /// None while the game is idle, the app embedding it may have music of its own
+    fn for_state(state: &AppState) -> Option<MusicTrack> {
+        match state {
+            AppState::Idle => None,
+            AppState::Title | AppState::Lobby | AppState::Records | AppState::LanGames | AppState::Replays => Some(MusicTrack::Title),
+            AppState::Win => Some(MusicTrack::Win),
+            _ => Some(MusicTrack::Game),
+        }
+    }
+}
+
+/// A piece of music fading in or out
+struct Playing {
+    track: MusicTrack,
+    sink: Handle<AudioSink>,
+    /// Current share of the music volume, from 0 to 1
+    fade: f32,
+    fading_in: bool,
+}
+
+#[derive(Default)]
+struct Music {
+    tracks: HashMap<MusicTrack, Handle<AudioSource>>,
+    playing: Vec<Playing>,
+}
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Music>()
+            .add_startup_system(setup_sounds)
+            .add_system(play_event_sounds)
+            .add_system(change_music)
+            .add_system(fade_music.after(change_music))
+            .add_system_set(SystemSet::on_enter(AppState::Win).with_system(play_win_sound));
+    }
+}
+
+fn setup_sounds(mut commands: Commands,
+                mut sources: ResMut<Assets<AudioSource>>,
+                mut music: ResMut<Music>,
+                asset_server: Res<AssetServer>) {
+    let effects = SoundEffect::ALL.iter()
+        .map(|effect| {
+            let bytes: Arc<[u8]> = to_wav(&effect.render()).into();
+            (*effect, sources.add(AudioSource { bytes }))
+        })
+        .collect();
+    commands.insert_resource(SoundEffects(effects));
+    music.tracks = MusicTrack::ALL.iter().map(|track| (*track, asset_server.load(track.path()))).collect();
+}
+
+fn play_event_sounds(mut events: EventReader<PongEvent>,
+                     audio: Res<Audio>,
+                     mixer: Res<AudioSettings>,
+                     effects: Res<SoundEffects>) {
+    for e in events.iter() {
+        let effect = match e {
+            PongEvent::Bounce(Collider::Paddle(_), _) => SoundEffect::PaddleHit,
+            PongEvent::Bounce(..) => SoundEffect::WallHit,
+            PongEvent::Goal(_) => SoundEffect::Goal,
+            PongEvent::Countdown(0) => SoundEffect::Serve,
+            PongEvent::Countdown(_) => SoundEffect::Tick,
+        };
+        effects.play(&audio, &mixer, effect);
+    }
+}
+
+fn play_win_sound(audio: Res<Audio>, mixer: Res<AudioSettings>, effects: Res<SoundEffects>) {
+    effects.play(&audio, &mixer, SoundEffect::Win);
+}
+
+/// Starts the music of the current state, fading out whatever was playing
+fn change_music(state: Res<State<AppState>>,
+                mut music: ResMut<Music>,
+                audio: Res<Audio>,
+                asset_server: Res<AssetServer>,
+                sinks: Res<Assets<AudioSink>>) {
+    let track = MusicTrack::for_state(state.current());
+    for playing in music.playing.iter_mut() {
+        playing.fading_in = Some(playing.track) == track;
+    }
+    let track = match track {
+        Some(track) if !music.playing.iter().any(|playing| playing.track == track) => track,
+        _ => return,
+    };
+    let source = match music.tracks.get(&track) {
+        Some(source) => source.clone(),
+        None => return,
+    };
+    // Tried again every frame while the file is loading, never if it is missing
+    if asset_server.get_load_state(&source) != LoadState::Loaded {
+        return;
+    }
+    let settings = match track {
+        MusicTrack::Win => PlaybackSettings::ONCE,
+        MusicTrack::Title | MusicTrack::Game => PlaybackSettings::LOOP,
+    };
+    let sink = sinks.get_handle(audio.play_with_settings(source, settings.with_volume(0.0)));
+    music.playing.push(Playing { track, sink, fade: 0.0, fading_in: true });
+}
+
+fn fade_music(mut music: ResMut<Music>, mixer: Res<AudioSettings>, sinks: Res<Assets<AudioSink>>, time: Res<Time>) {
+    let step = time.delta_seconds() / CROSSFADE;
+    for playing in music.playing.iter_mut() {
+        playing.fade = if playing.fading_in { (playing.fade + step).min(1.0) } else { (playing.fade - step).max(0.0) };
+        if let Some(sink) = sinks.get(&playing.sink) {
+            sink.set_volume(playing.fade * music_volume(&mixer));
+            if playing.fade == 0.0 {
+                sink.stop();
+            }
+        }
+    }
+    music.playing.retain(|playing| playing.fading_in || playing.fade > 0.0);
+}
@@ -0,0 +1,317 @@
+use std::{collections::HashMap, fs, io, path::PathBuf};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::config::data_dir;
+use crate::replay::Playback;
+use crate::types::*;
+
+const STATS_FILE: &str = "stats.ron";
+const STATS_VERSION: u32 = 1;
+
+/// Records finished matches into the statistics file
+pub struct StatsPlugin {
+    /// Statistics file to use instead of the default one
+    pub path: Option<PathBuf>,
+}
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        let path = self.path.clone().or_else(|| data_dir().map(|dir| dir.join(STATS_FILE)));
+        app
+            .insert_resource(Stats::load(path))
+            .init_resource::<MatchTracker>()
+            .add_system_set(SystemSet::on_exit(AppState::NewGame).with_system(start_match))
+            // Not tied to InGame, the goal event may only be read after the state has changed
+            .add_system(track_events)
+            .add_system_set(SystemSet::on_enter(AppState::Win).with_system(record_match));
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoalRecord {
+    pub scorer: Player,
+    /// Seconds of play since the start of the match, see `Score::time`
+    pub time: f32,
+    /// Paddle hits before the goal
+    pub rally: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SideRecord {
+    pub name: String,
+    pub controller: Controller,
+    /// Goals in the last set
+    pub score: u32,
+    #[serde(default)]
+    pub sets: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchRecord {
+    /// Seconds since the Unix epoch
+    pub finished_at: u64,
+    pub left: SideRecord,
+    pub right: SideRecord,
+    /// Seconds of play from the start of the match to its end, the winning goal or the time limit
+    pub duration: f32,
+    pub longest_rally: u32,
+    pub goals: Vec<GoalRecord>,
+}
+
+impl MatchRecord {
+    fn left_won(&self) -> bool {
+        (self.left.sets, self.left.score) > (self.right.sets, self.right.score)
+    }
+
+    pub fn winner(&self) -> &SideRecord {
+        if self.left_won() { &self.left } else { &self.right }
+    }
+
+    pub fn loser(&self) -> &SideRecord {
+        if self.left_won() { &self.right } else { &self.left }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StatsFile {
+    version: u32,
+    matches: Vec<MatchRecord>,
+}
+
+/// Totals and personal bests of one player name
+#[derive(Debug, Clone, Default)]
+pub struct ProfileStats {
+    pub name: String,
+    pub wins: u32,
+    pub losses: u32,
+    pub goals: u32,
+    pub longest_rally: u32,
+    /// Duration of the quickest won match in seconds
+    pub fastest_win: Option<f32>,
+}
+
+pub struct Stats {
+    path: Option<PathBuf>,
+    pub matches: Vec<MatchRecord>,
+}
+
+impl Stats {
+    /// Loads the statistics file. An unreadable file is moved aside as `<file>.bak` and
+    /// statistics start over, so a broken file never keeps the game from starting.
+    pub fn load(path: Option<PathBuf>) -> Stats {
+        let mut stats = Stats { path, matches: Vec::new() };
+        let path = match &stats.path {
+            Some(path) => path,
+            None => return stats,
+        };
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return stats,
+            Err(e) => {
+                warn!("Could not read statistics from {}: {}", path.display(), e);
+                return stats;
+            }
+        };
+        match ron::from_str::<StatsFile>(&content) {
+            Ok(file) if file.version == STATS_VERSION => stats.matches = file.matches,
+            result => {
+                let mut backup = path.clone().into_os_string();
+                backup.push(".bak");
+                match result {
+                    Ok(file) => warn!("Unsupported statistics version {} in {}, starting over", file.version, path.display()),
+                    Err(e) => warn!("Corrupt statistics in {}, starting over: {}", path.display(), e),
+                }
+                if let Err(e) = fs::rename(path, &backup) {
+                    warn!("Could not back up statistics to {}: {}", PathBuf::from(&backup).display(), e);
+                }
+            }
+        }
+        stats
+    }
+
+    pub fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        let file = StatsFile { version: STATS_VERSION, matches: self.matches.clone() };
+        let result = ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default())
+            .map_err(io::Error::other)
+            .and_then(|content| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(path, content)
+            });
+        if let Err(e) = result {
+            warn!("Could not save statistics to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Player names ranked by wins, then by fewest losses
+    pub fn leaderboard(&self) -> Vec<ProfileStats> {
+        let mut profiles: HashMap<&str, ProfileStats> = HashMap::new();
+        for record in &self.matches {
+            for (player, side) in [(Player::Left, &record.left), (Player::Right, &record.right)] {
+                let profile = profiles.entry(&side.name).or_insert_with(|| ProfileStats {
+                    name: side.name.clone(),
+                    ..Default::default()
+                });
+                // Counted from the goal list, the side's score only covers the last set
+                profile.goals += record.goals.iter().filter(|goal| goal.scorer == player).count() as u32;
+                profile.longest_rally = profile.longest_rally.max(record.longest_rally);
+            }
+            let winner = profiles.get_mut(record.winner().name.as_str()).unwrap();
+            winner.wins += 1;
+            winner.fastest_win = Some(winner.fastest_win.map_or(record.duration, |fastest| fastest.min(record.duration)));
+            profiles.get_mut(record.loser().name.as_str()).unwrap().losses += 1;
+        }
+        let mut leaderboard: Vec<ProfileStats> = profiles.into_values().collect();
+        leaderboard.sort_by(|a, b| b.wins.cmp(&a.wins)
+            .then(a.losses.cmp(&b.losses))
+            .then(a.name.cmp(&b.name)));
+        leaderboard
+    }
+}
+
+/// Progress of the match being played, saved with it so a continued match is recorded whole
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MatchTracker {
+    /// Paddle hits since the last goal
+    pub rally: u32,
+    longest_rally: u32,
+    goals: Vec<GoalRecord>,
+}
+
+fn start_match(mut tracker: ResMut<MatchTracker>) {
+    *tracker = MatchTracker::default();
+}
+
+fn track_events(mut events: EventReader<PongEvent>, mut tracker: ResMut<MatchTracker>, score: Res<Score>) {
+    for e in events.iter() {
+        match e {
+            PongEvent::Bounce(Collider::Paddle(_), _) => tracker.rally += 1,
+            PongEvent::Bounce(..) | PongEvent::Countdown(_) => (),
+            PongEvent::Goal(player) => {
+                let goal = GoalRecord {
+                    scorer: *player,
+                    time: score.time,
+                    rally: tracker.rally,
+                };
+                tracker.longest_rally = tracker.longest_rally.max(tracker.rally);
+                tracker.rally = 0;
+                tracker.goals.push(goal);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+// There is no system clock to read on the web
+#[cfg(target_arch = "wasm32")]
+pub fn unix_time() -> u64 {
+    0
+}
+
+fn record_match(mut stats: ResMut<Stats>,
+                tracker: Res<MatchTracker>,
+                score: Res<Score>,
+                names: Res<PlayerNames>,
+                controllers: Res<Controllers>,
+                playback: Option<Res<Playback>>) {
+    // Already recorded when it was played
+    if playback.is_some() {
+        return;
+    }
+    let side = |player| SideRecord {
+        name: names.get(player).to_string(),
+        controller: controllers.get(player),
+        score: score.goals(player),
+        sets: score.sets(player),
+    };
+    let record = MatchRecord {
+        finished_at: unix_time(),
+        left: side(Player::Left),
+        right: side(Player::Right),
+        duration: score.time,
+        longest_rally: tracker.longest_rally,
+        goals: tracker.goals.clone(),
+    };
+    debug!("Recording match {:?}", record);
+    stats.matches.push(record);
+    stats.save();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bevy-pong-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn record(left: (&str, u32), right: (&str, u32), duration: f32) -> MatchRecord {
+        let side = |(name, score): (&str, u32)| SideRecord {
+            name: name.to_string(),
+            controller: Controller::Keyboard,
+            score,
+            sets: 0,
+        };
+        MatchRecord {
+            finished_at: 0,
+            left: side(left),
+            right: side(right),
+            duration,
+            longest_rally: 0,
+            goals: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn corrupt_statistics_are_moved_aside() {
+        let dir = temp_dir("corrupt-stats");
+        let path = dir.join(STATS_FILE);
+        fs::write(&path, "(version: 1, matches: [oops").unwrap();
+        let stats = Stats::load(Some(path.clone()));
+        assert!(stats.matches.is_empty());
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(dir.join("stats.ron.bak")).unwrap(), "(version: 1, matches: [oops");
+
+        // Starting over writes a fresh file next to the backup
+        stats.save();
+        assert!(Stats::load(Some(path)).matches.is_empty());
+        assert!(dir.join("stats.ron.bak").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn the_leaderboard_ranks_by_wins_then_fewest_losses() {
+        let stats = Stats {
+            path: None,
+            matches: vec![
+                record(("Ann", 3), ("Bo", 1), 40.0),
+                record(("Cy", 3), ("Bo", 2), 30.0),
+                record(("Ann", 0), ("Cy", 3), 50.0),
+                record(("Di", 3), ("Bo", 0), 20.0),
+                record(("Ann", 3), ("Di", 2), 25.0),
+                record(("Fay", 3), ("Eve", 1), 35.0),
+                record(("Eve", 3), ("Fay", 1), 35.0),
+            ],
+        };
+        let leaderboard = stats.leaderboard();
+        let ranks: Vec<_> = leaderboard.iter().map(|profile| (profile.name.as_str(), profile.wins, profile.losses)).collect();
+        // Cy lost less than Ann, and players with the same wins and losses go by name
+        assert_eq!(ranks, [("Cy", 2, 0), ("Ann", 2, 1), ("Di", 1, 1), ("Eve", 1, 1), ("Fay", 1, 1), ("Bo", 0, 3)]);
+        assert_eq!(leaderboard[1].fastest_win, Some(25.0));
+        assert_eq!(leaderboard[5].fastest_win, None);
+    }
+}
@@ -0,0 +1,199 @@
+use std::{f32::consts::TAU, fs, io, path::Path};
+
+pub const SAMPLE_RATE: u32 = 44_100;
+/// Fade in and out of every tone, so that they do not click
+const ATTACK: f32 = 0.004;
+const RELEASE: f32 = 0.02;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Wave {
+    Square,
+    Sine,
+}
+
+impl Wave {
+    /// Value at `phase`, measured in cycles
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Wave::Square => if phase.fract() < 0.5 { 1.0 } else { -1.0 },
+            Wave::Sine => (phase * TAU).sin(),
+        }
+    }
+}
+
+/// A single note within a sound
+#[derive(Debug, Clone, Copy)]
+pub struct Tone {
+    pub wave: Wave,
+    pub frequency: f32,
+    /// Seconds from the start of the sound
+    pub start: f32,
+    pub duration: f32,
+    pub volume: f32,
+}
+
+impl Tone {
+    const fn new(wave: Wave, frequency: f32, start: f32, duration: f32, volume: f32) -> Tone {
+        Tone { wave, frequency, start, duration, volume }
+    }
+
+    fn envelope(&self, time: f32) -> f32 {
+        let release = RELEASE.min(self.duration / 2.0);
+        let attack = (time / ATTACK).min(1.0);
+        let release = ((self.duration - time) / release).min(1.0);
+        attack.min(release).max(0.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SoundEffect {
+    PaddleHit,
+    WallHit,
+    Goal,
+    /// A second of the countdown before the serve
+    Tick,
+    /// The end of the countdown
+    Serve,
+    Win,
+}
+
+impl SoundEffect {
+    pub const ALL: [SoundEffect; 6] = [
+        SoundEffect::PaddleHit,
+        SoundEffect::WallHit,
+        SoundEffect::Goal,
+        SoundEffect::Tick,
+        SoundEffect::Serve,
+        SoundEffect::Win,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SoundEffect::PaddleHit => "paddle-hit",
+            SoundEffect::WallHit => "wall-hit",
+            SoundEffect::Goal => "goal",
+            SoundEffect::Tick => "tick",
+            SoundEffect::Serve => "serve",
+            SoundEffect::Win => "win",
+        }
+    }
+
+    /// The blips and beeps are close to the ones of the original arcade machine
+    fn tones(self) -> Vec<Tone> {
+        use Wave::*;
+        match self {
+            SoundEffect::PaddleHit => vec![Tone::new(Square, 459.0, 0.0, 0.096, 0.3)],
+            SoundEffect::WallHit => vec![Tone::new(Square, 226.0, 0.0, 0.04, 0.3)],
+            SoundEffect::Goal => vec![Tone::new(Square, 490.0, 0.0, 0.257, 0.3)],
+            SoundEffect::Tick => vec![Tone::new(Sine, 880.0, 0.0, 0.08, 0.5)],
+            SoundEffect::Serve => vec![Tone::new(Sine, 1760.0, 0.0, 0.2, 0.5)],
+            SoundEffect::Win => {
+                // C E G C arpeggio, the last note held with a sine an octave below
+                let mut tones: Vec<Tone> = [523.25, 659.25, 783.99].iter().enumerate()
+                    .map(|(index, frequency)| Tone::new(Square, *frequency, index as f32 * 0.12, 0.11, 0.2))
+                    .collect();
+                tones.push(Tone::new(Square, 1046.5, 0.36, 0.4, 0.2));
+                tones.push(Tone::new(Sine, 523.25, 0.36, 0.4, 0.4));
+                tones
+            },
+        }
+    }
+
+    /// Mono samples between -1 and 1 at `SAMPLE_RATE`
+    pub fn render(self) -> Vec<f32> {
+        let tones = self.tones();
+        let length = tones.iter().map(|tone| tone.start + tone.duration).fold(0.0, f32::max);
+        let mut samples = vec![0.0; (length * SAMPLE_RATE as f32).ceil() as usize];
+        for tone in &tones {
+            let first = (tone.start * SAMPLE_RATE as f32) as usize;
+            let count = (tone.duration * SAMPLE_RATE as f32) as usize;
+            for (index, sample) in samples.iter_mut().skip(first).take(count).enumerate() {
+                let time = index as f32 / SAMPLE_RATE as f32;
+                *sample += tone.wave.sample(time * tone.frequency) * tone.envelope(time) * tone.volume;
+            }
+        }
+        for sample in &mut samples {
+            *sample = sample.clamp(-1.0, 1.0);
+        }
+        samples
+    }
+}
+
+/// `samples` as a 16-bit mono WAV file
+pub fn to_wav(samples: &[f32]) -> Vec<u8> {
+    let data_size = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes()); // bytes per second
+    wav.extend_from_slice(&2u16.to_le_bytes()); // bytes per frame
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&((sample * i16::MAX as f32) as i16).to_le_bytes());
+    }
+    wav
+}
+
+/// Writes every sound effect to `dir` as `<name>.wav`
+pub fn export(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for effect in SoundEffect::ALL {
+        fs::write(dir.join(format!("{}.wav", effect.name())), to_wav(&effect.render()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sounds_are_audible_and_in_range() {
+        for effect in SoundEffect::ALL {
+            let samples = effect.render();
+            assert!(!samples.is_empty(), "{} is empty", effect.name());
+            assert!(samples.iter().all(|sample| (-1.0..=1.0).contains(sample)));
+            let peak = samples.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            assert!(peak > 0.1, "{} is silent", effect.name());
+        }
+    }
+
+    #[test]
+    fn sounds_fade_in_and_out() {
+        for effect in SoundEffect::ALL {
+            let samples = effect.render();
+            assert!(samples[0].abs() < 0.01, "{} starts with a click", effect.name());
+            assert!(samples[samples.len() - 1].abs() < 0.05, "{} ends with a click", effect.name());
+        }
+    }
+
+    #[test]
+    fn wav_header_matches_samples() {
+        let samples = SoundEffect::PaddleHit.render();
+        let wav = to_wav(&samples);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), SAMPLE_RATE);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()) as usize, samples.len() * 2);
+        assert_eq!(wav.len(), 44 + samples.len() * 2);
+    }
+
+    #[test]
+    fn export_writes_every_sound() {
+        let dir = std::env::temp_dir().join(format!("bevy-pong-sounds-{}", std::process::id()));
+        export(&dir).unwrap();
+        for effect in SoundEffect::ALL {
+            let wav = fs::read(dir.join(format!("{}.wav", effect.name()))).unwrap();
+            assert_eq!(wav, to_wav(&effect.render()));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
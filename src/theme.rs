@@ -0,0 +1,299 @@
+use bevy::{
+    prelude::*,
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    ecs::system::SystemParam,
+    reflect::TypeUuid,
+    utils::BoxedFuture
+};
+use serde::{Deserialize, Serialize};
+use crate::accessibility::{adjust_theme, AccessibilitySettings};
+use crate::types::*;
+
+/// Built into the executable, selectable by file name without the extension
+const BUILTIN_THEMES: &[(&str, &str)] = &[
+    ("default", include_str!("../assets/themes/default.theme.ron")),
+    ("classic", include_str!("../assets/themes/classic.theme.ron")),
+    ("neon", include_str!("../assets/themes/neon.theme.ron")),
+    ("amber", include_str!("../assets/themes/amber.theme.ron")),
+];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LightTheme {
+    pub color: Color,
+    pub intensity: f32,
+}
+
+/// Colors, lights and font of everything on screen
+#[derive(Debug, Clone, Serialize, Deserialize, TypeUuid)]
+#[uuid = "5e1c9a0e-1f4b-4c43-9a55-0c2a8a3b7d21"]
+pub struct Theme {
+    pub name: String,
+    pub background: Color,
+    /// Intensity is the brightness of the ambient light
+    pub ambient: LightTheme,
+    pub floor: Color,
+    pub wall: Color,
+    pub ball: Color,
+    pub ball_light: LightTheme,
+    pub left_goal: Color,
+    pub right_goal: Color,
+    pub left_paddle: Color,
+    pub right_paddle: Color,
+    pub left_light: LightTheme,
+    pub right_light: LightTheme,
+    pub text: Color,
+    pub text_secondary: Color,
+    /// Asset path of the font used for all text
+    pub font: String,
+}
+
+impl Theme {
+    pub fn goal(&self, player: Player) -> Color {
+        match player {
+            Player::Left => self.left_goal,
+            Player::Right => self.right_goal,
+        }
+    }
+
+    pub fn paddle(&self, player: Player) -> Color {
+        match player {
+            Player::Left => self.left_paddle,
+            Player::Right => self.right_paddle,
+        }
+    }
+
+    pub fn light(&self, player: Player) -> LightTheme {
+        match player {
+            Player::Left => self.left_light,
+            Player::Right => self.right_light,
+        }
+    }
+
+    /// Surface color of a themed object, `None` for lights and text
+    pub fn color(&self, themed: Themed) -> Option<Color> {
+        match themed {
+            Themed::Floor => Some(self.floor),
+            Themed::Wall => Some(self.wall),
+            Themed::Ball => Some(self.ball),
+            Themed::Goal(player) => Some(self.goal(player)),
+            Themed::Paddle(player) => Some(self.paddle(player)),
+            Themed::PaddleMarker(player) => Some(contrasting(self.paddle(player))),
+            Themed::GoalMarker(player) => Some(contrasting(self.goal(player))),
+            Themed::BallLight | Themed::Light(_) | Themed::Text { .. } | Themed::PlayerText(_) => None,
+        }
+    }
+}
+
+/// Black or white, whichever stands out more against `color`
+fn contrasting(color: Color) -> Color {
+    let [r, g, b, _] = color.as_linear_rgba_f32();
+    if 0.2126 * r + 0.7152 * g + 0.0722 * b > 0.18 { Color::BLACK } else { Color::WHITE }
+}
+
+/// Which part of the theme an entity takes its look from
+#[derive(Component, Debug, Clone, Copy)]
+pub enum Themed {
+    Floor,
+    Wall,
+    Ball,
+    Goal(Player),
+    Paddle(Player),
+    /// Accessibility markers on a paddle or goal, in black or white to stand out from it
+    PaddleMarker(Player),
+    GoalMarker(Player),
+    BallLight,
+    Light(Player),
+    /// The first `primary` sections use the text color and the rest the secondary text color
+    Text { primary: usize },
+    /// Text in the player's paddle color
+    PlayerText(Player),
+}
+
+#[derive(Default)]
+pub struct ThemeLoader;
+
+impl AssetLoader for ThemeLoader {
+    fn load<'a>(&'a self,
+                bytes: &'a [u8],
+                load_context: &'a mut LoadContext) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let theme: Theme = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(theme));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["theme.ron"]
+    }
+}
+
+/// The available themes and the one in use
+pub struct ThemeSelection {
+    pub themes: Vec<Handle<Theme>>,
+    pub current: usize,
+}
+
+/// The selected theme with the accessibility settings applied
+pub struct ResolvedTheme(Theme);
+
+impl Default for ResolvedTheme {
+    /// The default built-in theme, for running the game's screens without `ThemePlugin`
+    fn default() -> Self {
+        let (name, source) = BUILTIN_THEMES[0];
+        ResolvedTheme(ron::from_str(source).unwrap_or_else(|e| panic!("Invalid built-in theme '{}': {}", name, e)))
+    }
+}
+
+/// The theme in use, for systems that spawn or recolor things
+#[derive(SystemParam)]
+pub struct ActiveTheme<'w, 's> {
+    resolved: Res<'w, ResolvedTheme>,
+    asset_server: Res<'w, AssetServer>,
+    #[system_param(ignore)]
+    _marker: std::marker::PhantomData<&'s ()>,
+}
+
+impl<'w, 's> ActiveTheme<'w, 's> {
+    pub fn get(&self) -> &Theme {
+        &self.resolved.0
+    }
+
+    pub fn font(&self) -> Handle<Font> {
+        self.asset_server.load(&self.get().font)
+    }
+
+    pub fn text_style(&self, font_size: f32) -> TextStyle {
+        TextStyle { font: self.font(), font_size, color: self.get().text }
+    }
+
+    pub fn secondary_text_style(&self, font_size: f32) -> TextStyle {
+        TextStyle { font: self.font(), font_size, color: self.get().text_secondary }
+    }
+}
+
+pub struct ThemePlugin {
+    /// Name of a built-in theme or asset path of a `.theme.ron` file
+    pub theme: String,
+}
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Theme>().init_asset_loader::<ThemeLoader>();
+
+        let mut themes = app.world.resource_mut::<Assets<Theme>>();
+        let mut handles: Vec<Handle<Theme>> = BUILTIN_THEMES.iter()
+            .map(|(name, source)| {
+                let theme: Theme = ron::from_str(source)
+                    .unwrap_or_else(|e| panic!("Invalid built-in theme '{}': {}", name, e));
+                themes.add(theme)
+            })
+            .collect();
+        let current = match BUILTIN_THEMES.iter().position(|(name, _)| *name == self.theme) {
+            Some(index) => index,
+            None if self.theme.ends_with(".theme.ron") => {
+                handles.push(app.world.resource::<AssetServer>().load(&self.theme));
+                handles.len() - 1
+            },
+            None => {
+                warn!("Unknown theme '{}', using the default theme", self.theme);
+                0
+            }
+        };
+
+        // Until `resolve_theme` has run, a theme file that is still loading is replaced by the default
+        let initial_handle = &handles[if current < BUILTIN_THEMES.len() { current } else { 0 }];
+        let initial = app.world.resource::<Assets<Theme>>().get(initial_handle).unwrap().clone();
+
+        app
+            .insert_resource(ThemeSelection { themes: handles, current })
+            .insert_resource(ResolvedTheme(initial))
+            .add_startup_system_to_stage(StartupStage::PreStartup, resolve_theme)
+            .add_system(cycle_theme)
+            .add_system(resolve_theme.after(cycle_theme))
+            .add_system(apply_theme.after(resolve_theme));
+    }
+}
+
+fn cycle_theme(mut selection: ResMut<ThemeSelection>,
+               themes: Res<Assets<Theme>>,
+               keyboard_input: Res<Input<KeyCode>>) {
+    if keyboard_input.just_pressed(KeyCode::F2) {
+        selection.current = (selection.current + 1) % selection.themes.len();
+        if let Some(theme) = themes.get(&selection.themes[selection.current]) {
+            info!("Theme: {}", theme.name);
+        }
+    }
+}
+
+fn resolve_theme(selection: Res<ThemeSelection>,
+                 themes: Res<Assets<Theme>>,
+                 accessibility: Res<AccessibilitySettings>,
+                 mut theme_events: EventReader<AssetEvent<Theme>>,
+                 mut resolved: ResMut<ResolvedTheme>) {
+    // Also reapplies when a theme file is edited while the game runs
+    let theme_loaded = theme_events.iter().count() > 0;
+    if !selection.is_changed() && !accessibility.is_changed() && !theme_loaded {
+        return;
+    }
+    // A theme loaded from a file may not be ready yet, the built-in default always is
+    let theme = themes.get(&selection.themes[selection.current])
+        .unwrap_or_else(|| themes.get(&selection.themes[0]).unwrap());
+    resolved.0 = adjust_theme(theme, &accessibility);
+}
+
+fn apply_theme(theme: ActiveTheme,
+               mut clear_color: ResMut<ClearColor>,
+               mut ambient_light: ResMut<AmbientLight>,
+               mut materials: ResMut<Assets<StandardMaterial>>,
+               material_query: Query<(&Themed, &Handle<StandardMaterial>)>,
+               mut sprite_query: Query<(&Themed, &mut Sprite)>,
+               mut light_query: Query<(&Themed, &mut PointLight)>,
+               mut text_query: Query<(&Themed, &mut Text)>) {
+    if !theme.resolved.is_changed() {
+        return;
+    }
+    let font = theme.font();
+    let theme = theme.get();
+
+    clear_color.0 = theme.background;
+    ambient_light.color = theme.ambient.color;
+    ambient_light.brightness = theme.ambient.intensity;
+
+    for (themed, handle) in material_query.iter() {
+        if let (Some(material), Some(color)) = (materials.get_mut(handle), theme.color(*themed)) {
+            material.base_color = color;
+            if let Themed::Ball = themed {
+                material.emissive = color;
+            }
+        }
+    }
+
+    for (themed, mut sprite) in sprite_query.iter_mut() {
+        if let Some(color) = theme.color(*themed) {
+            sprite.color = color;
+        }
+    }
+
+    for (themed, mut light) in light_query.iter_mut() {
+        let light_theme = match themed {
+            Themed::BallLight => theme.ball_light,
+            Themed::Light(player) => theme.light(*player),
+            _ => continue,
+        };
+        light.color = light_theme.color;
+        light.intensity = light_theme.intensity;
+    }
+
+    for (themed, mut text) in text_query.iter_mut() {
+        for (index, section) in text.sections.iter_mut().enumerate() {
+            section.style.font = font.clone();
+            section.style.color = match themed {
+                Themed::Text { primary } if index < *primary => theme.text,
+                Themed::Text { .. } => theme.text_secondary,
+                Themed::PlayerText(player) => theme.paddle(*player),
+                _ => continue,
+            };
+        }
+    }
+}
@@ -2,6 +2,9 @@ use bevy::{
     prelude::*,
     app::AppExit
 };
+use crate::locale::Locale;
+use crate::save::SaveFile;
+use crate::theme::{ActiveTheme, Themed};
 use crate::types::*;
 
 pub struct TitlePlugin;
@@ -15,7 +18,18 @@ impl Plugin for TitlePlugin {
     }
 }
 
-pub fn title_enter(mut commands: Commands, asset_server: Res<AssetServer>) {
+pub fn title_enter(mut commands: Commands,
+                   theme: ActiveTheme,
+                   locale: Res<Locale>,
+                   save_file: Option<Res<SaveFile>>) {
+    let continue_text = match save_file.map(|save_file| save_file.load()) {
+        Some(Ok(Some(_))) => format!("\n{}", locale.get("title-continue")),
+        Some(Err(e)) => {
+            warn!("{}", e);
+            format!("\n{}", locale.format("title-cannot-continue", &[("error", &e.to_string())]))
+        },
+        _ => String::new(),
+    };
     commands.spawn_bundle(NodeBundle {
         style: Style {
             size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
@@ -33,26 +47,35 @@ pub fn title_enter(mut commands: Commands, asset_server: Res<AssetServer>) {
             text: Text {
                 sections: vec![
                     TextSection {
-                        value: "Pong".to_string(),
-                        style: TextStyle {
-                            font: asset_server.load("fonts/DejaVuSansMono-Bold.ttf"),
-                            font_size: 100.0,
-                            color: Color::WHITE
-                        }
+                        value: locale.get("title"),
+                        style: theme.text_style(100.0)
+                    },
+                    TextSection {
+                        value: format!("\n{}", locale.get("title-start")),
+                        style: theme.secondary_text_style(70.0)
+                    },
+                    TextSection {
+                        value: continue_text,
+                        style: theme.secondary_text_style(40.0)
+                    },
+                    TextSection {
+                        value: format!("\n{}", locale.get("title-records")),
+                        style: theme.secondary_text_style(40.0)
                     },
                     TextSection {
-                        value: "\nPress SPACE/A to start!".to_string(),
-                        style: TextStyle {
-                            font: asset_server.load("fonts/DejaVuSansMono-Bold.ttf"),
-                            font_size: 70.0,
-                            color: Color::GRAY
-                        }
+                        value: format!("\n{}", locale.get("title-lan")),
+                        style: theme.secondary_text_style(40.0)
+                    },
+                    TextSection {
+                        value: format!("\n{}", locale.get("title-replays")),
+                        style: theme.secondary_text_style(40.0)
                     }
                 ],
                alignment: TextAlignment::TOP_CENTER
             },
             ..Default::default()
-        });
+        })
+        .insert(Themed::Text { primary: 1 });
     });
 }
 
@@ -60,21 +83,49 @@ pub fn title_update(mut exit_events: EventWriter<AppExit>,
                     mut state: ResMut<State<AppState>>,
                     keyboard_input: Res<Input<KeyCode>>,
                     gamepads: Res<Gamepads>,
-                    gamepad_buttons: Res<Input<GamepadButton>>) {
+                    gamepad_buttons: Res<Input<GamepadButton>>,
+                    save_file: Option<Res<SaveFile>>) {
     let mut start = keyboard_input.just_released(KeyCode::Space);
+    let mut resume = keyboard_input.just_released(KeyCode::C);
+    let mut records = keyboard_input.just_released(KeyCode::R);
+    let mut lan = keyboard_input.just_released(KeyCode::L);
+    let mut replays = keyboard_input.just_released(KeyCode::V);
     let mut quit = keyboard_input.just_released(KeyCode::Escape);
 
     for gamepad in gamepads.iter() {
         if gamepad_buttons.just_released(GamepadButton::new(*gamepad, GamepadButtonType::South)) {
             start = true;
         }
+        if gamepad_buttons.just_released(GamepadButton::new(*gamepad, GamepadButtonType::North)) {
+            records = true;
+        }
+        if gamepad_buttons.just_released(GamepadButton::new(*gamepad, GamepadButtonType::West)) {
+            resume = true;
+        }
+        if gamepad_buttons.just_released(GamepadButton::new(*gamepad, GamepadButtonType::Select)) {
+            lan = true;
+        }
+        if gamepad_buttons.just_released(GamepadButton::new(*gamepad, GamepadButtonType::RightTrigger)) {
+            replays = true;
+        }
         if gamepad_buttons.just_released(GamepadButton::new(*gamepad, GamepadButtonType::East)) {
             quit = true;
         }
     }
     
+    // A save that cannot be continued is caught here, coming back from Continue in the same
+    // frame would find the key still released and go round again
+    resume = resume && save_file.is_some_and(|save_file| save_file.can_continue());
     if start {
         state.set(AppState::NewGame).unwrap()
+    } else if resume {
+        state.set(AppState::Continue).unwrap()
+    } else if records {
+        state.set(AppState::Records).unwrap()
+    } else if lan {
+        state.set(AppState::LanGames).unwrap()
+    } else if replays {
+        state.set(AppState::Replays).unwrap()
     } else if quit {
         exit_events.send(AppExit);
     }
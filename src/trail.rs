@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+use bevy::{
+    prelude::*,
+    render::texture::DEFAULT_IMAGE_HANDLE
+};
+use crate::consts::*;
+use crate::theme::ActiveTheme;
+use crate::types::*;
+
+const TRAIL_GHOSTS: usize = 12;
+/// How far back the trail reaches, so a faster ball leaves a longer trail
+const TRAIL_DURATION: f32 = 0.12;
+const MAX_TRAIL_POINTS: usize = 64;
+/// Keeps the trail just below the ball in the 2D view
+const TRAIL_Z: f32 = -0.05;
+
+/// Recent path of a ball and the ghosts drawn along it
+#[derive(Component)]
+pub struct Trail {
+    ghosts: Vec<Entity>,
+    /// Newest position first
+    points: VecDeque<Vec3>,
+    /// Paddle that last hit the ball, the trail takes its color
+    last_hit: Option<Player>,
+}
+
+impl Trail {
+    fn clear(&mut self) {
+        self.points.clear();
+        self.last_hit = None;
+    }
+
+    /// The point `distance` back along the trail, `None` if the trail is shorter than that
+    fn point_at(&self, distance: f32) -> Option<Vec3> {
+        let mut remaining = distance;
+        for (newer, older) in self.points.iter().zip(self.points.iter().skip(1)) {
+            let length = newer.distance(*older);
+            if remaining <= length && length > 0.0 {
+                return Some(newer.lerp(*older, remaining / length));
+            }
+            remaining -= length;
+        }
+        None
+    }
+}
+
+#[derive(Component)]
+struct TrailGhost;
+
+pub struct TrailPlugin;
+
+impl Plugin for TrailPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            // Runs after the balls from `setup` exist
+            .add_startup_system_to_stage(StartupStage::PostStartup, setup_trails)
+            // The ball is teleported when a match starts, continues or a new point is served
+            .add_system_set(SystemSet::on_enter(AppState::Ready).with_system(clear_trails))
+            .add_system_set(SystemSet::on_exit(AppState::Continue).with_system(clear_trails))
+            // The ball stands still while idle, its trail would stay in view of the app's cameras
+            .add_system_set(SystemSet::on_enter(AppState::Idle).with_system(hide_trails))
+            .add_system(track_hits)
+            .add_system(update_trails.after(track_hits));
+    }
+}
+
+fn setup_trails(mut commands: Commands,
+                mut meshes: ResMut<Assets<Mesh>>,
+                mut materials: ResMut<Assets<StandardMaterial>>,
+                ball_query: Query<Entity, With<Ball>>) {
+    let mesh = meshes.add(Mesh::from(shape::Icosphere { radius: BALL_SIZE/2.0, subdivisions: 8 }));
+    for ball in ball_query.iter() {
+        let ghosts = (0..TRAIL_GHOSTS)
+            .map(|_| {
+                let material = materials.add(StandardMaterial {
+                    unlit: true,
+                    alpha_mode: AlphaMode::Blend,
+                    ..Default::default()
+                });
+                commands.spawn_bundle(PbrBundle {
+                    mesh: mesh.clone(),
+                    material,
+                    visibility: Visibility { is_visible: false },
+                    ..Default::default()
+                })
+                .insert_bundle((Sprite { custom_size: Some(Vec2::splat(BALL_SIZE)), ..Default::default() },
+                                DEFAULT_IMAGE_HANDLE.typed::<Image>()))
+                .insert(TrailGhost)
+                .id()
+            })
+            .collect();
+        commands.entity(ball).insert(Trail { ghosts, points: VecDeque::new(), last_hit: None });
+    }
+}
+
+fn clear_trails(mut trail_query: Query<&mut Trail>) {
+    for mut trail in trail_query.iter_mut() {
+        trail.clear();
+    }
+}
+
+/// Also hides the ghosts right away, the trails may already have been updated this frame
+fn hide_trails(mut trail_query: Query<&mut Trail>, mut ghost_query: Query<&mut Visibility, With<TrailGhost>>) {
+    for mut trail in trail_query.iter_mut() {
+        trail.clear();
+    }
+    for mut visibility in ghost_query.iter_mut() {
+        visibility.is_visible = false;
+    }
+}
+
+fn track_hits(mut events: EventReader<PongEvent>, mut trail_query: Query<(&mut Trail, &Transform)>) {
+    for e in events.iter() {
+        if let PongEvent::Bounce(Collider::Paddle(player), contact) = e {
+            // With several balls, the one that touched the paddle is the one closest to the contact
+            let closest = trail_query.iter_mut()
+                .min_by(|(_, a), (_, b)| a.translation.distance(*contact).total_cmp(&b.translation.distance(*contact)));
+            if let Some((mut trail, _)) = closest {
+                trail.last_hit = Some(*player);
+            }
+        }
+    }
+}
+
+fn update_trails(theme: ActiveTheme,
+                 mut materials: ResMut<Assets<StandardMaterial>>,
+                 mut trail_query: Query<(&mut Trail, &Transform, &Moving)>,
+                 mut ghost_query: Query<(&mut Transform, &mut Visibility, &mut Sprite, &Handle<StandardMaterial>),
+                                        (With<TrailGhost>, Without<Trail>)>) {
+    let theme = theme.get();
+    for (mut trail, transform, moving) in trail_query.iter_mut() {
+        // Only record movement, so a paused ball keeps its trail
+        if trail.points.front() != Some(&transform.translation) {
+            trail.points.push_front(transform.translation);
+            trail.points.truncate(MAX_TRAIL_POINTS);
+        }
+
+        let length = moving.velocity.length() * TRAIL_DURATION;
+        let color = trail.last_hit.map_or(theme.ball, |player| theme.paddle(player));
+        for (index, ghost) in trail.ghosts.iter().enumerate() {
+            let (mut ghost_transform, mut visibility, mut sprite, material) = match ghost_query.get_mut(*ghost) {
+                Ok(ghost) => ghost,
+                Err(_) => continue,
+            };
+            // Ghosts further back are smaller and fainter
+            let fraction = (index + 1) as f32 / TRAIL_GHOSTS as f32;
+            match trail.point_at(length * fraction) {
+                Some(point) => {
+                    ghost_transform.translation = point.truncate().extend(point.z + TRAIL_Z);
+                    ghost_transform.scale = Vec3::splat(1.0 - fraction * 0.8);
+                    visibility.is_visible = true;
+                },
+                None => {
+                    visibility.is_visible = false;
+                    continue;
+                }
+            }
+            let mut ghost_color = color;
+            ghost_color.set_a(0.6 * (1.0 - fraction));
+            sprite.color = ghost_color;
+            if let Some(material) = materials.get_mut(material) {
+                material.base_color = ghost_color;
+            }
+        }
+    }
+}
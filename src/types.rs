@@ -1,8 +1,188 @@
+use std::{fmt, str::FromStr, time::Duration};
 use bevy::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use crate::consts::*;
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Player { Left, Right }
 
+/// What moves a paddle.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Controller {
+    /// The player's side of the keyboard (A/Z or K/M) and the matching stick of every gamepad
+    Local,
+    /// The player's side of the keyboard only
+    Keyboard,
+    /// Left stick of a single gamepad
+    Gamepad(usize),
+    /// Computer-controlled, follows the ball
+    Cpu,
+    /// Input received over the network, set by an online match for both paddles
+    Network,
+    /// Commands from a program connected over the bot protocol, see `bot::BotServer`
+    Bot,
+}
+
+impl fmt::Display for Controller {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Controller::Local => write!(f, "local"),
+            Controller::Keyboard => write!(f, "keyboard"),
+            Controller::Gamepad(id) => write!(f, "gamepad:{}", id),
+            Controller::Cpu => write!(f, "cpu"),
+            Controller::Network => write!(f, "network"),
+            Controller::Bot => write!(f, "bot"),
+        }
+    }
+}
+
+impl FromStr for Controller {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(Controller::Local),
+            "keyboard" => Ok(Controller::Keyboard),
+            "gamepad" => Ok(Controller::Gamepad(0)),
+            "cpu" => Ok(Controller::Cpu),
+            "bot" => Ok(Controller::Bot),
+            _ => s.strip_prefix("gamepad:")
+                .and_then(|id| id.parse().ok())
+                .map(Controller::Gamepad)
+                .ok_or_else(|| format!("unknown controller '{}', expected local, keyboard, gamepad[:N], cpu or bot", s))
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Controllers {
+    pub left: Controller,
+    pub right: Controller,
+}
+
+impl Controllers {
+    pub fn get(&self, player: Player) -> Controller {
+        match player {
+            Player::Left => self.left,
+            Player::Right => self.right,
+        }
+    }
+}
+
+impl Default for Controllers {
+    fn default() -> Self {
+        Controllers { left: Controller::Local, right: Controller::Local }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchRules {
+    /// Goals needed to win a set
+    pub goals_to_win: u32,
+    /// Sets needed to win the match, 1 plays a single set
+    pub sets_to_win: u32,
+    /// Seconds of play after which the leading player wins, a tied match goes on until the next goal
+    pub time_limit: Option<f32>,
+}
+
+impl MatchRules {
+    pub fn has_sets(&self) -> bool {
+        self.sets_to_win > 1
+    }
+
+    pub fn time_is_up(&self, score: &Score) -> bool {
+        self.time_limit.is_some_and(|limit| score.time >= limit)
+    }
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        MatchRules { goals_to_win: GOALS_TO_WIN, sets_to_win: 1, time_limit: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerNames {
+    pub left: String,
+    pub right: String,
+}
+
+impl PlayerNames {
+    pub fn get(&self, player: Player) -> &str {
+        match player {
+            Player::Left => &self.left,
+            Player::Right => &self.right,
+        }
+    }
+}
+
+impl Default for PlayerNames {
+    fn default() -> Self {
+        PlayerNames { left: "Left".to_string(), right: "Right".to_string() }
+    }
+}
+
+/// The player serving the current point
+pub struct Serve(pub Player);
+
+/// How far the game simulation advances in a tick, always the fixed tick of `net::tick_duration`
+/// so that the same inputs play the same match
+#[derive(Default)]
+pub struct SimTime {
+    pub delta: Duration,
+}
+
+impl SimTime {
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+}
+
+/// Time spent in the current phase of the match: `Ready`, `Goal` or `Win`
+#[derive(Default)]
+pub struct PhaseTimer(pub Timer);
+
+/// The state the simulation asked to move to, passed on to the state machine after the step
+#[derive(Default)]
+pub struct NextState(pub Option<AppState>);
+
+/// The state the game starts in and goes back to when a match is over or left, `Title` unless
+/// an app embedding the game has a menu of its own to go back to, usually `Idle`
+#[derive(Debug, Clone)]
+pub struct RootState(pub AppState);
+
+impl Default for RootState {
+    fn default() -> Self {
+        RootState(AppState::Title)
+    }
+}
+
+impl RootState {
+    /// The root state of `app`, for plugins that run systems on entering it. Set before adding them.
+    pub fn of(app: &mut App) -> AppState {
+        app.init_resource::<RootState>().world.resource::<RootState>().0.clone()
+    }
+}
+
+/// Source of all gameplay randomness, seeded again at the start of every match
+#[derive(Clone, Deref, DerefMut)]
+pub struct GameRng(pub ChaCha8Rng);
+
+/// Random seed of the match being played, and of the next one
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct MatchSeed {
+    pub current: u64,
+    pub next: u64,
+}
+
+impl MatchSeed {
+    pub fn new(seed: u64) -> Self {
+        MatchSeed { current: seed, next: seed }
+    }
+}
+
 #[derive(Component)]
 pub struct Ball;
 
@@ -14,11 +194,11 @@ pub struct Moving {
     pub velocity: Vec3
 }
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Collider {
     Wall,
     Ball,
-    Paddle,
+    Paddle(Player),
     Goal(Player)
 }
 
@@ -34,36 +214,120 @@ pub struct ScoreText(pub Player);
 #[derive(Component)]
 pub struct TitleText;
 
+#[derive(Component)]
+pub struct RecordsText;
+
+#[derive(Component)]
+pub struct LanText;
+
+#[derive(Component)]
+pub struct ReplaysText;
+
+#[derive(Component)]
+pub struct PlaybackText;
+
+#[derive(Component)]
+pub struct LobbyText;
+
+/// The lobby's text telling how connecting goes, or why it did not
+#[derive(Component)]
+pub struct LobbyStatusText;
+
+#[derive(Component)]
+pub struct PauseText;
+
 #[derive(Component)]
 pub struct GoalText;
 
 #[derive(Component)]
 pub struct WinText;
 
+#[derive(Component)]
+pub struct GoalReplayText;
+
 #[derive(Component)]
 pub struct ReadyText;
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PongEvent {
     Goal(Player),
+    /// The ball changed direction after hitting something, at the given contact point
+    Bounce(Collider, Vec3),
+    /// Seconds left before the ball is served, 0 when it is
+    Countdown(u32),
 }
 
 //       <------- Win <--------------------          
 //      /                                  \
 // Title -> NewGame -> Ready -> InGame -> Goal
-//                        \                /
-//                         <---------------
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+//   |                    \                /
+//   |                     <---------------
+//   +-> Records -> Title
+//   +-> LanGames -> Title or Lobby
+//   +-> Replays -> Title, or NewGame to play a replay, which goes back to Replays
+//   +-> Continue -> Ready, InGame or Goal, whichever was saved
+//
+// Lobby -> NewGame, online matches start here and leave to Title when the connection ends
+//
+// Paused is pushed on top of InGame, except in online matches. GoalReplay is pushed on top of Goal
+// to show the goal again in local matches.
+//
+// Matches go back to `RootState`, which is Title above. An app embedding the game can have them
+// end in Idle instead, where the game shows and plays nothing until the app starts a new game.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum AppState {
+    Idle,
     Title,
+    Lobby,
+    Records,
+    LanGames,
+    Replays,
     NewGame,
+    Continue,
     Ready,
     InGame,
+    Paused,
     Goal,
+    GoalReplay,
     Win
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Score {
+    /// Goals in the current set
     pub left: u32,
     pub right: u32,
+    #[serde(default)]
+    pub left_sets: u32,
+    #[serde(default)]
+    pub right_sets: u32,
+    /// Seconds of play, the clock only runs while the ball is in play
+    #[serde(default)]
+    pub time: f32,
+}
+
+impl Score {
+    pub fn goals(&self, player: Player) -> u32 {
+        match player {
+            Player::Left => self.left,
+            Player::Right => self.right,
+        }
+    }
+
+    pub fn sets(&self, player: Player) -> u32 {
+        match player {
+            Player::Left => self.left_sets,
+            Player::Right => self.right_sets,
+        }
+    }
+
+    /// The player ahead on sets, then on goals, `None` if level
+    pub fn leader(&self) -> Option<Player> {
+        match (self.left_sets, self.left).cmp(&(self.right_sets, self.right)) {
+            std::cmp::Ordering::Greater => Some(Player::Left),
+            std::cmp::Ordering::Less => Some(Player::Right),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
 }
 
@@ -0,0 +1,227 @@
+use bevy::{
+    prelude::*,
+    core_pipeline::clear_color::ClearColorConfig,
+    render::{camera::ScalingMode, texture::DEFAULT_IMAGE_HANDLE, view::RenderLayers}
+};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use crate::types::*;
+use crate::camera::CameraRig;
+use crate::consts::*;
+use crate::locale::Locale;
+use crate::theme::{ActiveTheme, Themed};
+
+/// How the playfield is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum ViewMode {
+    /// Tilted perspective view with lighting
+    #[value(name = "3d")]
+    ThreeD,
+    /// Flat top-down view of plain rectangles, lighter on the GPU
+    #[value(name = "2d")]
+    TwoD,
+}
+
+/// Draws only the UI, over the whole window. Sprites are kept out by its render layer.
+#[derive(Component)]
+pub struct UiCamera;
+
+/// The game's 3D camera, cameras of an app embedding the game are left alone
+#[derive(Component)]
+pub struct PongCamera3d;
+
+/// The game's 2D camera for the flat view
+#[derive(Component)]
+pub struct PongCamera2d;
+
+const UI_LAYER: u8 = 31;
+
+pub struct VisualsPlugin {
+    pub view: ViewMode,
+}
+
+impl Plugin for VisualsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(self.view)
+            // Runs after the game entities from `setup` exist
+            .add_startup_system_to_stage(StartupStage::PostStartup, setup_visuals)
+            .add_system(toggle_view)
+            .add_system(apply_view.after(toggle_view))
+            .add_system_set(SystemSet::on_enter(AppState::Idle).with_system(hide_when_idle))
+            .add_system_set(SystemSet::on_exit(AppState::Idle).with_system(show_after_idle));
+    }
+}
+
+fn setup_visuals(mut commands: Commands,
+                 mut meshes: ResMut<Assets<Mesh>>,
+                 mut materials: ResMut<Assets<StandardMaterial>>,
+                 theme: ActiveTheme,
+                 locale: Res<Locale>,
+                 colliding_query: Query<(Entity, &Colliding)>) {
+    let font = theme.font();
+    let theme = theme.get();
+    // Floor
+    let floor_material = materials.add(StandardMaterial {
+        base_color: theme.floor,
+        metallic: 0.0,
+        reflectance: 0.0,
+        ..StandardMaterial::default()
+    });
+    commands.spawn_bundle(PbrBundle {
+        mesh: meshes.add(Mesh::from(shape::Box::new(AREA_WIDTH, AREA_HEIGHT, WALL_THICKNESS))),
+        material: floor_material,
+        transform: Transform::from_xyz(0.0, 0.0, -WALL_THICKNESS),
+        ..Default::default()
+    })
+    .insert(Themed::Floor);
+
+    let wall_material = materials.add(theme.wall.into());
+    let ball_material = materials.add(StandardMaterial {
+        base_color: theme.ball,
+        emissive: theme.ball,
+        reflectance: 0.0,
+        ..StandardMaterial::default()
+    });
+    let ball_mesh = meshes.add(Mesh::from(shape::Icosphere { radius: BALL_SIZE/2.0, subdivisions: 64 }));
+
+    for (entity, colliding) in colliding_query.iter() {
+        let box_mesh = || Mesh::from(shape::Box::new(colliding.size.x, colliding.size.y, WALL_THICKNESS));
+        let (mesh, material, themed) = match colliding.kind {
+            Collider::Wall => (meshes.add(box_mesh()), wall_material.clone(), Themed::Wall),
+            Collider::Goal(player) => (meshes.add(box_mesh()), materials.add(theme.goal(player).into()), Themed::Goal(player)),
+            Collider::Paddle(player) => (meshes.add(box_mesh()), materials.add(theme.paddle(player).into()), Themed::Paddle(player)),
+            Collider::Ball => (ball_mesh.clone(), ball_material.clone(), Themed::Ball),
+        };
+        // Everything is both a mesh for the 3D camera and a sprite for the 2D camera,
+        // only the active camera draws its half
+        let sprite = Sprite {
+            color: theme.color(themed).unwrap_or_default(),
+            custom_size: Some(colliding.size),
+            ..Default::default()
+        };
+        let mut entity_commands = commands.entity(entity);
+        entity_commands
+            .insert_bundle((mesh, material, themed, Visibility::default(), ComputedVisibility::default()))
+            .insert_bundle((sprite, DEFAULT_IMAGE_HANDLE.typed::<Image>()));
+        if let Collider::Ball = colliding.kind {
+            entity_commands.with_children(|parent| {
+                parent.spawn_bundle(PointLightBundle {
+                    point_light: PointLight {
+                        color: theme.ball_light.color,
+                        intensity: theme.ball_light.intensity,
+                        shadows_enabled: true,
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(0.0, 0.0, BALL_SIZE * 2.0),
+                    ..Default::default()
+                })
+                .insert(Themed::BallLight);
+            });
+        }
+    }
+
+    // cameras, one of them active depending on the view mode, and one for the UI on top
+    commands.spawn_bundle(Camera3dBundle {
+        transform: Transform::from_xyz(0.0, -AREA_HEIGHT/2.0, AREA_WIDTH).looking_at(Vec3::ZERO, Vec3::Z),
+        ..Default::default()
+    })
+    .insert(CameraRig::default())
+    .insert(UiCameraConfig { show_ui: false })
+    .insert(PongCamera3d);
+    let mut camera_2d = Camera2dBundle::default();
+    camera_2d.camera.priority = 1;
+    camera_2d.projection.scaling_mode = ScalingMode::Auto {
+        min_width: AREA_WIDTH + WALL_THICKNESS,
+        min_height: AREA_HEIGHT + WALL_THICKNESS
+    };
+    commands.spawn_bundle(camera_2d)
+        .insert(UiCameraConfig { show_ui: false })
+        .insert(PongCamera2d);
+    let mut ui_camera = Camera2dBundle::default();
+    ui_camera.camera.priority = 2;
+    ui_camera.camera_2d.clear_color = ClearColorConfig::None;
+    commands.spawn_bundle(ui_camera)
+        .insert(RenderLayers::layer(UI_LAYER))
+        .insert(UiCamera);
+
+    // lights
+    for (player, x) in [(Player::Left, -AREA_WIDTH/4.0), (Player::Right, AREA_WIDTH/4.0)] {
+        commands.spawn_bundle(PointLightBundle {
+            point_light: PointLight {
+                color: theme.light(player).color,
+                intensity: theme.light(player).intensity,
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(x, 0.0, 10.0),
+            ..Default::default()
+        })
+        .insert(Themed::Light(player));
+    }
+
+    let big_text = |value: &str| TextBundle {
+        text: Text::from_section(value, TextStyle {
+            font: font.clone(),
+            font_size: 100.0,
+            color: theme.text
+        }),
+        style: Style {
+            display: Display::None,
+            margin: UiRect::all(Val::Auto),
+            align_self: AlignSelf::Center,
+            ..Style::default()
+        },
+        ..Default::default()
+    };
+    commands.spawn_bundle(big_text("")).insert(ReadyText).insert(Themed::Text { primary: 1 });
+    commands.spawn_bundle(big_text(&locale.get("goal"))).insert(GoalText).insert(Themed::Text { primary: 1 });
+    commands.spawn_bundle(big_text("")).insert(WinText).insert(Themed::Text { primary: 1 });
+}
+
+fn toggle_view(mut view: ResMut<ViewMode>, keyboard_input: Res<Input<KeyCode>>) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        *view = match *view {
+            ViewMode::ThreeD => ViewMode::TwoD,
+            ViewMode::TwoD => ViewMode::ThreeD,
+        };
+        info!("View: {:?}", *view);
+    }
+}
+
+/// Nothing is shown while the game is idle, the app embedding it has cameras of its own
+fn apply_view(view: Res<ViewMode>,
+              state: Res<State<AppState>>,
+              mut camera_3d_query: Query<&mut Camera, (With<PongCamera3d>, Without<PongCamera2d>, Without<UiCamera>)>,
+              mut camera_2d_query: Query<&mut Camera, (With<PongCamera2d>, Without<PongCamera3d>, Without<UiCamera>)>,
+              mut ui_camera_query: Query<&mut Camera, (With<UiCamera>, Without<PongCamera3d>, Without<PongCamera2d>)>) {
+    let shown = *state.current() != AppState::Idle;
+    // Runs every frame until the cameras exist, then only on changes
+    if !view.is_changed() && !state.is_changed()
+        && camera_3d_query.iter().all(|camera| camera.is_active == (shown && *view == ViewMode::ThreeD)) {
+        return;
+    }
+    for mut camera in camera_3d_query.iter_mut() {
+        camera.is_active = shown && *view == ViewMode::ThreeD;
+    }
+    for mut camera in camera_2d_query.iter_mut() {
+        camera.is_active = shown && *view == ViewMode::TwoD;
+    }
+    for mut camera in ui_camera_query.iter_mut() {
+        camera.is_active = shown;
+    }
+}
+
+/// The field and the lights exist from startup on. While the game is idle they are hidden, the
+/// cameras of an app embedding the game would draw them too. Children go with their parents, and
+/// the game's UI is hidden by its display style.
+fn hide_when_idle(mut visibility_query: Query<&mut Visibility, (With<Themed>, Without<Parent>, Without<Node>)>) {
+    for mut visibility in visibility_query.iter_mut() {
+        visibility.is_visible = false;
+    }
+}
+
+fn show_after_idle(mut visibility_query: Query<&mut Visibility, (With<Themed>, Without<Parent>, Without<Node>)>) {
+    for mut visibility in visibility_query.iter_mut() {
+        visibility.is_visible = true;
+    }
+}
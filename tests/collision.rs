@@ -0,0 +1,222 @@
+//! Property tests of the ball's collisions. Each case puts the ball and the paddles somewhere on
+//! the field, gives the ball a velocity and the paddles random inputs, and plays the simulation
+//! tick by tick until a goal. The cases come from seeds counted up from a fixed one, so that every
+//! run tries the same. A failing case is shrunk to a simpler one that still fails and printed, to
+//! be added to `tests/regressions/collision.ron`, where it is tried first from then on.
+
+use std::{env, fmt};
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use bevy_pong::{
+    accessibility::AccessibilitySettings,
+    consts::*,
+    game::{play_tick, simulation_stage},
+    net::{tick_duration, NetInputs},
+    setup::setup,
+    types::*
+};
+
+/// New cases tried on each run, `PONG_PROPERTY_CASES` overrides it
+const CASES: u64 = 256;
+/// Seed of the first new case, `PONG_PROPERTY_SEED` overrides it
+const SEED: u64 = 0x5eed;
+/// Longest a case plays, in ticks
+const MAX_TICKS: usize = 900;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Case {
+    ball: Vec2,
+    velocity: Vec2,
+    /// y of the left and right paddle
+    paddles: [f32; 2],
+    game_speed: f32,
+    /// Paddle inputs of each tick, the case ends when they do
+    inputs: Vec<[i8; 2]>,
+}
+
+impl Case {
+    fn generate(rng: &mut ChaCha8Rng) -> Case {
+        let half = Vec2::new(AREA_WIDTH, AREA_HEIGHT) / 2.0 - Vec2::splat(WALL_THICKNESS + BALL_SIZE);
+        // Up to the speed of a serve, the fastest the ball goes as bounces only turn it around
+        let mut speed = || (if rng.gen() { 1.0 } else { -1.0 }) * BALL_SPEED * rng.gen_range(0.25..=1.0);
+        let velocity = Vec2::new(speed(), speed());
+        let mut inputs = Vec::with_capacity(MAX_TICKS);
+        let mut input = [0, 0];
+        for _ in 0..rng.gen_range(1..=MAX_TICKS) {
+            // Held for a while, like a player would
+            for direction in input.iter_mut() {
+                if rng.gen_bool(0.05) {
+                    *direction = *[-127, 0, 127, rng.gen()].get(rng.gen_range(0..4)).unwrap();
+                }
+            }
+            inputs.push(input);
+        }
+        Case {
+            ball: Vec2::new(rng.gen_range(-half.x..=half.x), rng.gen_range(-half.y..=half.y)),
+            velocity,
+            paddles: [(); 2].map(|_| rng.gen_range(-half.y..=half.y)),
+            game_speed: rng.gen_range(0.25..=2.0),
+            inputs,
+        }
+    }
+
+    /// Simpler cases to try in place of this one, most shrinking first
+    fn shrink(&self) -> Vec<Case> {
+        let mut cases = Vec::new();
+        let mut with = |change: &dyn Fn(&mut Case)| {
+            let mut case = self.clone();
+            change(&mut case);
+            if case != *self {
+                cases.push(case);
+            }
+        };
+        with(&|case| case.inputs.truncate(case.inputs.len() / 2));
+        with(&|case| case.inputs.truncate(case.inputs.len() - 1));
+        with(&|case| case.inputs.iter_mut().for_each(|input| *input = [0, 0]));
+        with(&|case| {
+            let half = case.inputs.len() / 2;
+            case.inputs[..half].iter_mut().for_each(|input| *input = [0, 0]);
+        });
+        for side in 0..2 {
+            with(&|case| case.inputs.iter_mut().for_each(|input| input[side] = 0));
+            with(&|case| case.paddles[side] = 0.0);
+            with(&|case| case.paddles[side] = (case.paddles[side] * 10.0).round() / 10.0);
+        }
+        with(&|case| case.game_speed = 1.0);
+        with(&|case| case.velocity = case.velocity.signum() * BALL_SPEED);
+        with(&|case| case.ball = Vec2::ZERO);
+        with(&|case| case.ball = (case.ball * 10.0).round() / 10.0);
+        with(&|case| case.ball.y = 0.0);
+        cases
+    }
+
+    /// Seconds of play the ball can be inside a paddle, twice what it takes to go through one
+    /// lengthwise at the slower speed of the ball
+    fn stuck_seconds(&self) -> f32 {
+        2.0 * (PADDLE_LENGTH + BALL_SIZE) / (self.velocity.abs().min_element() * self.game_speed)
+    }
+}
+
+/// What a case broke, with the tick it happened on
+struct Failure {
+    tick: usize,
+    message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at tick {}: {}", self.tick, self.message)
+    }
+}
+
+fn world(case: &Case) -> World {
+    let mut app = App::new();
+    app.add_event::<PongEvent>()
+        .init_resource::<Score>()
+        .init_resource::<SimTime>()
+        .init_resource::<NetInputs>()
+        .init_resource::<NextState>()
+        .init_resource::<MatchRules>()
+        .insert_resource(AccessibilitySettings { game_speed: case.game_speed, ..Default::default() })
+        .insert_resource(State::new(AppState::InGame))
+        .add_startup_system(setup);
+    app.update();
+    let mut world = app.world;
+    for (mut transform, mut moving) in world.query_filtered::<(&mut Transform, &mut Moving), With<Ball>>().iter_mut(&mut world) {
+        transform.translation = case.ball.extend(0.0);
+        moving.velocity = case.velocity.extend(0.0);
+    }
+    for (Paddle(player), mut transform) in world.query::<(&Paddle, &mut Transform)>().iter_mut(&mut world) {
+        transform.translation.y = case.paddles[*player as usize];
+    }
+    world
+}
+
+/// Plays the case until a goal or the end of its inputs
+fn check(case: &Case) -> Result<(), Failure> {
+    let mut world = world(case);
+    let mut stage = simulation_stage();
+    let stuck_ticks = (case.stuck_seconds() / tick_duration().as_secs_f32()) as usize;
+    let mut inside_paddle = 0;
+    for (tick, inputs) in case.inputs.iter().enumerate() {
+        let before = world.resource::<Score>().clone();
+        play_tick(&mut stage, &mut world, *inputs);
+        let fail = |message: String| Err(Failure { tick, message });
+
+        let score = world.resource::<Score>().clone();
+        let ball = world.query_filtered::<&Transform, With<Ball>>().single(&world).translation;
+        let goal = world.resource::<NextState>().0 == Some(AppState::Goal);
+        let goals = (score.left + score.right) as i64 - (before.left + before.right) as i64;
+        if goal != (goals == 1) || !(0..=1).contains(&goals) {
+            return fail(format!("{} goals counted from {:?} to {:?}, goal {}", goals, before, score, goal));
+        }
+        if goal {
+            // Scored by the player on the other side
+            let scorer = if ball.x > 0.0 { score.left > before.left } else { score.right > before.right };
+            return if scorer { Ok(()) } else { fail(format!("the wrong player scored with the ball at {}", ball)) };
+        }
+        if ball.x.abs() > (AREA_WIDTH - WALL_THICKNESS) / 2.0 || ball.y.abs() > AREA_HEIGHT / 2.0 {
+            return fail(format!("the ball is off the field at {} without a goal", ball));
+        }
+
+        let ball_size = Vec2::splat(BALL_SIZE);
+        let in_paddle = world.query::<(&Paddle, &Colliding, &Transform)>().iter(&world)
+            .any(|(_, colliding, transform)| {
+                let distance = (ball - transform.translation).truncate().abs();
+                distance.cmplt((ball_size + colliding.size) / 2.0).all()
+            });
+        inside_paddle = if in_paddle { inside_paddle + 1 } else { 0 };
+        if inside_paddle > stuck_ticks {
+            return fail(format!("the ball has been inside a paddle for {} ticks, at {}", inside_paddle, ball));
+        }
+    }
+    Ok(())
+}
+
+/// The simplest case that still fails the way `case` does, with its failure
+fn shrink(mut case: Case, mut failure: Failure) -> (Case, Failure) {
+    case.inputs.truncate(failure.tick + 1);
+    'simpler: loop {
+        for simpler in case.shrink() {
+            if let Err(simpler_failure) = check(&simpler) {
+                case = simpler;
+                failure = simpler_failure;
+                case.inputs.truncate(failure.tick + 1);
+                continue 'simpler;
+            }
+        }
+        return (case, failure);
+    }
+}
+
+/// Cases that failed before, shrunk
+fn regressions() -> Vec<Case> {
+    ron::from_str(include_str!("regressions/collision.ron")).expect("invalid tests/regressions/collision.ron")
+}
+
+fn env_var(name: &str, default: u64) -> u64 {
+    env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+#[test]
+fn the_ball_stays_on_the_field_and_goals_count_once() {
+    for (index, case) in regressions().iter().enumerate() {
+        if let Err(failure) = check(case) {
+            panic!("regression {} failed {}\n{:#?}", index, failure, case);
+        }
+    }
+    let first = env_var("PONG_PROPERTY_SEED", SEED);
+    for seed in (0..env_var("PONG_PROPERTY_CASES", CASES)).map(|n| first.wrapping_add(n)) {
+        let case = Case::generate(&mut ChaCha8Rng::seed_from_u64(seed));
+        if let Err(failure) = check(&case) {
+            let (case, failure) = shrink(case, failure);
+            let shrunk = ron::ser::to_string_pretty(&case, PrettyConfig::new().compact_arrays(true)).unwrap();
+            panic!("seed {} failed {}, run it alone with PONG_PROPERTY_SEED={} PONG_PROPERTY_CASES=1\n\
+                    shrunk to the case below, add it to tests/regressions/collision.ron to keep it:\n{}",
+                   seed, failure, seed, shrunk);
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Runs the game's plugins without a window: fixed frame times, scripted key presses and
+//! nothing rendered. The entities come from `setup` alone, the text the game would show is not there.
+// Each test file uses a part of it
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant}
+};
+use bevy::{
+    prelude::*,
+    asset::AssetPlugin,
+    ecs::system::Resource,
+    input::{keyboard::KeyboardInput, ButtonState, InputPlugin},
+    tasks::IoTaskPool,
+    window::{WindowCloseRequested, WindowId}
+};
+use raw_window_handle::{RawWindowHandle, XlibHandle};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use bevy_pong::{
+    accessibility::AccessibilitySettings,
+    camera::{CameraPlugin, CameraSettings},
+    game::GamePlugin,
+    goal_replay::GoalReplayPlugin,
+    locale::{Locale, FALLBACK_LANGUAGE},
+    particles::ParticlesPlugin,
+    pause::PausePlugin,
+    replay::{ReplayDir, ReplayPlugin},
+    save::{SaveFile, SavePlugin},
+    scaling::ScalingPlugin,
+    setup::setup,
+    stats::StatsPlugin,
+    theme::{ResolvedTheme, ThemePlugin},
+    title::TitlePlugin,
+    trail::TrailPlugin,
+    types::*,
+    visuals::{ViewMode, VisualsPlugin},
+    PongPlugin
+};
+
+pub const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+const SEED: u64 = 5;
+
+/// Gives each harness a directory of its own, tests run side by side
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Harness {
+    pub app: App,
+    now: Instant,
+    /// For files the game writes, removed when the harness is dropped
+    dir: PathBuf,
+}
+
+impl Harness {
+    /// A local match of the left CPU against an idle keyboard player on the right, to two goals,
+    /// with the goal replay off. Resources can be replaced with `with` before the first step.
+    pub fn new(start: AppState) -> Harness {
+        let dir = Harness::new_dir();
+        // The screens load their fonts, which nothing here can read
+        IoTaskPool::init(Default::default);
+        let mut app = App::new();
+        app.insert_resource(Time::default())
+            .add_plugin(AssetPlugin)
+            .add_plugin(InputPlugin)
+            .add_event::<PongEvent>()
+            .add_event::<WindowCloseRequested>()
+            .init_resource::<Score>()
+            .init_resource::<ResolvedTheme>()
+            .insert_resource(MatchRules { goals_to_win: 2, ..Default::default() })
+            .insert_resource(Controllers { left: Controller::Cpu, right: Controller::Keyboard })
+            .insert_resource(PlayerNames::default())
+            .insert_resource(Locale::new(Some(FALLBACK_LANGUAGE)))
+            .insert_resource(AccessibilitySettings::default())
+            .insert_resource(CameraSettings { goal_replay: false, ..Default::default() })
+            .insert_resource(GameRng(ChaCha8Rng::seed_from_u64(SEED)))
+            .insert_resource(MatchSeed::new(SEED))
+            .add_startup_system(setup)
+            .add_plugin(GamePlugin)
+            .add_plugin(TitlePlugin)
+            .add_plugin(PausePlugin)
+            .add_plugin(SavePlugin)
+            .add_plugin(StatsPlugin { path: Some(dir.join("stats.ron")) })
+            .add_plugin(GoalReplayPlugin)
+            .add_plugin(ReplayPlugin { windowed: false })
+            // Nowhere outside the harness's directory
+            .insert_resource(SaveFile::new(Some(dir.join("save.ron"))))
+            .insert_resource(ReplayDir::new(Some(dir.join("replays"))))
+            .add_state(start);
+        Harness::start(app, dir)
+    }
+
+    /// The game as an app of one's own would have it, headless
+    pub fn embedded(pong: PongPlugin) -> Harness {
+        let dir = Harness::new_dir();
+        let mut app = App::new();
+        app.insert_resource(Time::default())
+            .add_plugin(InputPlugin)
+            .add_plugin(pong.headless().stats_file(dir.join("stats.ron")))
+            .insert_resource(ReplayDir::new(Some(dir.join("replays"))));
+        Harness::start(app, dir)
+    }
+
+    /// Also the cameras, meshes, effects and text the game draws, in a window that is not there, of
+    /// `width` by `height` physical pixels. Nothing is rendered.
+    pub fn visual(mut self, width: u32, height: u32) -> Harness {
+        IoTaskPool::init(Default::default);
+        let mut windows = Windows::default();
+        let handle = RawWindowHandle::Xlib(XlibHandle::empty());
+        windows.add(Window::new(WindowId::primary(), &WindowDescriptor::default(), width, height, 1.0, None, handle));
+        if !self.app.world.contains_resource::<AssetServer>() {
+            self.app.add_plugin(AssetPlugin);
+        }
+        self.app.insert_resource(windows)
+            .init_resource::<ClearColor>()
+            .init_resource::<AmbientLight>()
+            .add_asset::<Mesh>()
+            .add_asset::<StandardMaterial>()
+            .add_plugin(ThemePlugin { theme: "default".to_string() })
+            .add_plugin(VisualsPlugin { view: ViewMode::ThreeD })
+            .add_plugin(CameraPlugin { settings: CameraSettings { goal_replay: false, ..Default::default() } })
+            .add_plugin(ScalingPlugin { ui_scale: 1.0 })
+            .add_plugin(ParticlesPlugin)
+            .add_plugin(TrailPlugin);
+        self
+    }
+
+    fn new_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("bevy-pong-test-{}-{}", std::process::id(), NEXT_DIR.fetch_add(1, Ordering::Relaxed)))
+    }
+
+    fn start(mut app: App, dir: PathBuf) -> Harness {
+        let now = Instant::now();
+        app.world.resource_mut::<Time>().update_with_instant(now);
+        Harness { app, now, dir }
+    }
+
+    pub fn with<R: Resource>(mut self, resource: R) -> Harness {
+        self.app.insert_resource(resource);
+        self
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    /// Runs a frame of `FRAME`, a tick of the simulation
+    pub fn step(&mut self) {
+        self.now += FRAME;
+        self.app.world.resource_mut::<Time>().update_with_instant(self.now);
+        self.app.update();
+    }
+
+    pub fn steps(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.step();
+        }
+    }
+
+    fn key(&mut self, key_code: KeyCode, state: ButtonState) {
+        self.app.world.resource_mut::<Events<KeyboardInput>>().send(KeyboardInput { scan_code: 0, key_code: Some(key_code), state });
+    }
+
+    /// Pressed from the next frame on
+    pub fn hold(&mut self, key_code: KeyCode) {
+        self.key(key_code, ButtonState::Pressed);
+    }
+
+    pub fn release(&mut self, key_code: KeyCode) {
+        self.key(key_code, ButtonState::Released);
+    }
+
+    /// Presses the key for a frame and lets go of it the next
+    pub fn tap(&mut self, key_code: KeyCode) {
+        self.hold(key_code);
+        self.step();
+        self.release(key_code);
+        self.step();
+    }
+
+    pub fn state(&self) -> AppState {
+        self.app.world.resource::<State<AppState>>().current().clone()
+    }
+
+    pub fn score(&self) -> Score {
+        self.app.world.resource::<Score>().clone()
+    }
+
+    pub fn ball(&mut self) -> Vec3 {
+        self.app.world.query_filtered::<&Transform, With<Ball>>().single(&self.app.world).translation
+    }
+
+    pub fn paddle(&mut self, player: Player) -> Vec3 {
+        self.app.world.query::<(&Paddle, &Transform)>()
+            .iter(&self.app.world)
+            .find(|(Paddle(paddle_player), _)| *paddle_player == player)
+            .map(|(_, transform)| transform.translation)
+            .unwrap()
+    }
+
+    /// Steps until in `state`, for at most `seconds`. Returns the states gone through on the way,
+    /// the current one first.
+    pub fn run_until(&mut self, state: AppState, seconds: u32) -> Vec<AppState> {
+        let mut states = vec![self.state()];
+        for _ in 0..seconds * 60 {
+            if self.state() == state {
+                return states;
+            }
+            self.step();
+            if states.last() != Some(&self.state()) {
+                states.push(self.state());
+            }
+        }
+        panic!("not in {:?} after {} seconds, went through {:?}", state, seconds, states);
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,255 @@
+mod common;
+
+use bevy::{prelude::*, app::AppExit, window::{WindowCloseRequested, WindowId}};
+use bevy_pong::{camera::CameraSettings, save::SaveFile, stats::{MatchTracker, Stats}, types::*};
+use common::Harness;
+
+#[test]
+fn a_match_from_the_title_to_the_win() {
+    let mut game = Harness::new(AppState::Title);
+    game.step();
+    assert_eq!(game.state(), AppState::Title);
+    game.tap(KeyCode::Space);
+    let states = game.run_until(AppState::Title, 60);
+    assert_eq!(states, [
+        AppState::NewGame, AppState::Ready, AppState::InGame, AppState::Goal,
+        AppState::Ready, AppState::InGame, AppState::Goal, AppState::Win, AppState::Title,
+    ]);
+    // The CPU against nobody
+    let score = game.score();
+    assert_eq!((score.left, score.right, score.left_sets, score.right_sets), (2, 0, 1, 0));
+    // And kept as a replay
+    assert_eq!(std::fs::read_dir(game.dir().join("replays")).unwrap().count(), 1);
+}
+
+#[test]
+fn goals_are_counted_and_the_ball_served_again() {
+    let mut game = Harness::new(AppState::NewGame);
+    game.run_until(AppState::InGame, 10);
+    assert_eq!(game.score(), Score::default());
+    game.run_until(AppState::Goal, 30);
+    assert_eq!((game.score().left, game.score().right), (1, 0));
+    assert!(game.ball().x.abs() > 9.0, "the ball is past a paddle");
+    assert!(game.score().time > 0.0);
+
+    game.run_until(AppState::Ready, 10);
+    assert_eq!((game.score().left, game.score().right), (1, 0));
+    assert_eq!(game.ball(), Vec3::ZERO);
+}
+
+#[test]
+fn winning_a_set_starts_the_next_one() {
+    let mut game = Harness::new(AppState::NewGame)
+        .with(MatchRules { goals_to_win: 1, sets_to_win: 2, ..Default::default() });
+    game.run_until(AppState::Goal, 30);
+    game.run_until(AppState::Ready, 10);
+    let score = game.score();
+    assert_eq!((score.left, score.right, score.left_sets, score.right_sets), (0, 0, 1, 0));
+
+    let states = game.run_until(AppState::Win, 60);
+    assert_eq!(states.iter().filter(|state| **state == AppState::Goal).count(), 1);
+    assert_eq!(game.score().left_sets, 2);
+}
+
+#[test]
+fn keys_move_the_paddles() {
+    let mut game = Harness::new(AppState::NewGame)
+        .with(Controllers { left: Controller::Keyboard, right: Controller::Keyboard });
+    game.run_until(AppState::InGame, 10);
+    let left = game.paddle(Player::Left);
+    let right = game.paddle(Player::Right);
+
+    game.hold(KeyCode::A);
+    game.hold(KeyCode::M);
+    game.steps(20);
+    assert!(game.paddle(Player::Left).y > left.y);
+    assert!(game.paddle(Player::Right).y < right.y);
+    assert_eq!(game.paddle(Player::Left).x, left.x);
+
+    game.release(KeyCode::A);
+    game.release(KeyCode::M);
+    game.step();
+    let stopped = game.paddle(Player::Left);
+    game.steps(10);
+    assert_eq!(game.paddle(Player::Left), stopped);
+}
+
+#[test]
+fn the_match_stands_still_while_paused() {
+    let mut game = Harness::new(AppState::NewGame);
+    game.run_until(AppState::InGame, 10);
+    game.steps(30);
+    game.tap(KeyCode::Escape);
+    assert_eq!(game.state(), AppState::Paused);
+    let ball = game.ball();
+    let time = game.score().time;
+    game.steps(120);
+    assert_eq!(game.ball(), ball);
+    assert_eq!(game.score().time, time);
+
+    game.tap(KeyCode::Space);
+    assert_eq!(game.state(), AppState::InGame);
+    game.steps(5);
+    assert_ne!(game.ball(), ball);
+}
+
+#[test]
+fn quitting_from_the_pause_menu_goes_back_to_the_title() {
+    let mut game = Harness::new(AppState::NewGame);
+    game.run_until(AppState::InGame, 10);
+    game.tap(KeyCode::Escape);
+    assert_eq!(game.state(), AppState::Paused);
+    game.tap(KeyCode::Escape);
+    assert_eq!(game.state(), AppState::Title);
+    game.steps(10);
+    assert!(game.app.world.resource::<Events<AppExit>>().is_empty());
+    assert!(!game.app.world.resource::<SaveFile>().exists());
+}
+
+#[test]
+fn a_saved_match_continues_where_it_was_left() {
+    let mut game = Harness::new(AppState::NewGame);
+    game.run_until(AppState::Goal, 30);
+    game.run_until(AppState::InGame, 10);
+    game.steps(30);
+    game.tap(KeyCode::Escape);
+    let ball = game.ball();
+    game.tap(KeyCode::S);
+    assert_eq!(game.state(), AppState::Title);
+    assert!(game.app.world.resource::<SaveFile>().exists());
+
+    // Another match in between would not change what was saved
+    game.app.world.insert_resource(Score::default());
+    game.tap(KeyCode::C);
+    game.run_until(AppState::InGame, 1);
+    assert_eq!((game.score().left, game.score().right), (1, 0));
+    assert_eq!(game.ball(), ball);
+}
+
+#[test]
+fn a_continued_match_is_recorded_whole() {
+    let mut game = Harness::new(AppState::NewGame);
+    game.run_until(AppState::Goal, 30);
+    let first_goal = game.score().time;
+    game.run_until(AppState::InGame, 10);
+    game.tap(KeyCode::Escape);
+    game.tap(KeyCode::S);
+
+    game.app.world.insert_resource(MatchTracker::default());
+    game.tap(KeyCode::C);
+    game.run_until(AppState::Win, 30);
+    let stats = game.app.world.resource::<Stats>();
+    let record = stats.matches.last().unwrap();
+    let times: Vec<f32> = record.goals.iter().map(|goal| goal.time).collect();
+    assert_eq!(times.len(), 2);
+    assert_eq!(times[0], first_goal);
+    // Seconds of play, not counting the time spent on the pause menu or the title
+    assert_eq!(record.duration, game.score().time);
+}
+
+#[test]
+fn a_match_won_on_time_is_recorded_to_its_end() {
+    let mut game = Harness::new(AppState::NewGame);
+    game.run_until(AppState::Goal, 30);
+    let first_goal = game.score().time;
+
+    // Ends a second into play after the first goal
+    let rules = MatchRules { goals_to_win: 10, time_limit: Some(first_goal + 1.0), ..Default::default() };
+    let mut game = Harness::new(AppState::NewGame).with(rules);
+    let states = game.run_until(AppState::Win, 30);
+    assert_eq!(states, [AppState::NewGame, AppState::Ready, AppState::InGame, AppState::Goal, AppState::Ready, AppState::InGame, AppState::Win]);
+    let stats = game.app.world.resource::<Stats>();
+    let record = stats.matches.last().unwrap();
+    assert_eq!(record.goals.len(), 1);
+    assert_eq!(record.duration, game.score().time);
+    assert!(record.duration >= first_goal + 1.0);
+}
+
+#[test]
+fn a_goal_is_shown_again_until_skipped() {
+    let mut game = Harness::new(AppState::NewGame)
+        .with(CameraSettings::default());
+    let states = game.run_until(AppState::GoalReplay, 30);
+    assert_eq!(states.last(), Some(&AppState::GoalReplay));
+    let goal = game.score();
+
+    // The match waits while the ball goes over its last seconds
+    let mut positions = Vec::new();
+    for _ in 0..10 {
+        game.step();
+        positions.push(game.ball());
+    }
+    assert_eq!(game.state(), AppState::GoalReplay);
+    assert_eq!(game.score(), goal);
+    assert!(positions.windows(2).all(|pair| pair[0] != pair[1]));
+    assert!(positions.iter().all(|position| position.x.abs() < 9.0));
+
+    game.tap(KeyCode::Space);
+    assert_eq!(game.state(), AppState::Goal);
+    assert!(game.ball().x.abs() > 9.0, "back where the goal was scored");
+    game.run_until(AppState::Ready, 10);
+    assert_eq!(game.score().left + game.score().right, 1);
+}
+
+#[test]
+fn a_match_closed_during_a_goal_replay_continues_from_the_goal() {
+    let names = PlayerNames { left: "Ann".to_string(), right: "Bo".to_string() };
+    let mut game = Harness::new(AppState::NewGame)
+        .with(CameraSettings::default())
+        .with(names.clone())
+        .with(Controllers { left: Controller::Cpu, right: Controller::Local });
+    game.run_until(AppState::GoalReplay, 30);
+    game.steps(10);
+    let rng = game.app.world.resource::<GameRng>().get_word_pos();
+    let seed = game.app.world.resource::<MatchSeed>().current;
+    game.app.world.send_event(WindowCloseRequested { id: WindowId::primary() });
+    game.step();
+
+    // The game started again
+    let mut again = Harness::new(AppState::Title)
+        .with(SaveFile::new(Some(game.dir().join("save.ron"))));
+    again.step();
+    again.tap(KeyCode::C);
+    again.run_until(AppState::Goal, 1);
+    assert!(again.ball().x.abs() > 9.0, "where the goal was scored, not where the replay was");
+    assert_eq!((again.score().left, again.score().right), (1, 0));
+    assert_eq!(*again.app.world.resource::<PlayerNames>(), names);
+    assert_eq!(again.app.world.resource::<Controllers>().right, Controller::Local);
+    assert_eq!(again.app.world.resource::<MatchSeed>().current, seed);
+    assert_eq!(again.app.world.resource::<GameRng>().get_word_pos(), rng);
+    // The goal is shown for the rest of its time, then play goes on
+    let states = again.run_until(AppState::InGame, 10);
+    assert_eq!(states, [AppState::Goal, AppState::Ready, AppState::InGame]);
+}
+
+#[test]
+fn a_damaged_save_is_not_continued() {
+    let mut game = Harness::new(AppState::NewGame);
+    game.run_until(AppState::InGame, 10);
+    game.app.world.send_event(WindowCloseRequested { id: WindowId::primary() });
+    game.step();
+    let path = game.dir().join("save.ron");
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(saved.contains("phase: InGame,"));
+
+    let elapsed = |value: &str| saved.lines()
+        .map(|line| if line.trim_start().starts_with("phase_elapsed:") { format!("    phase_elapsed: {},", value) } else { line.to_string() })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let damaged = [
+        saved.replace("phase: InGame,", "phase: Continue,"),
+        saved.replace("phase: InGame,", "phase: Lobby,"),
+        elapsed("-1.0"),
+        elapsed("NaN"),
+    ];
+    for damaged in damaged {
+        std::fs::write(&path, &damaged).unwrap();
+        let mut again = Harness::new(AppState::Title)
+            .with(SaveFile::new(Some(path.clone())));
+        again.step();
+        again.tap(KeyCode::C);
+        again.step();
+        assert_eq!(again.state(), AppState::Title);
+        assert_eq!(again.score(), Score::default());
+    }
+}
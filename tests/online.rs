@@ -0,0 +1,91 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_pong::{
+    net::{MatchSetup, NetSession, Netcode},
+    types::PlayerNames,
+    AppState, Controller, Controllers, MatchRules, PongPlugin
+};
+use common::Harness;
+
+const SEED: u64 = 11;
+const TIME_LIMIT: f32 = 60.0;
+
+fn rules() -> MatchRules {
+    // Ends the match should the paddles keep the ball in play
+    MatchRules { goals_to_win: 2, sets_to_win: 1, time_limit: Some(TIME_LIMIT) }
+}
+
+/// Each side plays its own paddle with the keyboard, the other one comes over the network
+fn peer(session: NetSession) -> Harness {
+    let pong = PongPlugin::new()
+        .rules(rules())
+        .controllers(Controllers { left: Controller::Keyboard, right: Controller::Keyboard })
+        .seed(SEED)
+        .start_state(AppState::Lobby);
+    Harness::embedded(pong).with(session)
+}
+
+/// Up for `frames` frames, then down for as long, over and over
+fn script(peer: &mut Harness, frame: u32, frames: u32, (up, down): (KeyCode, KeyCode)) {
+    let phase = frame % (2 * frames);
+    if phase == 0 {
+        peer.release(down);
+        peer.hold(up);
+    } else if phase == frames {
+        peer.release(up);
+        peer.hold(down);
+    }
+}
+
+/// A host and a client on loopback in the same process, played frame by frame in turn
+fn play_online(netcode: Netcode) {
+    let setup = MatchSetup {
+        seed: SEED,
+        rules: rules(),
+        names: PlayerNames::default(),
+        game_speed: 1.0,
+        netcode,
+        input_delay: netcode.default_input_delay(),
+    };
+    let host_session = NetSession::host(0, setup).unwrap();
+    let address = format!("127.0.0.1:{}", host_session.local_addr().unwrap().port());
+    let mut host = peer(host_session);
+    let mut client = peer(NetSession::connect(&address, "Client".to_string()).unwrap());
+
+    let mut states = (vec![host.state()], vec![client.state()]);
+    for frame in 0.. {
+        if host.state() == AppState::Title && client.state() == AppState::Title {
+            break;
+        }
+        assert!(frame < 2 * 60 * 60, "{:?}: not over after two minutes, went through {:?}", netcode, states);
+        script(&mut host, frame, 40, (KeyCode::A, KeyCode::Z));
+        script(&mut client, frame, 25, (KeyCode::K, KeyCode::M));
+        host.step();
+        client.step();
+        for (peer, states) in [(&host, &mut states.0), (&client, &mut states.1)] {
+            if states.last() != Some(&peer.state()) {
+                states.push(peer.state());
+            }
+        }
+    }
+
+    // Both played the whole match, neither was dropped on the way
+    for states in [&states.0, &states.1] {
+        assert_eq!(states[..4], [AppState::Lobby, AppState::NewGame, AppState::Ready, AppState::InGame], "{:?}", netcode);
+        assert_eq!(states[states.len() - 2..], [AppState::Win, AppState::Title], "{:?}", netcode);
+    }
+    let score = host.score();
+    assert_eq!(client.score(), score, "{:?}", netcode);
+    assert!(score.left == 2 || score.right == 2 || score.time >= TIME_LIMIT, "{:?}: {:?}", netcode, score);
+}
+
+#[test]
+fn host_and_client_end_with_the_same_score_in_lockstep() {
+    play_online(Netcode::Lockstep);
+}
+
+#[test]
+fn host_and_client_end_with_the_same_score_with_rollback() {
+    play_online(Netcode::Rollback);
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_pong::{
+    stats::Stats,
+    theme::Themed,
+    visuals::{PongCamera2d, PongCamera3d},
+    AppState, Controller, Controllers, MatchRules, PongPlugin, PongEvent, RootState, Score
+};
+use common::Harness;
+
+/// States of an app with the game as one of its parts
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+enum Launcher {
+    Menu,
+    Pong,
+}
+
+fn pong() -> PongPlugin {
+    PongPlugin::new()
+        .rules(MatchRules { goals_to_win: 1, ..Default::default() })
+        .controllers(Controllers { left: Controller::Keyboard, right: Controller::Cpu })
+        .seed(5)
+}
+
+#[test]
+fn the_builder_sets_up_the_game() {
+    let mut game = Harness::embedded(pong());
+    game.step();
+    assert_eq!(game.state(), AppState::Title);
+    assert_eq!(game.app.world.resource::<RootState>().0, AppState::Title);
+    assert_eq!(game.app.world.resource::<MatchRules>().goals_to_win, 1);
+    assert_eq!(game.app.world.resource::<Controllers>().right, Controller::Cpu);
+
+    let mut game = Harness::embedded(pong().start_state(AppState::NewGame));
+    let states = game.run_until(AppState::Title, 30);
+    assert_eq!(states, [AppState::NewGame, AppState::Ready, AppState::InGame, AppState::Goal, AppState::Win, AppState::Title]);
+}
+
+#[test]
+fn an_app_starts_matches_and_gets_the_game_back() {
+    let mut game = Harness::embedded(pong().root_state(AppState::Idle));
+    game.app.add_state(Launcher::Menu);
+    game.steps(60);
+    assert_eq!(game.state(), AppState::Idle);
+    assert_eq!(game.ball(), Vec3::ZERO);
+
+    game.app.world.resource_mut::<State<Launcher>>().set(Launcher::Pong).unwrap();
+    game.app.world.resource_mut::<State<AppState>>().set(AppState::NewGame).unwrap();
+    game.step();
+    let states = game.run_until(AppState::Idle, 30);
+    assert_eq!(states, [AppState::NewGame, AppState::Ready, AppState::InGame, AppState::Goal, AppState::Win, AppState::Idle]);
+    assert_eq!(game.score(), Score { right: 1, ..game.score() });
+    assert_eq!(*game.app.world.resource::<State<Launcher>>().current(), Launcher::Pong);
+    assert_eq!(game.app.world.resource::<Stats>().matches.len(), 1);
+
+    // The match is over and kept as a replay, nothing goes on until the next
+    assert_eq!(std::fs::read_dir(game.dir().join("replays")).unwrap().count(), 1);
+    let ball = game.ball();
+    game.steps(60);
+    assert_eq!(game.state(), AppState::Idle);
+    assert_eq!(game.ball(), ball);
+}
+
+/// What the game could change on the cameras and the text of the app
+fn host_view(game: &Harness, cameras: [Entity; 2], text: Entity) -> (Vec<(bool, Option<UVec2>, Transform)>, f32) {
+    let world = &game.app.world;
+    let cameras = cameras.iter()
+        .map(|&camera| {
+            let entity = world.entity(camera);
+            let viewport = entity.get::<Camera>().unwrap().viewport.as_ref().map(|viewport| viewport.physical_size);
+            (entity.get::<Camera>().unwrap().is_active, viewport, *entity.get::<Transform>().unwrap())
+        })
+        .collect();
+    (cameras, world.entity(text).get::<Text>().unwrap().sections[0].style.font_size)
+}
+
+/// Entities the game spawned that would be drawn, by cameras of its own or of the app
+fn pong_drawn(game: &mut Harness, host: &[Entity]) -> Vec<Entity> {
+    let entities: Vec<Entity> = game.app.world.query_filtered::<Entity, (With<Visibility>, Without<Camera>)>()
+        .iter(&game.app.world)
+        .collect();
+    let world = &game.app.world;
+    let drawn = |entity: Entity| {
+        let mut ancestor = Some(entity);
+        while let Some(entity) = ancestor {
+            let entity = world.entity(entity);
+            let hidden = entity.get::<Visibility>().is_some_and(|visibility| !visibility.is_visible)
+                || entity.get::<Style>().is_some_and(|style| style.display == Display::None);
+            if hidden {
+                return false;
+            }
+            ancestor = entity.get::<Parent>().map(|parent| parent.get());
+        }
+        true
+    };
+    entities.into_iter().filter(|entity| !host.contains(entity) && drawn(*entity)).collect()
+}
+
+fn pong_shown(game: &mut Harness) -> (bool, bool) {
+    let world = &mut game.app.world;
+    let camera = world.query_filtered::<&Camera, With<PongCamera3d>>().single(world).is_active;
+    let floor = world.query::<(&Themed, &Visibility)>()
+        .iter(world)
+        .find(|(themed, _)| matches!(themed, Themed::Floor))
+        .unwrap().1.is_visible;
+    (camera, floor)
+}
+
+#[test]
+fn the_apps_cameras_and_text_are_left_alone() {
+    // Wider than the game's framing and smaller, for letterboxing and scaled text
+    let mut game = Harness::embedded(pong().root_state(AppState::Idle)).visual(800, 360);
+    let world = &mut game.app.world;
+    let camera_3d = world.spawn().insert_bundle(Camera3dBundle::default()).id();
+    let mut camera_2d = Camera2dBundle::default();
+    camera_2d.camera.priority = 10;
+    let camera_2d = world.spawn().insert_bundle(camera_2d).id();
+    let text = world.spawn().insert_bundle(TextBundle::from_section("Menu", TextStyle { font_size: 40.0, ..Default::default() })).id();
+    game.steps(10);
+    let host = host_view(&game, [camera_3d, camera_2d], text);
+    assert!(host.0.iter().all(|(active, viewport, _)| *active && viewport.is_none()));
+    assert_eq!(host.1, 40.0);
+    assert_eq!(pong_shown(&mut game), (false, false));
+    assert_eq!(pong_drawn(&mut game, &[text]), []);
+
+    game.app.world.resource_mut::<State<AppState>>().set(AppState::NewGame).unwrap();
+    game.step();
+    let mut goals = 0;
+    for _ in 0..30 * 60 {
+        game.step();
+        goals += game.app.world.resource::<Events<PongEvent>>().iter_current_update_events()
+            .filter(|event| matches!(event, PongEvent::Goal(_)))
+            .count();
+        if game.state() == AppState::Idle {
+            break;
+        }
+        assert_eq!(pong_shown(&mut game), (true, true));
+        assert_eq!(host_view(&game, [camera_3d, camera_2d], text), host);
+    }
+    assert_eq!(game.state(), AppState::Idle);
+    assert_eq!(goals, 1);
+    assert_eq!(pong_drawn(&mut game, &[text]), []);
+    // The game's own cameras were fitted to the window
+    let world = &mut game.app.world;
+    assert!(world.query_filtered::<&Camera, With<PongCamera2d>>().single(world).viewport.is_some());
+    game.steps(60);
+    assert_eq!(host_view(&game, [camera_3d, camera_2d], text), host);
+    assert_eq!(pong_shown(&mut game), (false, false));
+    assert_eq!(pong_drawn(&mut game, &[text]), []);
+}